@@ -0,0 +1,139 @@
+use crate::bencode::bencoded_value::BencodedValue;
+use std::ops::Range;
+
+/// Borrowed counterpart of [`BencodedValue`]. Byte strings are slices
+/// of the parsed input, so nothing is copied, and every value keeps
+/// the span of bytes it occupies in the input.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BencodedRef<'a> {
+    /// Bencoded byte string
+    ByteString(&'a [u8], Range<usize>),
+    /// Bencoded dictionary, the keys and the values are also bencoded
+    Dictionary(Vec<(BencodedRef<'a>, BencodedRef<'a>)>, Range<usize>),
+    /// Bencoded integers
+    Integer(i64, Range<usize>),
+    /// Bencoded list
+    List(Vec<BencodedRef<'a>>, Range<usize>),
+}
+
+impl<'a> BencodedRef<'a> {
+    /// Returns the span of bytes that the value occupies in the
+    /// parsed input, including its delimiters. Slicing the input with
+    /// it gives back the exact bytes of the value.
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            BencodedRef::ByteString(_, span)
+            | BencodedRef::Dictionary(_, span)
+            | BencodedRef::Integer(_, span)
+            | BencodedRef::List(_, span) => span.clone(),
+        }
+    }
+    /// Returns [`Some`] if self coincides with the
+    /// [`BencodedRef::ByteString`] variant, else returns [`None`]
+    pub fn byte_string(self) -> Option<&'a [u8]> {
+        if let BencodedRef::ByteString(s, _) = self {
+            Some(s)
+        } else {
+            None
+        }
+    }
+    /// Returns [`Some`] if self coincides with the
+    /// [`BencodedRef::Dictionary`] variant, else returns [`None`]
+    pub fn dictionary(self) -> Option<Vec<(BencodedRef<'a>, BencodedRef<'a>)>> {
+        if let BencodedRef::Dictionary(d, _) = self {
+            Some(d)
+        } else {
+            None
+        }
+    }
+    /// Returns [`Some`] if self coincides with the
+    /// [`BencodedRef::Integer`] variant, else returns [`None`]
+    pub fn integer(self) -> Option<i64> {
+        if let BencodedRef::Integer(i, _) = self {
+            Some(i)
+        } else {
+            None
+        }
+    }
+    /// Returns [`Some`] if self coincides with the
+    /// [`BencodedRef::List`] variant, else returns [`None`]
+    pub fn list(self) -> Option<Vec<BencodedRef<'a>>> {
+        if let BencodedRef::List(l, _) = self {
+            Some(l)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value associated with `key` if self is a
+    /// dictionary that contains it. If the key is repeated the first
+    /// value is returned.
+    pub fn get(&self, key: &[u8]) -> Option<&BencodedRef<'a>> {
+        if let BencodedRef::Dictionary(d, _) = self {
+            d.iter()
+                .find(|(k, _)| matches!(k, BencodedRef::ByteString(s, _) if *s == key))
+                .map(|(_, v)| v)
+        } else {
+            None
+        }
+    }
+
+    /// Creates an owned [`BencodedValue`] copying the borrowed byte
+    /// strings. The spans are discarded.
+    pub fn to_value(&self) -> BencodedValue {
+        match self {
+            BencodedRef::ByteString(s, _) => BencodedValue::ByteString(s.to_vec()),
+            BencodedRef::Dictionary(d, _) => BencodedValue::Dictionary(
+                d.iter()
+                    .map(|(k, v)| (k.to_value(), v.to_value()))
+                    .collect(),
+            ),
+            BencodedRef::Integer(i, _) => BencodedValue::Integer(*i),
+            BencodedRef::List(l, _) => {
+                BencodedValue::List(l.iter().map(BencodedRef::to_value).collect())
+            }
+        }
+    }
+}
+
+impl<'a> From<BencodedRef<'a>> for BencodedValue {
+    fn from(value: BencodedRef<'a>) -> Self {
+        value.to_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_value_from_dictionary() {
+        let dict = BencodedRef::Dictionary(
+            vec![(
+                BencodedRef::ByteString(b"one", 1..6),
+                BencodedRef::Integer(1, 6..9),
+            )],
+            0..10,
+        );
+
+        assert_eq!(dict.get(b"one"), Some(&BencodedRef::Integer(1, 6..9)));
+        assert_eq!(dict.get(b"two"), None);
+    }
+
+    #[test]
+    fn convert_to_owned_value() {
+        let list = BencodedRef::List(
+            vec![
+                BencodedRef::ByteString(b"test", 1..7),
+                BencodedRef::Integer(5, 7..10),
+            ],
+            0..11,
+        );
+        let want = BencodedValue::List(vec![
+            BencodedValue::ByteString(b"test".to_vec()),
+            BencodedValue::Integer(5),
+        ]);
+
+        assert_eq!(list.to_value(), want);
+    }
+}
@@ -1,3 +1,6 @@
+use crate::bencode::path_segment::{describe, PathSegment};
+use crate::bencode::query_error::QueryError;
+
 /// Represent all the possible types that can be represented in
 /// bencode.
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -50,19 +53,145 @@ impl BencodedValue {
         }
     }
 
-    pub fn encode(&mut self) -> Vec<u8> {
+    /// Returns the bytes if self is a [`BencodedValue::ByteString`],
+    /// without consuming it
+    pub fn as_byte_string(&self) -> Option<&[u8]> {
+        if let BencodedValue::ByteString(s) = self {
+            Some(s)
+        } else {
+            None
+        }
+    }
+    /// Returns the string if self is a [`BencodedValue::ByteString`]
+    /// with valid UTF-8, without consuming it
+    pub fn as_str(&self) -> Option<&str> {
+        self.as_byte_string()
+            .and_then(|s| std::str::from_utf8(s).ok())
+    }
+    /// Returns the entries if self is a
+    /// [`BencodedValue::Dictionary`], without consuming it
+    pub fn as_dictionary(&self) -> Option<&[(BencodedValue, BencodedValue)]> {
+        if let BencodedValue::Dictionary(d) = self {
+            Some(d)
+        } else {
+            None
+        }
+    }
+    /// Returns the integer if self is a [`BencodedValue::Integer`]
+    pub fn as_integer(&self) -> Option<i64> {
+        if let BencodedValue::Integer(i) = self {
+            Some(*i)
+        } else {
+            None
+        }
+    }
+    /// Returns the elements if self is a [`BencodedValue::List`],
+    /// without consuming it
+    pub fn as_list(&self) -> Option<&[BencodedValue]> {
+        if let BencodedValue::List(l) = self {
+            Some(l)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value associated with `key` if self is a
+    /// dictionary that contains it. If the key is repeated the first
+    /// value is returned.
+    pub fn get(&self, key: &[u8]) -> Option<&BencodedValue> {
+        self.as_dictionary()?
+            .iter()
+            .find(|(k, _)| k.as_byte_string() == Some(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Follows `path` through nested dictionaries and lists and
+    /// returns the value at its end. Build the path with
+    /// [`bencode_path`](crate::bencode_path).
+    ///
+    /// # Errors
+    ///
+    /// This function will return [`QueryError::NotFound`] if a key is
+    /// missing or an index is out of bounds, and
+    /// [`QueryError::WrongType`] if a key is looked up in something
+    /// that is not a dictionary or an index in something that is not
+    /// a list.
+    pub fn get_path(&self, path: &[PathSegment]) -> Result<&BencodedValue, QueryError> {
+        path.iter()
+            .enumerate()
+            .try_fold(self, |value, (i, segment)| {
+                let found = match segment {
+                    PathSegment::Key(k) => value
+                        .as_dictionary()
+                        .ok_or_else(|| QueryError::WrongType(describe(&path[..i]), "a dictionary"))?
+                        .iter()
+                        .find(|(key, _)| key.as_byte_string() == Some(k))
+                        .map(|(_, v)| v),
+                    PathSegment::Index(n) => value
+                        .as_list()
+                        .ok_or_else(|| QueryError::WrongType(describe(&path[..i]), "a list"))?
+                        .get(*n),
+                };
+                found.ok_or_else(|| QueryError::NotFound(describe(&path[..=i])))
+            })
+    }
+
+    /// Returns the integer at `path`. See [`BencodedValue::get_path`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no value at the
+    /// path or if it is not an integer.
+    pub fn get_int(&self, path: &[PathSegment]) -> Result<i64, QueryError> {
+        self.get_path(path)?
+            .as_integer()
+            .ok_or_else(|| QueryError::WrongType(describe(path), "an integer"))
+    }
+
+    /// Returns the UTF-8 string at `path`. See
+    /// [`BencodedValue::get_path`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no value at the
+    /// path or if it is not a valid UTF-8 byte string.
+    pub fn get_str(&self, path: &[PathSegment]) -> Result<&str, QueryError> {
+        self.get_path(path)?
+            .as_str()
+            .ok_or_else(|| QueryError::WrongType(describe(path), "a UTF-8 string"))
+    }
+
+    /// Returns the bytes of the byte string at `path`. See
+    /// [`BencodedValue::get_path`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is no value at the
+    /// path or if it is not a byte string.
+    pub fn get_bytes(&self, path: &[PathSegment]) -> Result<&[u8], QueryError> {
+        self.get_path(path)?
+            .as_byte_string()
+            .ok_or_else(|| QueryError::WrongType(describe(path), "a byte string"))
+    }
+
+    /// Encodes the value as bencode. The output is always canonical:
+    /// the entries of every dictionary are written with their keys
+    /// sorted as raw byte strings, whatever the order of the
+    /// [`Vec`].
+    pub fn encode(&self) -> Vec<u8> {
         match self {
             BencodedValue::ByteString(s) => {
                 let mut begin = s.len().to_string().into_bytes();
-                begin.append(&mut vec![b':']);
-                // begin.insert(1, b':');
-                begin.append(s);
+                begin.push(b':');
+                begin.extend_from_slice(s);
                 begin
             }
             BencodedValue::Dictionary(d) => {
-                let mut dict: Vec<u8> = d
-                    .iter_mut()
-                    .flat_map(|(k, v)| k.encode().into_iter().chain(v.encode().into_iter()))
+                let mut entries: Vec<_> = d.iter().collect();
+                entries.sort_by_cached_key(|(k, _)| k.key_bytes());
+                let mut dict: Vec<u8> = entries
+                    .into_iter()
+                    .flat_map(|(k, v)| k.encode().into_iter().chain(v.encode()))
                     .collect();
                 dict.insert(0, b'd');
                 dict.push(b'e');
@@ -75,19 +204,51 @@ impl BencodedValue {
                 ascii_integer
             }
             BencodedValue::List(l) => {
-                let mut list: Vec<u8> =
-		    l.iter_mut().flat_map(|v| v.encode().into_iter()).collect();
+                let mut list: Vec<u8> = l.iter().flat_map(|v| v.encode()).collect();
                 list.insert(0, b'l');
                 list.push(b'e');
                 list
             }
         }
     }
+
+    /// Returns true if encoding the value gives back the same
+    /// structure, that is, if the keys of every dictionary are byte
+    /// strings in strictly increasing order. Use it on parsed inputs
+    /// to check that they were canonical.
+    pub fn is_canonical(&self) -> bool {
+        match self {
+            BencodedValue::ByteString(_) | BencodedValue::Integer(_) => true,
+            BencodedValue::Dictionary(d) => {
+                let keys_sorted = d.windows(2).all(|w| match (&w[0].0, &w[1].0) {
+                    (BencodedValue::ByteString(a), BencodedValue::ByteString(b)) => a < b,
+                    _ => false,
+                });
+                let keys_are_strings = d
+                    .iter()
+                    .all(|(k, _)| matches!(k, BencodedValue::ByteString(_)));
+                keys_sorted && keys_are_strings && d.iter().all(|(_, v)| v.is_canonical())
+            }
+            BencodedValue::List(l) => l.iter().all(BencodedValue::is_canonical),
+        }
+    }
+
+    /// Bytes used to sort the value when it is a dictionary key. Keys
+    /// should be byte strings, any other value is sorted by its
+    /// encoding.
+    fn key_bytes(&self) -> Vec<u8> {
+        match self {
+            BencodedValue::ByteString(s) => s.clone(),
+            v => v.encode(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bencode::parser;
+    use crate::bencode_path;
 
     #[test]
     fn enconde_integer() {
@@ -127,7 +288,7 @@ mod tests {
     }
     #[test]
     fn enconde_dictionary() {
-        let want = b"d3:onei1e6:string3:str4:listli1ei2ei3ee4:dictd3:onei1e3:twoi2eee";
+        let want = b"d4:dictd3:onei1e3:twoi2ee4:listli1ei2ei3ee3:onei1e6:string3:stre";
         let got = BencodedValue::Dictionary(vec![
             (
                 BencodedValue::ByteString("one".into()),
@@ -163,4 +324,117 @@ mod tests {
 
         assert_eq!(got, want)
     }
+
+    #[test]
+    fn encode_sorts_keys_as_raw_bytes() {
+        let value = BencodedValue::Dictionary(vec![
+            (
+                BencodedValue::ByteString("b".into()),
+                BencodedValue::Integer(1),
+            ),
+            (
+                BencodedValue::ByteString("a".into()),
+                BencodedValue::Integer(2),
+            ),
+            (
+                BencodedValue::ByteString("B".into()),
+                BencodedValue::Integer(3),
+            ),
+        ]);
+
+        assert_eq!(value.encode(), b"d1:Bi3e1:ai2e1:bi1ee");
+        // Encoding does not consume the byte strings
+        assert_eq!(value.encode(), b"d1:Bi3e1:ai2e1:bi1ee");
+    }
+
+    #[test]
+    fn is_canonical_checks_nested_dictionaries() {
+        let sorted = BencodedValue::Dictionary(vec![
+            (
+                BencodedValue::ByteString("a".into()),
+                BencodedValue::Integer(1),
+            ),
+            (
+                BencodedValue::ByteString("b".into()),
+                BencodedValue::Integer(2),
+            ),
+        ]);
+        let unsorted = BencodedValue::List(vec![BencodedValue::Dictionary(vec![
+            (
+                BencodedValue::ByteString("b".into()),
+                BencodedValue::Integer(2),
+            ),
+            (
+                BencodedValue::ByteString("a".into()),
+                BencodedValue::Integer(1),
+            ),
+        ])]);
+        let duplicated = BencodedValue::Dictionary(vec![
+            (
+                BencodedValue::ByteString("a".into()),
+                BencodedValue::Integer(1),
+            ),
+            (
+                BencodedValue::ByteString("a".into()),
+                BencodedValue::Integer(2),
+            ),
+        ]);
+
+        assert!(sorted.is_canonical());
+        assert!(!unsorted.is_canonical());
+        assert!(!duplicated.is_canonical());
+    }
+
+    fn torrent() -> BencodedValue {
+        parser::parse(
+            b"d8:announce3:url4:infod5:filesld6:lengthi3e4:pathl1:a5:b.txteee4:name3:dir\
+              12:piece lengthi16eee"
+                .to_vec(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn get_path_through_dictionaries_and_lists() {
+        let torrent = torrent();
+
+        assert_eq!(
+            torrent.get_str(bencode_path!["info", "files", 0, "path", 1]),
+            Ok("b.txt")
+        );
+        assert_eq!(
+            torrent.get_int(bencode_path!["info", "piece length"]),
+            Ok(16)
+        );
+        assert_eq!(
+            torrent.get_bytes(bencode_path!["announce"]),
+            Ok(&b"url"[..])
+        );
+        assert_eq!(
+            torrent.get(b"info").and_then(|i| i.get(b"name")),
+            Some(&BencodedValue::ByteString(b"dir".to_vec()))
+        );
+    }
+
+    #[test]
+    fn get_path_reports_the_failing_path() {
+        let torrent = torrent();
+
+        assert_eq!(
+            torrent.get_path(bencode_path!["info", "files", 1]),
+            Err(QueryError::NotFound("info.files[1]".to_string()))
+        );
+        assert_eq!(
+            torrent.get_path(bencode_path!["info", "name", 0]),
+            Err(QueryError::WrongType("info.name".to_string(), "a list"))
+        );
+        assert_eq!(
+            torrent.get_int(bencode_path!["announce"]),
+            Err(QueryError::WrongType("announce".to_string(), "an integer"))
+        );
+        assert_eq!(
+            torrent.get_path(bencode_path![0]),
+            Err(QueryError::WrongType("the root".to_string(), "a list"))
+        );
+    }
 }
@@ -0,0 +1,447 @@
+use crate::bencode::bencoded_value::BencodedValue;
+use crate::bencode::parser;
+use crate::bencode::parser_options::ParserOptions;
+use crate::bencode::serde_error::SerdeError;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Unexpected, Visitor};
+use std::vec;
+
+/// Deserializes an instance of `T` from a [`BencodedValue`].
+///
+/// Missing dictionary keys are read as [`None`] for optional fields,
+/// the integers 0 and 1 are read as booleans and byte strings can be
+/// read either as strings or as sequences of bytes.
+///
+/// # Errors
+///
+/// This function will return an error if the value does not match
+/// the structure of `T`.
+pub fn from_value<T: DeserializeOwned>(value: BencodedValue) -> Result<T, SerdeError> {
+    T::deserialize(Deserializer { value })
+}
+
+/// Parses `bytes` and deserializes an instance of `T` from them. See
+/// [`from_value`].
+///
+/// # Errors
+///
+/// This function will return an error if the bytes are not valid
+/// bencode or if they do not match the structure of `T`.
+pub fn from_bytes<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, SerdeError> {
+    from_value(parser::parse_with_options(bytes, ParserOptions::default())?)
+}
+
+/// Deserializer that consumes a [`BencodedValue`].
+pub struct Deserializer {
+    value: BencodedValue,
+}
+
+impl Deserializer {
+    pub fn new(value: BencodedValue) -> Self {
+        Self { value }
+    }
+
+    fn unexpected(&self) -> Unexpected<'_> {
+        match &self.value {
+            BencodedValue::ByteString(s) => Unexpected::Bytes(s),
+            BencodedValue::Dictionary(_) => Unexpected::Map,
+            BencodedValue::Integer(i) => Unexpected::Signed(*i),
+            BencodedValue::List(_) => Unexpected::Seq,
+        }
+    }
+
+    fn invalid_type(&self, exp: &dyn de::Expected) -> SerdeError {
+        de::Error::invalid_type(self.unexpected(), exp)
+    }
+}
+
+impl IntoDeserializer<'_, SerdeError> for BencodedValue {
+    type Deserializer = Deserializer;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        Deserializer::new(self)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            BencodedValue::ByteString(s) => match String::from_utf8(s) {
+                Ok(s) => visitor.visit_string(s),
+                Err(e) => visitor.visit_byte_buf(e.into_bytes()),
+            },
+            BencodedValue::Dictionary(d) => visitor.visit_map(MapAccess::new(d)),
+            BencodedValue::Integer(i) => visitor.visit_i64(i),
+            BencodedValue::List(l) => visitor.visit_seq(SeqAccess::new(l)),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            BencodedValue::Integer(0) => visitor.visit_bool(false),
+            BencodedValue::Integer(1) => visitor.visit_bool(true),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, SerdeError> {
+        Err(SerdeError::UnsupportedType("f32"))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, SerdeError> {
+        Err(SerdeError::UnsupportedType("f64"))
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            BencodedValue::ByteString(s) => visitor.visit_byte_buf(s),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        // Bencode has no null, absent values are missing keys
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            BencodedValue::List(l) => visitor.visit_seq(SeqAccess::new(l)),
+            BencodedValue::ByteString(s) => {
+                visitor.visit_seq(de::value::SeqDeserializer::new(s.into_iter()))
+            }
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            BencodedValue::Dictionary(d) => visitor.visit_map(MapAccess::new(d)),
+            _ => Err(self.invalid_type(&visitor)),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.value {
+            BencodedValue::ByteString(_) => visitor.visit_enum(EnumAccess {
+                variant: self.value,
+                value: None,
+            }),
+            BencodedValue::Dictionary(mut d) if d.len() == 1 => {
+                let (variant, value) = d.remove(0);
+                visitor.visit_enum(EnumAccess {
+                    variant,
+                    value: Some(value),
+                })
+            }
+            _ => Err(self.invalid_type(&"a byte string or a dictionary with one key")),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 char str string identifier
+    }
+}
+
+struct SeqAccess {
+    items: vec::IntoIter<BencodedValue>,
+}
+
+impl SeqAccess {
+    fn new(items: Vec<BencodedValue>) -> Self {
+        Self {
+            items: items.into_iter(),
+        }
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SerdeError> {
+        self.items
+            .next()
+            .map(|v| seed.deserialize(Deserializer::new(v)))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct MapAccess {
+    entries: vec::IntoIter<(BencodedValue, BencodedValue)>,
+    value: Option<BencodedValue>,
+}
+
+impl MapAccess {
+    fn new(entries: Vec<(BencodedValue, BencodedValue)>) -> Self {
+        Self {
+            entries: entries.into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SerdeError> {
+        match self.entries.next() {
+            Some((k, v)) => {
+                self.value = Some(v);
+                seed.deserialize(Deserializer::new(k)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SerdeError> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| SerdeError::Message("value requested before its key".to_string()))?;
+        seed.deserialize(Deserializer::new(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumAccess {
+    variant: BencodedValue,
+    value: Option<BencodedValue>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = SerdeError;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), SerdeError> {
+        let variant = seed.deserialize(Deserializer::new(self.variant))?;
+        Ok((variant, VariantAccess { value: self.value }))
+    }
+}
+
+struct VariantAccess {
+    value: Option<BencodedValue>,
+}
+
+impl VariantAccess {
+    fn value(self) -> Result<Deserializer, SerdeError> {
+        self.value
+            .map(Deserializer::new)
+            .ok_or_else(|| de::Error::invalid_type(Unexpected::UnitVariant, &"a variant with data"))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        match self.value {
+            None => Ok(()),
+            Some(_) => Err(de::Error::invalid_type(
+                Unexpected::NewtypeVariant,
+                &"a unit variant",
+            )),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, SerdeError> {
+        seed.deserialize(self.value()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_seq(self.value()?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        de::Deserializer::deserialize_map(self.value()?, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bencode::ser::to_bytes;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct File {
+        path: Vec<String>,
+        length: u64,
+        md5sum: Option<String>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Info {
+        name: String,
+        #[serde(rename = "piece length")]
+        piece_length: u32,
+        #[serde(with = "crate::bencode::bytes")]
+        pieces: Vec<u8>,
+        private: Option<bool>,
+        files: Vec<File>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Event {
+        Started,
+        Completed(u32),
+        Stopped { reason: String },
+    }
+
+    #[test]
+    fn deserialize_struct_ignoring_unknown_keys() {
+        let s = b"d5:filesld6:lengthi3e4:pathl1:aeee4:name4:test12:piece lengthi16e6:pieces3:\x00\xff\x017:privatei1e6:source3:abce";
+        let want = Info {
+            name: "test".to_string(),
+            piece_length: 16,
+            pieces: vec![0, 255, 1],
+            private: Some(true),
+            files: vec![File {
+                path: vec!["a".to_string()],
+                length: 3,
+                md5sum: None,
+            }],
+        };
+
+        assert_eq!(from_bytes::<Info>(s).unwrap(), want);
+    }
+
+    #[test]
+    fn round_trip_struct() {
+        let info = Info {
+            name: "test".to_string(),
+            piece_length: 16,
+            pieces: vec![1, 2, 3],
+            private: None,
+            files: vec![],
+        };
+
+        let bytes = to_bytes(&info).unwrap();
+
+        assert_eq!(from_bytes::<Info>(&bytes).unwrap(), info);
+    }
+
+    #[test]
+    fn round_trip_enum() {
+        for event in [
+            Event::Started,
+            Event::Completed(2),
+            Event::Stopped {
+                reason: "done".to_string(),
+            },
+        ] {
+            let bytes = to_bytes(&event).unwrap();
+            assert_eq!(from_bytes::<Event>(&bytes).unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn deserialize_byte_string_as_byte_vector() {
+        assert_eq!(from_bytes::<Vec<u8>>(b"3:abc").unwrap(), b"abc".to_vec());
+    }
+
+    #[test]
+    fn deserialize_with_missing_field_returns_error() {
+        assert!(matches!(
+            from_bytes::<File>(b"d6:lengthi3ee"),
+            Err(SerdeError::Message(_))
+        ));
+    }
+
+    #[test]
+    fn deserialize_invalid_bencode_returns_error() {
+        assert!(matches!(
+            from_bytes::<File>(b"d6:length"),
+            Err(SerdeError::Parse(_))
+        ));
+    }
+}
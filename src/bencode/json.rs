@@ -0,0 +1,219 @@
+//! Conversion between bencode and JSON.
+//!
+//! Byte strings that are valid UTF-8 become JSON strings. The rest
+//! become an object with a single `hex` or `base64` key, like
+//! `{"hex": "00ff"}`, and dictionary keys that are not UTF-8 are
+//! written as `"hex:00ff"` or `"base64:AP8="`. Converting back
+//! decodes both forms, so the keys that could be mistaken for them,
+//! like `hex` or `hex:00ff` itself, are always written encoded.
+
+use crate::bencode::bencoded_value::BencodedValue;
+use crate::bencode::json_error::JsonError;
+use crate::utils;
+use serde_json::{Map, Value};
+
+/// How to write the byte strings that are not valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+impl BinaryEncoding {
+    fn name(&self) -> &'static str {
+        match self {
+            BinaryEncoding::Hex => "hex",
+            BinaryEncoding::Base64 => "base64",
+        }
+    }
+
+    fn encode(&self, bytes: &[u8]) -> String {
+        match self {
+            BinaryEncoding::Hex => utils::to_hex(bytes),
+            BinaryEncoding::Base64 => utils::to_base64(bytes),
+        }
+    }
+}
+
+/// Converts the bencoded value to JSON.
+pub fn to_json(value: &BencodedValue, binary: BinaryEncoding) -> Value {
+    match value {
+        BencodedValue::ByteString(s) => match std::str::from_utf8(s) {
+            Ok(s) => Value::String(s.to_string()),
+            Err(_) => {
+                let mut object = Map::new();
+                object.insert(binary.name().to_string(), Value::String(binary.encode(s)));
+                Value::Object(object)
+            }
+        },
+        BencodedValue::Dictionary(d) => Value::Object(
+            d.iter()
+                .map(|(k, v)| (json_key(k, binary), to_json(v, binary)))
+                .collect(),
+        ),
+        BencodedValue::Integer(i) => Value::from(*i),
+        BencodedValue::List(l) => Value::Array(l.iter().map(|v| to_json(v, binary)).collect()),
+    }
+}
+
+fn json_key(key: &BencodedValue, binary: BinaryEncoding) -> String {
+    match key {
+        BencodedValue::ByteString(s) => match std::str::from_utf8(s) {
+            Ok(k) if !is_reserved_key(k) => k.to_string(),
+            _ => format!("{}:{}", binary.name(), binary.encode(s)),
+        },
+        k => to_json(k, binary).to_string(),
+    }
+}
+
+/// Converts a JSON value to bencode. Booleans become the integers 0
+/// and 1.
+///
+/// # Errors
+///
+/// This function will return an error if the value contains a null,
+/// a number that is not an integer or a binary string that can not
+/// be decoded.
+pub fn from_json(value: &Value) -> Result<BencodedValue, JsonError> {
+    match value {
+        Value::Null => Err(JsonError::UnsupportedValue(value.to_string())),
+        Value::Bool(b) => Ok(BencodedValue::Integer(*b as i64)),
+        Value::Number(n) => n
+            .as_i64()
+            .map(BencodedValue::Integer)
+            .ok_or_else(|| JsonError::UnsupportedValue(n.to_string())),
+        Value::String(s) => Ok(BencodedValue::ByteString(s.as_bytes().to_vec())),
+        Value::Array(a) => a
+            .iter()
+            .map(from_json)
+            .collect::<Result<_, _>>()
+            .map(BencodedValue::List),
+        Value::Object(o) => {
+            if let Some(bytes) = binary_string(o)? {
+                return Ok(BencodedValue::ByteString(bytes));
+            }
+            o.iter()
+                .map(|(k, v)| Ok((BencodedValue::ByteString(bencode_key(k)?), from_json(v)?)))
+                .collect::<Result<_, _>>()
+                .map(BencodedValue::Dictionary)
+        }
+    }
+}
+
+/// Decodes `{"hex": ..}` and `{"base64": ..}` objects. Returns
+/// [`None`] if the object is not one of them.
+fn binary_string(object: &Map<String, Value>) -> Result<Option<Vec<u8>>, JsonError> {
+    if object.len() != 1 {
+        return Ok(None);
+    }
+    let decoded = match object.iter().next() {
+        Some((k, Value::String(s))) if k == "hex" => utils::from_hex(s),
+        Some((k, Value::String(s))) if k == "base64" => utils::from_base64(s),
+        _ => return Ok(None),
+    };
+    decoded
+        .map(Some)
+        .ok_or_else(|| JsonError::InvalidBinary(Value::Object(object.clone()).to_string()))
+}
+
+/// Whether the key would be decoded as something else when converting
+/// back, as a binary string or as a binary key.
+fn is_reserved_key(key: &str) -> bool {
+    ["hex", "base64"]
+        .iter()
+        .any(|name| key == *name || key.starts_with(&format!("{}:", name)))
+}
+
+fn bencode_key(key: &str) -> Result<Vec<u8>, JsonError> {
+    let decoded = if let Some(hex) = key.strip_prefix("hex:") {
+        utils::from_hex(hex)
+    } else if let Some(base64) = key.strip_prefix("base64:") {
+        utils::from_base64(base64)
+    } else {
+        return Ok(key.as_bytes().to_vec());
+    };
+    decoded.ok_or_else(|| JsonError::InvalidBinary(key.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bencode::parser;
+    use serde_json::json;
+
+    #[test]
+    fn convert_tracker_response_to_json() {
+        let response =
+            parser::parse(b"d8:intervali900e5:peers6:\x7f\x00\x00\x01\x1a\xe12:ok4:truee".to_vec())
+                .unwrap();
+
+        assert_eq!(
+            to_json(&response, BinaryEncoding::Hex),
+            json!({"interval": 900, "peers": {"hex": "7f0000011ae1"}, "ok": "true"})
+        );
+        assert_eq!(
+            to_json(&response, BinaryEncoding::Base64),
+            json!({"interval": 900, "peers": {"base64": "fwAAARrh"}, "ok": "true"})
+        );
+    }
+
+    #[test]
+    fn convert_json_to_bencode() {
+        let value = json!({
+            "announce": "http://tracker",
+            "info": {"pieces": {"hex": "00ff"}, "private": true, "hex:ff": [1, 2]},
+        });
+
+        assert_eq!(
+            from_json(&value).unwrap().encode(),
+            b"d8:announce14:http://tracker4:infod6:pieces2:\x00\xff7:privatei1e1:\xffli1ei2eeee"
+        );
+    }
+
+    #[test]
+    fn round_trip_through_json() {
+        let bytes = b"d4:name4:test1:\xfeli-3e0:d1:a2:\x80\x81eee".to_vec();
+        let value = parser::parse(bytes.clone()).unwrap();
+
+        for binary in [BinaryEncoding::Hex, BinaryEncoding::Base64] {
+            let json = to_json(&value, binary);
+            assert_eq!(from_json(&json).unwrap().encode(), bytes);
+        }
+    }
+
+    #[test]
+    fn keys_that_look_binary_round_trip_through_json() {
+        let bytes = b"d6:base643:abc3:hexd3:hex2:ffe6:hex:ff1:ae".to_vec();
+        let value = parser::parse(bytes.clone()).unwrap();
+
+        for binary in [BinaryEncoding::Hex, BinaryEncoding::Base64] {
+            let json = to_json(&value, binary);
+            assert_eq!(from_json(&json).unwrap().encode(), bytes);
+        }
+        assert_eq!(
+            to_json(&value, BinaryEncoding::Hex),
+            json!({
+                "hex:686578": {"hex:686578": "ff"},
+                "hex:626173653634": "abc",
+                "hex:6865783a6666": "a",
+            })
+        );
+    }
+
+    #[test]
+    fn convert_unsupported_json_returns_error() {
+        assert!(matches!(
+            from_json(&json!([1.5])),
+            Err(JsonError::UnsupportedValue(_))
+        ));
+        assert!(matches!(
+            from_json(&json!({ "a": null })),
+            Err(JsonError::UnsupportedValue(_))
+        ));
+        assert!(matches!(
+            from_json(&json!({ "hex": "xyz" })),
+            Err(JsonError::InvalidBinary(_))
+        ));
+    }
+}
@@ -0,0 +1,26 @@
+use std::{error::Error, fmt};
+
+/// Represents the possible errors that can occur while converting a
+/// JSON value to bencode.
+#[derive(Debug, PartialEq, Eq)]
+pub enum JsonError {
+    /// The JSON value has no representation in bencode, like null or
+    /// a float
+    UnsupportedValue(String),
+    /// A `{"hex": ..}` or `{"base64": ..}` object, or a `hex:` or
+    /// `base64:` key, could not be decoded
+    InvalidBinary(String),
+}
+
+impl Error for JsonError {}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::UnsupportedValue(v) => {
+                write!(f, "The value {} can not be represented in bencode", v)
+            }
+            JsonError::InvalidBinary(v) => write!(f, "Invalid binary string {}", v),
+        }
+    }
+}
@@ -1,3 +1,16 @@
+pub mod bencoded_ref;
 pub mod bencoded_value;
+pub mod bytes;
+pub mod de;
+pub mod json;
+pub mod json_error;
 pub mod parser;
 pub mod parser_error;
+pub mod parser_options;
+pub mod path_segment;
+pub mod query_error;
+pub mod ser;
+pub mod serde_error;
+
+pub use de::{from_bytes, from_value};
+pub use ser::{to_bytes, to_value};
@@ -1,17 +1,30 @@
+use crate::bencode::bencoded_ref::BencodedRef;
 use crate::bencode::bencoded_value::BencodedValue;
 use crate::bencode::parser_error::ParserError;
+use crate::bencode::parser_options::ParserOptions;
+use std::ops::Range;
 use std::str;
 
 /// # Parser
-/// This type is tasked with parsing bencoded data
-pub struct Parser {
+/// This type is tasked with parsing bencoded data. The input is
+/// borrowed, the parsed byte strings are slices of it.
+pub struct Parser<'a> {
     /// Characters in the bencoded string
-    characters: Vec<u8>,
+    characters: &'a [u8],
     /// Cursor that point to the current character
     cursor: usize,
+    /// Limits enforced while parsing
+    options: ParserOptions,
+    /// Nesting level of the value being parsed
+    depth: usize,
+    /// Number of values parsed so far
+    elements: usize,
+    /// Byte span of each value of the top level dictionary, along
+    /// with its key
+    spans: Vec<(&'a [u8], Range<usize>)>,
 }
 
-impl Iterator for Parser {
+impl Iterator for Parser<'_> {
     type Item = u8;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -19,16 +32,16 @@ impl Iterator for Parser {
     }
 }
 
-impl Parser {
+impl<'a> Parser<'a> {
     /// Parses the string if it's a valid bencoded byte string and
-    /// returns a Result<BencodedValue, ParserError>. Advances the
+    /// returns a Result<BencodedRef, ParserError>. Advances the
     /// cursor.
     ///
     /// # Errors
     ///
     /// This function will return an error if there an error parsing
-    /// the length of the byte string. It will only parse the number
-    /// of characters specified by the length.
+    /// the length of the byte string, or if the input is shorter than
+    /// the specified length.
     ///
     /// # Examples
     ///
@@ -39,19 +52,34 @@ impl Parser {
     /// assert_eq!(bencoded_byte_string, BencodedValue::ByteString("example"".into()));
     /// ```
     ///
-    fn byte_string(&mut self) -> Result<BencodedValue, ParserError> {
-        let length = self
-            .take_while(|c| (*c as char).is_alphanumeric() && *c != b':')
-            .map(|c| c as char)
-            .collect::<String>()
-            .parse()
-            .map_err(|_| ParserError::InvalidByteStringLength)?;
-        let byte_string = self.take(length).collect::<Vec<u8>>();
+    fn byte_string(&mut self) -> Result<BencodedRef<'a>, ParserError> {
+        let start = self.cursor;
+        let digits = self.take_while(|c| c.is_ascii_alphanumeric());
+        let length: usize = str::from_utf8(digits)
+            .ok()
+            .and_then(|d| d.parse().ok())
+            .ok_or(ParserError::InvalidByteStringLength)?;
+        self.take_if(b':')
+            .ok_or(ParserError::InvalidByteStringLength)?;
+        if length > self.options.max_string_length {
+            return Err(ParserError::StringTooLong(start));
+        }
 
-        Ok(BencodedValue::ByteString(byte_string))
+        let end = self
+            .cursor
+            .checked_add(length)
+            .filter(|end| *end <= self.characters.len())
+            .ok_or(ParserError::InvalidEncoding(
+                self.characters.len(),
+                "byte string too short",
+            ))?;
+        let byte_string = &self.characters[self.cursor..end];
+        self.cursor = end;
+
+        Ok(BencodedRef::ByteString(byte_string, start..end))
     }
     /// Parses the string if it's a valid bencoded dictionary and
-    /// returns a Result<BencodedValue, ParserError>. Advances the
+    /// returns a Result<BencodedRef, ParserError>. Advances the
     /// cursor.
     ///
     /// # Errors
@@ -83,16 +111,25 @@ impl Parser {
     /// assert_eq!(bencoded_byte_string, BencodedValue::Dictionary(dict));
     /// ```
     ///
-    fn dictionary(&mut self) -> Result<BencodedValue, ParserError> {
+    fn dictionary(&mut self) -> Result<BencodedRef<'a>, ParserError> {
+        let start = self.cursor;
         let mut dict = Vec::new();
         let mut end_found = false;
         self.pop();
 
         while let Some(c) = self.peek() {
             match c {
-                b'1'..=b'9' => {
-                    let key = self.byte_string()?;
-                    let value = self.bencoded_value()?;
+                b'0'..=b'9' => {
+                    let key = self.bencoded_ref()?;
+                    if self.options.strict {
+                        self.check_key_order(dict.last().map(|(k, _)| k), &key)?;
+                    }
+                    let value = self.bencoded_ref()?;
+                    if self.depth == 1 {
+                        if let BencodedRef::ByteString(k, _) = key {
+                            self.spans.push((k, value.span()));
+                        }
+                    }
                     dict.push((key, value));
                 }
                 b'e' => {
@@ -110,20 +147,20 @@ impl Parser {
         }
 
         if end_found {
-            Ok(BencodedValue::Dictionary(dict))
+            Ok(BencodedRef::Dictionary(dict, start..self.cursor))
         } else {
             Err(ParserError::InvalidEncoding(self.cursor, "end not found"))
         }
     }
     /// Parses the string if it's a valid bencoded integer and returns
-    /// a Result<BencodedValue, ParserError>. Advances the cursor.
+    /// a Result<BencodedRef, ParserError>. Advances the cursor.
     ///
     /// # Errors
     ///
     /// This function will return an error if there an error parsing
     /// the integer value. Both i-0e and i05e (zero must be the only
-    /// digit if it is the first digit) will return an error. This
-    /// method won't return and error if the *e* delimiter is missing.
+    /// digit if it is the first digit) will return an error, and so
+    /// will a missing *e* delimiter.
     ///
     /// # Examples
     ///
@@ -134,22 +171,24 @@ impl Parser {
     /// assert_eq!(bencoded_byte_string, BencodedValue::Integer(8));
     /// ```
     ///    
-    fn integer(&mut self) -> Result<BencodedValue, ParserError> {
-        let bencoded_string = self
-            .skip(1)
-            .take_while(|c| *c != 101)
-            .map(|c| c as char)
-            .collect::<String>();
+    fn integer(&mut self) -> Result<BencodedRef<'a>, ParserError> {
+        let start = self.cursor;
+        self.pop();
+        let digits = self.take_while(|c| c != b'e');
+        self.take_if(b'e')
+            .ok_or(ParserError::InvalidEncoding(self.cursor, "end not found"))?;
+        let bencoded_string = String::from_utf8_lossy(digits).into_owned();
         if check_zero(&bencoded_string) {
             return Err(ParserError::InvalidInteger(bencoded_string));
         }
 
-        Ok(BencodedValue::Integer(bencoded_string.parse().map_err(
-            |_| ParserError::InvalidInteger(bencoded_string),
-        )?))
+        let integer = bencoded_string
+            .parse()
+            .map_err(|_| ParserError::InvalidInteger(bencoded_string))?;
+        Ok(BencodedRef::Integer(integer, start..self.cursor))
     }
     /// Parses the string if it's a valid bencoded list and returns a
-    /// Result<BencodedValue, ParserError>. Advances the cursor.
+    /// Result<BencodedRef, ParserError>. Advances the cursor.
     ///
     /// # Errors
     ///
@@ -170,31 +209,108 @@ impl Parser {
     /// assert_eq!(bencoded_list, list);
     /// ```
     ///    
-    fn list(&mut self) -> Result<BencodedValue, ParserError> {
+    fn list(&mut self) -> Result<BencodedRef<'a>, ParserError> {
+        let start = self.cursor;
         let mut vec = Vec::new();
         self.pop();
-        let end_found;
         loop {
-            if self.take_if(b'e') == Some(b'e') {
-                end_found = true;
+            if self.take_if(b'e').is_some() {
                 break;
             }
-            vec.push(self.bencoded_value()?);
+            vec.push(self.bencoded_ref()?);
         }
-        if end_found {
-            Ok(BencodedValue::List(vec))
-        } else {
-            Err(ParserError::InvalidEncoding(self.cursor, "end missing"))
+        Ok(BencodedRef::List(vec, start..self.cursor))
+    }
+    /// Strict mode: checks that `key` is greater than the previous
+    /// key of the dictionary.
+    fn check_key_order(
+        &self,
+        previous: Option<&BencodedRef<'a>>,
+        key: &BencodedRef<'a>,
+    ) -> Result<(), ParserError> {
+        if let (Some(BencodedRef::ByteString(p, _)), BencodedRef::ByteString(k, span)) =
+            (previous, key)
+        {
+            if p == k {
+                return Err(ParserError::DuplicateKey(span.start));
+            } else if p > k {
+                return Err(ParserError::UnsortedKeys(span.start));
+            }
         }
+        Ok(())
     }
     /// Creates a new instance of parser, that parses the string s
     /// (bencoded string).
-    pub fn new(s: Vec<u8>) -> Self {
+    pub fn new(s: &'a [u8]) -> Self {
+        Self::with_options(s, ParserOptions::default())
+    }
+    /// Creates a new instance of parser, that parses the string s
+    /// (bencoded string) enforcing the specified limits.
+    pub fn with_options(s: &'a [u8], options: ParserOptions) -> Self {
         Self {
             cursor: 0,
             characters: s,
+            options,
+            depth: 0,
+            elements: 0,
+            spans: Vec::new(),
         }
     }
+
+    /// Returns the byte span, in the parsed string, of the value
+    /// associated with `key` in the top level dictionary. Returns
+    /// [`None`] if the top level value isn't a dictionary, if it
+    /// hasn't been parsed yet or if it doesn't contain the key.
+    pub fn span(&self, key: &[u8]) -> Option<Range<usize>> {
+        self.spans
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, span)| span.clone())
+    }
+
+    /// Returns the original bytes in the specified span, exactly as
+    /// they were before being parsed.
+    pub fn raw(&self, span: Range<usize>) -> Option<&'a [u8]> {
+        self.characters.get(span)
+    }
+    /// Parses the string if it's a valid bencoded value and
+    /// returns a Result<BencodedRef, ParserError>. Nothing is copied
+    /// from the input.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there an error parsing
+    /// parsing the value, if the string passed was empty or if any
+    /// of the limits is exceeded.
+    pub fn bencoded_ref(&mut self) -> Result<BencodedRef<'a>, ParserError> {
+        self.elements += 1;
+        if self.elements > self.options.max_elements {
+            return Err(ParserError::TooManyElements(self.cursor));
+        }
+        self.peek()
+            .map(|c| match c {
+                b'i' => self.integer(),
+                b'0'..=b'9' => self.byte_string(),
+                b'l' | b'd' => self.nested(c),
+                _ => Err(ParserError::InvalidEncoding(self.cursor, "invalid bencode")),
+            })
+            .map_or_else(|| Err(ParserError::Empty), |r| r)
+    }
+    /// Parses a list or a dictionary, keeping track of the nesting
+    /// depth.
+    fn nested(&mut self, c: u8) -> Result<BencodedRef<'a>, ParserError> {
+        if self.depth >= self.options.max_depth {
+            return Err(ParserError::DepthLimitExceeded(self.cursor));
+        }
+        self.depth += 1;
+        let value = if c == b'l' {
+            self.list()
+        } else {
+            self.dictionary()
+        };
+        self.depth -= 1;
+        value
+    }
     /// Parses the string if it's a valid bencoded value and
     /// returns a Result<BencodedValue, ParserError>.
     ///
@@ -217,16 +333,7 @@ impl Parser {
     /// ```
     ///   
     pub fn bencoded_value(&mut self) -> Result<BencodedValue, ParserError> {
-        self.peek()
-            .map(|c| match c {
-                b'i' => self.integer(),
-                b'1'..=b'9' => self.byte_string(),
-                b'l' => self.list(),
-                b'd' => self.dictionary(),
-                _ => {
-                    Err(ParserError::InvalidEncoding(self.cursor, "invalid bencode"))},
-            })
-            .map_or_else(|| Err(ParserError::Empty), |r| r)
+        self.bencoded_ref().map(|r| r.to_value())
     }
 
     /// Peeks ahead one character without moving the cursor. Returns
@@ -255,6 +362,16 @@ impl Parser {
         }
         None
     }
+
+    /// Advances the cursor while the characters satisfy the
+    /// predicate, and returns the slice of the ones that did.
+    fn take_while<P: Fn(u8) -> bool>(&mut self, predicate: P) -> &'a [u8] {
+        let start = self.cursor;
+        while self.peek().is_some_and(&predicate) {
+            self.cursor += 1;
+        }
+        &self.characters[start..self.cursor]
+    }
 }
 /// Validates the preconditions of an bencoded integer associated with
 /// zero.
@@ -291,7 +408,39 @@ fn check_zero(s: &str) -> bool {
 /// ```
 ///   
 pub fn parse(s: Vec<u8>) -> Result<BencodedValue, ParserError> {
+    let mut parser = Parser::new(&s);
+    parser.bencoded_value()
+}
+/// Parses the string if it's a valid bencoded value and returns a
+/// Result<BencodedRef, ParserError> that borrows from it.
+///
+/// # Errors
+///
+/// This function will return an error if there an error parsing
+/// parsing the value, or if the string passed was empty.
+///
+/// # Examples
+///
+/// ```#s
+/// let s = b"d4:infod6:lengthi0eee";
+/// let info = parse_ref(s)?.get(b"info").unwrap().span();
+/// assert_eq!(&s[info], b"d6:lengthi0ee");
+/// ```
+///
+pub fn parse_ref(s: &[u8]) -> Result<BencodedRef<'_>, ParserError> {
     let mut parser = Parser::new(s);
+    parser.bencoded_ref()
+}
+/// Parses the string if it's a valid bencoded value enforcing the
+/// limits in `options`. Should be used for untrusted input.
+///
+/// # Errors
+///
+/// This function will return an error if there an error parsing
+/// parsing the value, if the string passed was empty or if any of the
+/// limits is exceeded.
+pub fn parse_with_options(s: &[u8], options: ParserOptions) -> Result<BencodedValue, ParserError> {
+    let mut parser = Parser::with_options(s, options);
     parser.bencoded_value()
 }
 
@@ -418,8 +567,8 @@ mod tests {
 
     #[test]
     fn parse_bencoded_dictionary_with_integer_values() {
-        let s = "d3:onei1e3:twoi2e5:threei3ee".into();
-        let mut parser = Parser::new(s);
+        let s: Vec<u8> = "d3:onei1e3:twoi2e5:threei3ee".into();
+        let mut parser = Parser::new(&s);
         let dict = [
             (
                 BencodedValue::ByteString("one".into()),
@@ -536,4 +685,145 @@ mod tests {
         ]);
         assert_eq!(parse(s).unwrap(), want)
     }
+
+    #[test]
+    fn parser_records_span_of_top_level_values() {
+        let s: Vec<u8> = "d1:ad1:bi1ee1:cli2eee".into();
+        let mut parser = Parser::new(&s);
+        parser.bencoded_value().unwrap();
+
+        let span = parser.span(b"a").unwrap();
+        assert_eq!(parser.raw(span), Some(&b"d1:bi1ee"[..]));
+        assert_eq!(parser.span(b"c"), Some(15..20));
+        assert_eq!(parser.span(b"b"), None);
+    }
+
+    #[test]
+    fn parser_iterates_over_the_remaining_characters() {
+        let s = b"i1e3:abc";
+        let mut parser = Parser::new(s);
+        parser.bencoded_value().unwrap();
+
+        assert_eq!(parser.collect::<Vec<u8>>(), b"3:abc");
+    }
+
+    #[test]
+    fn parse_ref_borrows_byte_strings_and_records_spans() {
+        let s = b"d1:ad1:bi1ee1:cl4:testee";
+        let got = parse_ref(s).unwrap();
+
+        let a = got.get(b"a").unwrap();
+        assert_eq!(&s[a.span()], b"d1:bi1ee");
+        assert_eq!(a.get(b"b").map(BencodedRef::span), Some(8..11));
+
+        let c = got.get(b"c").unwrap().clone().list().unwrap();
+        assert_eq!(c, vec![BencodedRef::ByteString(b"test", 16..22)]);
+        assert_eq!(
+            c[0].clone().byte_string().unwrap().as_ptr(),
+            s[18..].as_ptr()
+        );
+        assert_eq!(got.span(), 0..s.len());
+    }
+
+    #[test]
+    fn parse_empty_byte_string() {
+        let s = "d0:0:e".into();
+        let want = BencodedValue::Dictionary(vec![(
+            BencodedValue::ByteString(vec![]),
+            BencodedValue::ByteString(vec![]),
+        )]);
+        assert_eq!(parse(s).unwrap(), want)
+    }
+
+    #[test]
+    fn parse_byte_string_shorter_than_its_length() {
+        let s = "5:abc".into();
+        assert_eq!(
+            parse(s).unwrap_err(),
+            ParserError::InvalidEncoding(5, "byte string too short")
+        )
+    }
+
+    #[test]
+    fn deeply_nested_list_exceeds_depth_limit() {
+        let s = [vec![b'l'; 100_000], vec![b'e'; 100_000]].concat();
+        assert_eq!(parse(s).unwrap_err(), ParserError::DepthLimitExceeded(256));
+    }
+
+    #[test]
+    fn nesting_up_to_the_depth_limit_is_accepted() {
+        let options = ParserOptions {
+            max_depth: 2,
+            ..ParserOptions::default()
+        };
+        assert!(parse_with_options(b"llee", options).is_ok());
+        assert_eq!(
+            parse_with_options(b"llleee", options).unwrap_err(),
+            ParserError::DepthLimitExceeded(2)
+        );
+    }
+
+    #[test]
+    fn byte_string_longer_than_limit() {
+        let options = ParserOptions {
+            max_string_length: 3,
+            ..ParserOptions::default()
+        };
+        assert_eq!(
+            parse_with_options(b"l3:abc4:abcde", options).unwrap_err(),
+            ParserError::StringTooLong(6)
+        );
+    }
+
+    #[test]
+    fn too_many_elements() {
+        let options = ParserOptions {
+            max_elements: 3,
+            ..ParserOptions::default()
+        };
+        assert!(parse_with_options(b"li1ei2ee", options).is_ok());
+        assert_eq!(
+            parse_with_options(b"li1ei2ei3ee", options).unwrap_err(),
+            ParserError::TooManyElements(7)
+        );
+    }
+
+    #[test]
+    fn integer_without_end_is_rejected() {
+        let missing_end = ParserError::InvalidEncoding(3, "end not found");
+        assert_eq!(parse(b"i12".to_vec()).unwrap_err(), missing_end);
+        assert_eq!(
+            parse_with_options(b"i12", ParserOptions::untrusted()).unwrap_err(),
+            missing_end
+        );
+        assert_eq!(
+            parse_with_options(b"li12", ParserOptions::default().strict(true)).unwrap_err(),
+            ParserError::InvalidEncoding(4, "end not found")
+        );
+    }
+
+    #[test]
+    fn strict_mode_rejects_unsorted_keys() {
+        let options = ParserOptions::default().strict(true);
+        assert_eq!(
+            parse_with_options(b"d1:bi1e1:ai2ee", options).unwrap_err(),
+            ParserError::UnsortedKeys(7)
+        );
+        assert!(parse(b"d1:bi1e1:ai2ee".to_vec()).is_ok());
+    }
+
+    #[test]
+    fn strict_mode_rejects_duplicate_keys() {
+        let options = ParserOptions::default().strict(true);
+        assert_eq!(
+            parse_with_options(b"d1:ai1e1:ai2ee", options).unwrap_err(),
+            ParserError::DuplicateKey(7)
+        );
+    }
+
+    #[test]
+    fn strict_mode_compares_keys_as_raw_bytes() {
+        let options = ParserOptions::default().strict(true);
+        assert!(parse_with_options(b"d1:Ai1e1:ai2e2:aai3ee", options).is_ok());
+    }
 }
@@ -1,9 +1,24 @@
 /// Represents the possible errors that can occur while parsing some
-/// bencoded string.
+/// bencoded string. The offsets are relative to the beginning of the
+/// input.
 #[derive(Debug, PartialEq, Eq)]
 pub enum ParserError {
     Empty,
     InvalidEncoding(usize, &'static str), //TODO: Remove str
     InvalidInteger(String),
     InvalidByteStringLength,
+    /// A list or dictionary starting at the offset exceeds the
+    /// maximum nesting depth
+    DepthLimitExceeded(usize),
+    /// The byte string starting at the offset is longer than allowed
+    StringTooLong(usize),
+    /// The value starting at the offset exceeds the maximum number of
+    /// elements
+    TooManyElements(usize),
+    /// Strict mode: the key at the offset is smaller than the previous
+    /// key of the dictionary
+    UnsortedKeys(usize),
+    /// Strict mode: the key at the offset is equal to the previous key
+    /// of the dictionary
+    DuplicateKey(usize),
 }
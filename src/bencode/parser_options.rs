@@ -0,0 +1,47 @@
+/// Limits enforced while parsing bencoded data. The defaults only
+/// bound the nesting depth, so that deeply nested values can't
+/// overflow the stack; data coming from the network should be parsed
+/// with [`ParserOptions::untrusted`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserOptions {
+    /// Maximum nesting of lists and dictionaries
+    pub max_depth: usize,
+    /// Maximum length of a single byte string
+    pub max_string_length: usize,
+    /// Maximum number of values (including dictionary keys) in the
+    /// whole input
+    pub max_elements: usize,
+    /// If true, dictionaries whose keys aren't sorted as raw byte
+    /// strings or that have repeated keys are rejected
+    pub strict: bool,
+}
+
+impl ParserOptions {
+    /// Limits suitable for small messages received from untrusted
+    /// peers, such as tracker responses.
+    pub fn untrusted() -> Self {
+        Self {
+            max_depth: 32,
+            max_string_length: 1 << 22, // 4 MiB
+            max_elements: 1 << 16,
+            strict: false,
+        }
+    }
+
+    /// Returns a copy of the options with the strict mode set.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 256,
+            max_string_length: usize::MAX,
+            max_elements: usize::MAX,
+            strict: false,
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+
+/// One step of a path inside a [`BencodedValue`]: a dictionary key or
+/// a list index. Build paths with the [`bencode_path`] macro.
+///
+/// [`BencodedValue`]: crate::bencode::bencoded_value::BencodedValue
+/// [`bencode_path`]: crate::bencode_path
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PathSegment<'a> {
+    /// Key of a dictionary
+    Key(&'a [u8]),
+    /// Position in a list
+    Index(usize),
+}
+
+impl<'a> From<&'a str> for PathSegment<'a> {
+    fn from(key: &'a str) -> Self {
+        PathSegment::Key(key.as_bytes())
+    }
+}
+
+impl<'a> From<&'a [u8]> for PathSegment<'a> {
+    fn from(key: &'a [u8]) -> Self {
+        PathSegment::Key(key)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u8; N]> for PathSegment<'a> {
+    fn from(key: &'a [u8; N]) -> Self {
+        PathSegment::Key(key)
+    }
+}
+
+impl From<usize> for PathSegment<'_> {
+    fn from(index: usize) -> Self {
+        PathSegment::Index(index)
+    }
+}
+
+impl fmt::Display for PathSegment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Key(k) => write!(f, "{}", String::from_utf8_lossy(k)),
+            PathSegment::Index(i) => write!(f, "[{}]", i),
+        }
+    }
+}
+
+/// Formats a path as `info.files[0].path`. The empty path is the
+/// root value.
+pub fn describe(path: &[PathSegment]) -> String {
+    if path.is_empty() {
+        return "the root".to_string();
+    }
+    path.iter()
+        .enumerate()
+        .map(|(i, s)| match s {
+            PathSegment::Key(_) if i > 0 => format!(".{}", s),
+            _ => s.to_string(),
+        })
+        .collect()
+}
+
+/// Builds a path for [`BencodedValue::get_path`] from keys and
+/// indexes.
+///
+/// ```
+/// use bittorrent::bencode_path;
+///
+/// let path = bencode_path!["info", "files", 0, "path"];
+/// assert_eq!(path.len(), 4);
+/// ```
+///
+/// [`BencodedValue::get_path`]: crate::bencode::bencoded_value::BencodedValue::get_path
+#[macro_export]
+macro_rules! bencode_path {
+    ($( $segment:expr ),* $(,)?) => {
+        &[$( $crate::bencode::path_segment::PathSegment::from($segment) ),*][..]
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_path_with_keys_and_indexes() {
+        let path = crate::bencode_path!["info", "files", 0, "path", 1];
+
+        assert_eq!(describe(path), "info.files[0].path[1]");
+    }
+}
@@ -0,0 +1,26 @@
+use std::{error::Error, fmt};
+
+/// Represents the possible errors that can occur while looking up a
+/// path inside a bencoded value. The paths are formatted as
+/// `info.files[0].path`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum QueryError {
+    /// There is no value at the path: a key is missing or an index
+    /// is out of bounds
+    NotFound(String),
+    /// The value at the path is not of the expected type
+    WrongType(String, &'static str),
+}
+
+impl Error for QueryError {}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::NotFound(path) => write!(f, "There is no value at {}", path),
+            QueryError::WrongType(path, expected) => {
+                write!(f, "Expected {} at {}", expected, path)
+            }
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use crate::bencode::bencoded_value::BencodedValue;
+use crate::bencode::serde_error::SerdeError;
+use serde::ser::{self, Serialize};
+
+/// Serializes `value` as a [`BencodedValue`].
+///
+/// Structs and maps become dictionaries with their keys sorted, so
+/// the encoded result is canonical. Fields that are [`None`] are left
+/// out of the dictionary, booleans are encoded as the integers 0 and
+/// 1 and enums are externally tagged.
+///
+/// # Errors
+///
+/// This function will return an error if the value contains a type
+/// without a bencode representation, like a float, or a map whose
+/// keys are not strings.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<BencodedValue, SerdeError> {
+    value
+        .serialize(Serializer)?
+        .ok_or(SerdeError::UnsupportedType("none"))
+}
+
+/// Serializes `value` as bencode. See [`to_value`].
+///
+/// # Errors
+///
+/// This function will return an error if the value can not be
+/// represented in bencode.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, SerdeError> {
+    to_value(value).map(|v| v.encode())
+}
+
+/// Serializer that builds a [`BencodedValue`]. Values without a
+/// representation in bencode ([`None`] and units) are serialized as
+/// [`None`], so the containing dictionary can skip them.
+struct Serializer;
+
+/// Builds a dictionary sorting the entries by the raw bytes of the
+/// keys.
+fn dictionary(mut entries: Vec<(Vec<u8>, BencodedValue)>) -> BencodedValue {
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    BencodedValue::Dictionary(
+        entries
+            .into_iter()
+            .map(|(k, v)| (BencodedValue::ByteString(k), v))
+            .collect(),
+    )
+}
+
+fn integer<T: TryInto<i64>>(v: T) -> Result<Option<BencodedValue>, SerdeError> {
+    v.try_into()
+        .map(|i| Some(BencodedValue::Integer(i)))
+        .map_err(|_| SerdeError::Message("integer out of range".to_string()))
+}
+
+fn required(value: Option<BencodedValue>) -> Result<BencodedValue, SerdeError> {
+    value.ok_or(SerdeError::UnsupportedType("none"))
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<BencodedValue>;
+    type Error = SerdeError;
+
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        integer(v as i64)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        integer(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        integer(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        integer(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        integer(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        integer(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        integer(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        integer(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        integer(v)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
+        Err(SerdeError::UnsupportedType("f32"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok, Self::Error> {
+        Err(SerdeError::UnsupportedType("f64"))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(BencodedValue::ByteString(v.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        let value = required(value.serialize(Serializer)?)?;
+        Ok(Some(dictionary(vec![(variant.into(), value)])))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(MapSerializer {
+            entries: Vec::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+/// Builds a bencoded list. The elements can not be [`None`].
+struct SeqSerializer {
+    items: Vec<BencodedValue>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.items.push(required(value.serialize(Serializer)?)?);
+        Ok(())
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Option<BencodedValue>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        Ok(Some(BencodedValue::List(self.items)))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Option<BencodedValue>;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Option<BencodedValue>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// Builds a bencoded dictionary. The entries whose value is [`None`]
+/// are skipped.
+struct MapSerializer {
+    entries: Vec<(Vec<u8>, BencodedValue)>,
+    key: Option<Vec<u8>>,
+}
+
+impl MapSerializer {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: Vec<u8>, value: &T) -> Result<(), SerdeError> {
+        if let Some(value) = value.serialize(Serializer)? {
+            self.entries.push((key, value));
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Option<BencodedValue>;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        let key = match key.serialize(Serializer)? {
+            Some(BencodedValue::ByteString(k)) => k,
+            Some(BencodedValue::Integer(i)) => i.to_string().into_bytes(),
+            _ => return Err(SerdeError::KeyMustBeAString),
+        };
+        self.key = Some(key);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| SerdeError::Message("value serialized before its key".to_string()))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        Ok(Some(dictionary(self.entries)))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Option<BencodedValue>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.insert(key.into(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// Wraps the value of a tuple or struct variant in a dictionary with
+/// the variant name as its only key.
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> VariantSerializer<S> {
+    fn wrap(variant: &'static str, value: Option<BencodedValue>) -> Option<BencodedValue> {
+        value.map(|v| dictionary(vec![(variant.into(), v)]))
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+    type Ok = Option<BencodedValue>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.inner.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        let value = ser::SerializeSeq::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = Option<BencodedValue>;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.inner.insert(key.into(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, SerdeError> {
+        let value = ser::SerializeMap::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use std::collections::HashMap;
+
+    #[derive(Serialize)]
+    struct File {
+        path: Vec<String>,
+        length: u64,
+        md5sum: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    enum Event {
+        Started,
+        Completed(u32),
+    }
+
+    #[test]
+    fn serialize_struct_with_sorted_keys_and_without_none_fields() {
+        let file = File {
+            path: vec!["dir".to_string(), "a.txt".to_string()],
+            length: 10,
+            md5sum: None,
+        };
+
+        assert_eq!(
+            to_bytes(&file).unwrap(),
+            b"d6:lengthi10e4:pathl3:dir5:a.txtee"
+        );
+    }
+
+    #[test]
+    fn serialize_map_with_integer_keys() {
+        let mut map = HashMap::new();
+        map.insert(10, true);
+        map.insert(2, false);
+
+        assert_eq!(to_bytes(&map).unwrap(), b"d2:10i1e1:2i0ee");
+    }
+
+    #[test]
+    fn serialize_enums_externally_tagged() {
+        assert_eq!(to_bytes(&Event::Started).unwrap(), b"7:Started");
+        assert_eq!(to_bytes(&Event::Completed(3)).unwrap(), b"d9:Completedi3ee");
+    }
+
+    #[test]
+    fn serialize_float_returns_error() {
+        assert_eq!(to_bytes(&1.5), Err(SerdeError::UnsupportedType("f64")));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::bencode::parser_error::ParserError;
+use std::{error::Error, fmt};
+
+/// Represents the possible errors that can occur while converting a
+/// type from or to bencode with serde.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SerdeError {
+    /// Error reported by the `Serialize` or `Deserialize`
+    /// implementation of the type
+    Message(String),
+    /// The input is not valid bencode
+    Parse(ParserError),
+    /// The type has no representation in bencode, like floats
+    UnsupportedType(&'static str),
+    /// A dictionary key was not serialized as a string
+    KeyMustBeAString,
+}
+
+impl Error for SerdeError {}
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerdeError::Message(msg) => write!(f, "{}", msg),
+            SerdeError::Parse(e) => write!(f, "Invalid bencode: {:?}", e),
+            SerdeError::UnsupportedType(t) => {
+                write!(f, "The type {} can not be represented in bencode", t)
+            }
+            SerdeError::KeyMustBeAString => write!(f, "Dictionary keys must be strings"),
+        }
+    }
+}
+
+impl serde::ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError::Message(msg.to_string())
+    }
+}
+
+impl serde::de::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError::Message(msg.to_string())
+    }
+}
+
+impl From<ParserError> for SerdeError {
+    fn from(e: ParserError) -> Self {
+        SerdeError::Parse(e)
+    }
+}
@@ -0,0 +1,75 @@
+//! Pretty-prints a bencoded file, or the standard input, as JSON.
+//!
+//! Usage: `bencode-dump [--base64] [--encode] [FILE]`
+//!
+//! Byte strings that are not UTF-8 are written in hexadecimal, or in
+//! base64 with `--base64`. With `--encode` the input is read as JSON
+//! and written back as bencode, which is handy for crafting test
+//! fixtures.
+
+use bittorrent::bencode::json::{self, BinaryEncoding};
+use bittorrent::bencode::parser;
+use bittorrent::bencode::parser_options::ParserOptions;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::exit;
+
+const USAGE: &str = "Usage: bencode-dump [--base64] [--encode] [FILE]";
+
+fn main() {
+    let mut binary = BinaryEncoding::Hex;
+    let mut encode = false;
+    let mut file = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--base64" => binary = BinaryEncoding::Base64,
+            "--encode" => encode = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if file.is_none() && !arg.starts_with("--") => file = Some(arg),
+            _ => fail(USAGE),
+        }
+    }
+
+    let input = match read_input(file.as_deref()) {
+        Ok(input) => input,
+        Err(e) => fail(&format!("Could not read the input: {}", e)),
+    };
+
+    if encode {
+        let value = serde_json::from_slice(&input)
+            .map_err(|e| e.to_string())
+            .and_then(|v| json::from_json(&v).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| fail(&format!("Invalid JSON: {}", e)));
+        if io::stdout().write_all(&value.encode()).is_err() {
+            exit(1);
+        }
+    } else {
+        let value = parser::parse_with_options(&input, ParserOptions::default())
+            .unwrap_or_else(|e| fail(&format!("Invalid bencode: {:?}", e)));
+        let json = json::to_json(&value, binary);
+        match serde_json::to_string_pretty(&json) {
+            Ok(s) => println!("{}", s),
+            Err(e) => fail(&e.to_string()),
+        }
+    }
+}
+
+fn read_input(file: Option<&str>) -> io::Result<Vec<u8>> {
+    match file {
+        Some(path) if path != "-" => fs::read(path),
+        _ => {
+            let mut input = Vec::new();
+            io::stdin().read_to_end(&mut input)?;
+            Ok(input)
+        }
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    exit(1)
+}
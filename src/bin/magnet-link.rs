@@ -0,0 +1,39 @@
+//! Prints the magnet links of torrent files, one per line.
+//!
+//! Usage: `magnet-link FILE...`
+//!
+//! The links carry the info hash, the name and the trackers of the
+//! torrent. Saved in a `.magnet` file in the torrents folder, they
+//! are resolved by the client on start.
+
+use bittorrent::torrent::magnet::Magnet;
+use bittorrent::torrent::metainfo;
+use std::env;
+use std::fs::File;
+use std::process::exit;
+
+const USAGE: &str = "Usage: magnet-link FILE...";
+
+fn main() {
+    let files: Vec<String> = env::args().skip(1).collect();
+    if files.is_empty() {
+        fail(USAGE);
+    }
+    if files.iter().any(|f| f == "-h" || f == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+
+    for file in files {
+        let metainfo = File::open(&file)
+            .map_err(|e| e.to_string())
+            .and_then(|f| metainfo::read_torrent(f).map_err(|e| format!("{:?}", e)))
+            .unwrap_or_else(|e| fail(&format!("Could not read {}: {}", file, e)));
+        println!("{}", Magnet::from_metainfo(&metainfo).to_uri());
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    exit(1)
+}
@@ -0,0 +1,99 @@
+//! Creates a `.torrent` file for a file or a directory.
+//!
+//! Usage: `make-torrent -a URL [OPTIONS] PATH`
+//!
+//! Every `-a` adds a tracker in its own tier, the first one is also
+//! the `announce` key. Use `-t` to add a tier with several comma
+//! separated trackers.
+
+use bittorrent::torrent::torrent_creator::TorrentCreator;
+use std::env;
+use std::fs;
+use std::process::exit;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "Usage: make-torrent -a URL [OPTIONS] PATH
+
+Options:
+  -a, --announce URL        tracker URL, can be repeated (one tier each)
+  -t, --tier URL[,URL...]   tier of trackers, can be repeated
+  -p, --piece-length BYTES  piece length, a power of two (default: automatic)
+  -c, --comment TEXT        comment
+  -o, --output FILE         output file (default: NAME.torrent)
+      --private             set the private flag
+      --created-by TEXT     program that created the torrent
+      --no-date             leave out the creation date";
+
+fn main() {
+    let mut tiers: Vec<Vec<String>> = Vec::new();
+    let mut piece_length = None;
+    let mut comment = None;
+    let mut output = None;
+    let mut private = false;
+    let mut created_by = Some(format!("bittorrent/{}", env!("CARGO_PKG_VERSION")));
+    let mut date = true;
+    let mut path = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| fail(USAGE));
+        match arg.as_str() {
+            "-a" | "--announce" => tiers.push(vec![value()]),
+            "-t" | "--tier" => tiers.push(value().split(',').map(String::from).collect()),
+            "-p" | "--piece-length" => {
+                piece_length = Some(value().parse().unwrap_or_else(|_| fail(USAGE)))
+            }
+            "-c" | "--comment" => comment = Some(value()),
+            "-o" | "--output" => output = Some(value()),
+            "--private" => private = true,
+            "--created-by" => created_by = Some(value()),
+            "--no-date" => date = false,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => fail(USAGE),
+        }
+    }
+
+    let path = path.unwrap_or_else(|| fail(USAGE));
+    let announce = match tiers.first().and_then(|t| t.first()) {
+        Some(a) => a.clone(),
+        None => fail(USAGE),
+    };
+    let creation_date = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .filter(|_| date)
+        .map(|d| d.as_secs() as i64);
+
+    let mut creator = TorrentCreator::new(&path, &announce);
+    creator
+        .piece_length(piece_length)
+        .comment(comment)
+        .private(private)
+        .created_by(created_by)
+        .creation_date(creation_date);
+    if tiers.len() > 1 || tiers[0].len() > 1 {
+        creator.announce_list(Some(tiers));
+    }
+    let bytes = creator
+        .create()
+        .unwrap_or_else(|e| fail(&format!("Could not create the torrent: {}", e)));
+
+    let output = output.unwrap_or_else(|| {
+        let name = path.trim_end_matches('/');
+        let name = name.rsplit('/').next().unwrap_or(name);
+        format!("{}.torrent", name)
+    });
+    if let Err(e) = fs::write(&output, bytes) {
+        fail(&format!("Could not write {}: {}", output, e));
+    }
+    println!("Created {}", output);
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    exit(1)
+}
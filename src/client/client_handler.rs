@@ -1,6 +1,7 @@
-use log::error;
+use log::{error, info, warn};
 
 use crate::client::client_error::ClientError;
+use crate::client::magnet_resolver;
 use crate::client::torrent_file::TorrentFile;
 use crate::config;
 use crate::download::handler::HandlerDownload;
@@ -8,11 +9,17 @@ use crate::download::handler::HandlerDownload;
 use crate::log::logger::LogHandle;
 use crate::log::logger::Logger;
 use crate::peer::peer_handler::Peer;
+use crate::peer::peer_id::session_peer_id;
+use crate::peer::peer_source::PeerSource;
+use crate::proxy::connector;
 use crate::server::server_handler::Server;
+use crate::torrent::magnet::Magnet;
 
 use crate::tracker::handler::Handler;
-use crate::tracker::request::tracker_request::TrackerRequest;
+use crate::tracker::scrape::scrape;
 
+use crate::tracker::response::scrape_data::ScrapeData;
+use crate::tracker::response::tracker_response::ResponseData;
 use crate::tracker::response::tracker_response::TrackerResponse;
 use crate::tracker::response::tracker_response::TrackerResponseMode::Failure;
 use crate::tracker::response::tracker_response::TrackerResponseMode::Response;
@@ -24,18 +31,32 @@ use crate::ui::render::Render;
 use crate::ui::render::RequestMessage;
 use crate::ui::render::TorrentId;
 use crate::ui::render::TorrentViewRawData;
-use crate::utils;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::ffi::OsStr;
 use std::fs;
 
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::path::Path;
-use std::sync::mpsc::TryRecvError;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Mutex, MutexGuard};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Time between checks of the announce schedule of the torrents.
+const ANNOUNCE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Messages sent to the client thread, by the UI listener and by the
+/// threads resolving magnet links.
+#[derive(Debug)]
+pub enum ClientMessage {
+    Terminate,
+    /// The metadata of a magnet link was fetched, the torrent can be
+    /// downloaded.
+    Resolved(Box<TorrentFile>),
+}
 
 #[derive(Debug)]
 /// Represents a bittorrent client.
@@ -71,14 +92,26 @@ impl Client {
     ) -> Result<(JoinHandle<()>, JoinHandle<()>), ClientError> {
         //Listener ui
         let mut log_handle = logger.new_handler();
-        let thread_listener_ui = listener_ui(Arc::clone(&torrents), render, log_handle.clone())
-            .ok_or(ClientError::ThreadError)?;
+        let (client_sender, client_receiver) = mpsc::channel();
+        let thread_listener_ui = listener_ui(
+            Arc::clone(&torrents),
+            render,
+            log_handle.clone(),
+            client_sender.clone(),
+        )
+        .ok_or(ClientError::ThreadError)?;
 
         //Config
 
         //Thread cliente
         let client_handler = thread::spawn(move || {
-            match start_client(config, log_handle.clone(), torrents) {
+            match start_client(
+                config,
+                log_handle.clone(),
+                torrents,
+                client_sender,
+                client_receiver,
+            ) {
                 Some(_) => (),
                 None => {
                     error!("An error ocurred while downloading the torrents, closing client...");
@@ -93,82 +126,306 @@ impl Client {
 }
 
 /// Starts the main client thread, starting both the download and
-/// serving processes
+/// serving processes. The magnet links are resolved in the
+/// background, `sender` hands the resulting torrents to the client.
 fn start_client(
     config: config::Config,
     logger: LogHandle,
     torrents: Arc<Mutex<Vec<TorrentFile>>>,
+    sender: Sender<ClientMessage>,
+    receiver: Receiver<ClientMessage>,
 ) -> Option<()> {
+    connector::set_proxy(config.proxy());
     //Server
     let mut server = Server::new(Arc::clone(&torrents));
-    server
-        .run(config.tcp_port(), config.logs(), logger.clone())
+    let port = server
+        .run(config.tcp_ports(), config.logs(), logger.clone())
+        .inspect_err(|e| {
+            error!("Couldn't start the server: {:?}", e);
+            logger
+                .clone()
+                .error(&format!("Couldn't start the server: {:?}", e));
+        })
         .ok()?;
-    download_torrents(torrents, config, logger)
+    let scrape_torrents = Arc::clone(&torrents);
+    let scrape_logger = logger.clone();
+    thread::spawn(move || scrape_trackers(scrape_torrents, scrape_logger));
+    resolve_magnets(&torrents, &config, port, sender, logger.clone())?;
+    let downloads = download_torrents(Arc::clone(&torrents), &config, port, logger.clone())?;
+    announce_torrents(torrents, downloads, &config, port, logger, receiver)
+}
+
+/// Resolves the magnet links of the `.magnet` files in the torrents
+/// folder, one link per line, in a thread each. The links of torrents
+/// already loaded are skipped.
+fn resolve_magnets(
+    torrents: &Arc<Mutex<Vec<TorrentFile>>>,
+    config: &config::Config,
+    port: u16,
+    sender: Sender<ClientMessage>,
+    mut logger: LogHandle,
+) -> Option<()> {
+    let mut hashes: Vec<Vec<u8>> = lock_torrents(torrents, &mut logger)?
+        .iter()
+        .map(|t| t.get_info_hash())
+        .collect();
+    for uri in read_magnets(config.torrents()) {
+        let magnet = match Magnet::parse(&uri) {
+            Ok(magnet) => magnet,
+            Err(e) => {
+                warn!("Invalid magnet link {}: {}", uri, e);
+                logger.error(&format!("Invalid magnet link {}: {}", uri, e));
+                continue;
+            }
+        };
+        if hashes.contains(&magnet.info_hash.to_vec()) {
+            continue;
+        }
+        hashes.push(magnet.info_hash.to_vec());
+
+        let (dir, external_ip) = (config.torrents(), config.external_ip());
+        let (sender, logger) = (sender.clone(), logger.clone());
+        thread::spawn(move || {
+            if let Some(torrent) =
+                magnet_resolver::resolve(&magnet, &dir, external_ip, port, logger)
+            {
+                let _ = sender.send(ClientMessage::Resolved(Box::new(torrent)));
+            }
+        });
+    }
+    Some(())
+}
+
+/// Returns the magnet links in the `.magnet` files of the folder.
+fn read_magnets<P: AsRef<Path>>(path: P) -> Vec<String> {
+    let dir = match fs::read_dir(path) {
+        Ok(dir) => dir,
+        Err(_) => return Vec::new(),
+    };
+    dir.flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension() == Some(OsStr::new("magnet")))
+        .flat_map(fs::read_to_string)
+        .flat_map(|s| {
+            s.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .collect()
 }
 
 /// Genrates a thread for each torrent, where the client will interact
-/// with the associated peers
+/// with the associated peers. The peers are handed to the threads as
+/// the trackers return them.
 fn download_torrents(
     torrents: Arc<Mutex<Vec<TorrentFile>>>,
-    config: config::Config,
+    config: &config::Config,
+    port: u16,
     mut logger: LogHandle,
+) -> Option<Vec<HandlerDownload>> {
+    let lock = lock_torrents(&torrents, &mut logger)?;
+    Some(
+        lock.iter()
+            .enumerate()
+            .map(|(i, torrent)| {
+                HandlerDownload::new(
+                    logger.clone(),
+                    Arc::clone(&torrents),
+                    i,
+                    config.downloads(),
+                    torrent.clone(),
+                    port,
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Announces the torrents to their trackers whenever their scheduler
+/// says so, handing the new peers to the downloads along with the
+/// ones found through the peer exchange, which are recorded on the
+/// torrent. When the ui terminates the trackers are told that the
+/// client stopped. The torrents of resolved magnet links start
+/// downloading as they arrive. `port` is the one the server is
+/// listening at.
+fn announce_torrents(
+    torrents: Arc<Mutex<Vec<TorrentFile>>>,
+    mut downloads: Vec<HandlerDownload>,
+    config: &config::Config,
+    port: u16,
+    mut logger: LogHandle,
+    receiver: Receiver<ClientMessage>,
 ) -> Option<()> {
-    let mut lock = match torrents.lock() {
-        Ok(t) => t,
-        Err(_) => {
-            error!("Poisoned Mutex");
-            logger.error("Poisoned Mutex");
+    loop {
+        for (i, download) in downloads.iter().enumerate() {
+            if let Some(peers) = announce_if_due(&torrents, i, config, port, &mut logger) {
+                download.add_peers(peers);
+            }
+            let found = {
+                let mut lock = lock_torrents(&torrents, &mut logger)?;
+                let found = lock[i].pex.take_found();
+                lock[i].add_found_peers(found)
+            };
+            if !found.is_empty() {
+                download.add_peers(found);
+            }
+        }
+        match receiver.recv_timeout(ANNOUNCE_CHECK_INTERVAL) {
+            Ok(ClientMessage::Terminate) | Err(RecvTimeoutError::Disconnected) => break,
+            Ok(ClientMessage::Resolved(torrent)) => {
+                let i = {
+                    let mut lock = lock_torrents(&torrents, &mut logger)?;
+                    lock.push((*torrent).clone());
+                    lock.len() - 1
+                };
+                downloads.push(HandlerDownload::new(
+                    logger.clone(),
+                    Arc::clone(&torrents),
+                    i,
+                    config.downloads(),
+                    *torrent,
+                    port,
+                ));
+            }
+            Err(RecvTimeoutError::Timeout) => continue,
+        }
+    }
+
+    for i in 0..downloads.len() {
+        stop_torrent(&torrents, i, config, port, &mut logger)?;
+    }
+    Some(())
+}
+
+/// Announces the torrent if it's due, returning the peers sent by the
+/// tracker. The torrents are not locked while waiting for the tracker.
+fn announce_if_due(
+    torrents: &Arc<Mutex<Vec<TorrentFile>>>,
+    i: usize,
+    config: &config::Config,
+    port: u16,
+    logger: &mut LogHandle,
+) -> Option<Vec<Peer>> {
+    let now = Instant::now();
+    let mut torrent = {
+        let mut lock = lock_torrents(torrents, logger)?;
+        let torrent = &mut lock[i];
+        let left = torrent.left();
+        torrent.scheduler.set_left(left, now);
+        if !torrent.scheduler.is_due(now, torrent.peers_connected.len()) {
             return None;
         }
+        torrent.clone()
     };
-    let mut connected = false;
-    for (i, torrent) in lock.iter_mut().enumerate() {
-        let info_hash = utils::hash_info(&torrent.metainfo.info.bencode());
-        let request = match handle_tracker(torrent, info_hash, &config, &logger) {
-            Some(r) => r,
-            None => continue,
-        };
+    let response = handle_tracker(&mut torrent, config, port, logger);
+
+    let mut lock = lock_torrents(torrents, logger)?;
+    let shared = &mut lock[i];
+    shared.trackers = torrent.trackers;
+    shared.scheduler = torrent.scheduler;
+    let response = response?;
+    shared.swarm = Some(ScrapeData {
+        complete: response.complete,
+        downloaded: shared.swarm.map_or(0, |s| s.downloaded),
+        incomplete: response.incomplete,
+    });
+    shared.response = Some(response.clone());
+    Some(response.peers)
+}
 
-        let response = match torrent.response.as_mut() {
-            Some(r) => r,
-            None => continue,
-        };
+/// Scrapes the trackers of the torrents, so the statistics of the
+/// swarms can be shown before the first announces answer. The
+/// torrents that share a tracker are scraped together. Like an
+/// announce, a torrent whose tracker fails falls back to the next
+/// one of its tiers.
+fn scrape_trackers(torrents: Arc<Mutex<Vec<TorrentFile>>>, mut logger: LogHandle) -> Option<()> {
+    let mut pending: Vec<([u8; 20], VecDeque<String>)> = lock_torrents(&torrents, &mut logger)?
+        .iter()
+        .map(|t| {
+            let urls = t.trackers.trackers().map(|tracker| tracker.url.clone());
+            (t.metainfo.info_hash(), urls.collect())
+        })
+        .collect();
+    let mut failed: HashSet<String> = HashSet::new();
 
-        if cfg!(feature = "server-demo") {
-            let peer = Peer {
-                peer_id: Some(request.peer_id),
-                ip: Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
-                port: config.tcp_port(),
-            };
-            response.peers.push(peer);
+    while !pending.is_empty() {
+        let mut by_tracker: HashMap<String, Vec<[u8; 20]>> = HashMap::new();
+        for (info_hash, urls) in pending.iter_mut() {
+            while urls.front().is_some_and(|url| failed.contains(url)) {
+                urls.pop_front();
+            }
+            if let Some(url) = urls.pop_front() {
+                by_tracker.entry(url).or_default().push(*info_hash);
+            }
+        }
+        if by_tracker.is_empty() {
+            break;
         }
-        HandlerDownload::new(
-            logger.clone(),
-            Arc::clone(&torrents),
-            i,
-            config.downloads(),
-            torrent.clone(),
-        );
 
-        connected |= true;
+        let mut scraped: HashMap<[u8; 20], ScrapeData> = HashMap::new();
+        for (url, info_hashes) in by_tracker {
+            match scrape(&url, &info_hashes, &mut logger) {
+                Ok(stats) => scraped.extend(stats),
+                Err(e) => {
+                    info!("Couldn't scrape {}: {}", url, e);
+                    logger.info(&format!("Couldn't scrape {}: {}", url, e));
+                    failed.insert(url);
+                }
+            }
+        }
+        for t in lock_torrents(&torrents, &mut logger)?.iter_mut() {
+            // The statistics of an announce are newer
+            if t.swarm.is_none() {
+                t.swarm = scraped.get(&t.metainfo.info_hash()).copied();
+            }
+        }
+        pending.retain(|(info_hash, urls)| !scraped.contains_key(info_hash) && !urls.is_empty());
     }
+    Some(())
+}
 
-    if !connected {
-        error!("Couldn't connect to any torrent");
-        logger.error("Couldn't connect to any torrent");
-        None
-    } else {
-        Some(()) // Could connect to at least one torrent
+/// Tells the trackers that the client stopped sharing the torrent, if
+/// they were told that it started.
+fn stop_torrent(
+    torrents: &Arc<Mutex<Vec<TorrentFile>>>,
+    i: usize,
+    config: &config::Config,
+    port: u16,
+    logger: &mut LogHandle,
+) -> Option<()> {
+    let mut torrent = lock_torrents(torrents, logger)?[i].clone();
+    if torrent.scheduler.is_started() {
+        torrent.scheduler.stop(Instant::now());
+        handle_tracker(&mut torrent, config, port, logger);
+    }
+    Some(())
+}
+
+/// Locks the torrents, logging if the mutex is poisoned
+fn lock_torrents<'a>(
+    torrents: &'a Arc<Mutex<Vec<TorrentFile>>>,
+    logger: &mut LogHandle,
+) -> Option<MutexGuard<'a, Vec<TorrentFile>>> {
+    match torrents.lock() {
+        Ok(t) => Some(t),
+        Err(_) => {
+            error!("Poisoned Mutex");
+            logger.error("Poisoned Mutex");
+            None
+        }
     }
 }
 
 /// Spawns a thread that will serve as a intermediary between the
-/// client and the UI
+/// client and the UI. When the UI terminates the client is told to
+/// terminate too.
 pub fn listener_ui(
     torrents: Arc<Mutex<Vec<TorrentFile>>>,
     render: Arc<Mutex<Render>>,
     mut logger: LogHandle,
+    client: Sender<ClientMessage>,
 ) -> Option<JoinHandle<()>> {
     let raw_data = get_info_wrapper(RequestMessage::MainView, &torrents, &mut logger)?;
     send_data_to_view(
@@ -191,6 +448,11 @@ pub fn listener_ui(
                 break;
             }
         };
+        if msg == RequestMessage::Terminate {
+            let _ = client.send(ClientMessage::Terminate);
+            send_data_to_view(MessagesFromMain::Terminate, &render, &mut log_handle);
+            break;
+        }
         handle_ui_msg(
             msg,
             Arc::clone(&torrents),
@@ -214,18 +476,17 @@ pub fn get_info(torrents: &[TorrentFile], msg: RequestMessage) -> Vec<RawData> {
 fn main_view_msg(torrents: &[TorrentFile]) -> Vec<RawData> {
     let mut vec = Vec::new();
     for t in torrents {
-        let info = t.metainfo.info().unwrap();
-        let mut peers = Vec::new();
-        if let Some(res) = t.response.clone() {
-            peers = res.peers.clone();
-        };
+        let info = t.metainfo.info();
+        let peers = t.peers();
         let data = RawData::Main {
-            name: info.name,
-            authentication_hash: utils::hash_info(&t.metainfo.info.bencode()).to_vec(),
-            total_size: info.length as u32,
-            number_of_pieces: (info.length / info.piece_length) as u32,
+            name: info.name(),
+            authentication_hash: t.get_info_hash(),
+            total_size: info.length() as u32,
+            number_of_pieces: t.pieces_ammount as u32,
             number_of_peers: peers.len() as u32,
             remaining_pieces: t.bitfield.get_missing().len() as u32,
+            seeders: t.swarm.map(|s| s.complete as u32),
+            leechers: t.swarm.map(|s| s.incomplete as u32),
         };
         vec.push(data);
     }
@@ -235,16 +496,13 @@ fn main_view_msg(torrents: &[TorrentFile]) -> Vec<RawData> {
 fn torrent_view_msg(torrents: &[TorrentFile]) -> Vec<RawData> {
     let mut vec = Vec::new();
     for t in torrents {
-        let info = t.metainfo.info().unwrap();
-        let mut peers = Vec::new();
-        if let Some(res) = t.response.clone() {
-            peers = res.peers.clone();
-        };
+        let info = t.metainfo.info();
+        let peers = t.peers();
         let data = RawData::Torrent {
-            name: info.name.clone(),
-            authentication_hash: utils::hash_info(&t.metainfo.info.bencode()).to_vec(),
-            total_size: info.length as u32,
-            number_of_pieces: (info.length / info.piece_length) as u32,
+            name: info.name(),
+            authentication_hash: t.get_info_hash(),
+            total_size: info.length() as u32,
+            number_of_pieces: t.pieces_ammount as u32,
             number_of_peers: peers.len() as u32,
             remaining_pieces: t.bitfield.get_missing().len() as u32,
             active_connections: t.peers_connected.len(),
@@ -259,17 +517,16 @@ fn live_view_msg(torrents: &[TorrentFile], id: TorrentId) -> Vec<RawData> {
     let t_clone = torrents.to_owned();
     let torrent: Vec<&TorrentFile> = t_clone
         .iter()
-        .filter(|&t| utils::get_info_from_torrentfile(t.metainfo.info.clone()).name == id.0)
+        .filter(|&t| t.metainfo.info().name() == id.0)
         .collect();
     let t = torrent[0];
-    let info = t.metainfo.info().unwrap();
+    let info = t.metainfo.info();
     let data = RawData::Live {
-        name: utils::get_info_from_torrentfile(t.metainfo.info.clone()).name,
+        name: info.name(),
         active_peers: t.peers_connected.clone(),
         upload_speed: 0,
-        downloaded_files: (info.length / info.piece_length) as u32
-            - t.bitfield.get_missing().len() as u32,
-        piece_size: info.piece_length as u32,
+        downloaded_files: t.pieces_ammount as u32 - t.bitfield.get_missing().len() as u32,
+        piece_size: info.piece_length() as u32,
     };
     vec.push(data);
 
@@ -291,30 +548,68 @@ pub fn get_files<P: AsRef<Path>>(path: P) -> Option<Vec<String>> {
     )
 }
 
-/// Interaction with the tracker
+/// Interaction with the tracker. Announces to the trackers of the
+/// torrent in order, until one of them answers, with the event and
+/// the statistics of the torrent, the port the client listens at and
+/// the external ip of the config. The next announce is scheduled
+/// according to the result.
 fn handle_tracker(
     torrent: &mut TorrentFile,
-    info_hash: [u8; 20],
     config: &config::Config,
+    port: u16,
     logger: &LogHandle,
-) -> Option<TrackerRequest> {
-    let mut tracker_request = TrackerRequest::new(
-        info_hash,
-        torrent.metainfo.announce.clone(),
-        config.tcp_port(),
-    );
-
-    let handler = Handler::new(logger.clone(), &mut tracker_request);
-    handler
-        .tracker_response
-        .as_ref()
-        .and_then(|TrackerResponse(r)| match r {
-            Failure => None,
-            Response(response_data) => {
-                torrent.response = Some(response_data.clone());
-                Some(tracker_request)
+) -> Option<ResponseData> {
+    let request = torrent.tracker_request(port);
+    let response = torrent.trackers.announce(|tracker| {
+        let url = tracker.url.clone();
+        let mut tracker_request = request.clone();
+        tracker_request.announce = url.clone();
+        tracker_request.set_ip(config.external_ip());
+        tracker_request.set_tracker_id(tracker.tracker_id.clone());
+        let handler = Handler::new(logger.clone(), &mut tracker_request);
+        match handler.tracker_response {
+            Some(TrackerResponse(Response(response_data))) => {
+                if response_data.tracker_id.is_some() {
+                    tracker.tracker_id = response_data.tracker_id.clone();
+                }
+                if let Some(ref warning) = response_data.warning_message {
+                    warn!("Tracker {} warning: {}", url, warning);
+                    logger
+                        .clone()
+                        .info(&format!("Tracker {} warning: {}", url, warning));
+                }
+                let peers = response_data.peers.len();
+                Ok((response_data, peers))
             }
-        })
+            Some(TrackerResponse(Failure(reason))) => {
+                Err(format!("The tracker returned a failure: {}", reason))
+            }
+            None => Err(handler.error.unwrap_or_default()),
+        }
+    });
+
+    let now = Instant::now();
+    let mut response_data = match response {
+        Some(r) => r,
+        None => {
+            torrent.scheduler.failed(now);
+            return None;
+        }
+    };
+    torrent
+        .scheduler
+        .succeeded(now, response_data.interval, response_data.min_interval);
+
+    if cfg!(feature = "server-demo") {
+        let peer = Peer {
+            peer_id: Some(session_peer_id()),
+            ip: Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+            port,
+            source: PeerSource::Tracker,
+        };
+        response_data.peers.push(peer);
+    }
+    Some(response_data)
 }
 
 /// Wrapper over get_info() that adds logging
@@ -0,0 +1,221 @@
+use crate::bencode::bencoded_value::BencodedValue;
+use crate::client::torrent_file::TorrentFile;
+use crate::download::handler::connect_to_useful_peer;
+use crate::log::logger::LogHandle;
+use crate::peer::peer_handler::Peer;
+use crate::pwp::extension_registry::ExtensionRegistry;
+use crate::pwp::metadata::Metadata;
+use crate::pwp::reserved::Capability;
+use crate::pwp::ut_metadata::{self, UtMetadata};
+use crate::torrent::magnet::Magnet;
+use crate::torrent::metainfo;
+use crate::tracker::announce_list::AnnounceList;
+use crate::tracker::handler::Handler;
+use crate::tracker::request::tracker_request::TrackerRequest;
+use crate::tracker::response::tracker_response::TrackerResponse;
+use crate::tracker::response::tracker_response::TrackerResponseMode::{Failure, Response};
+use crate::utils;
+use log::{info, warn};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest wait for the metadata of a magnet link.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(120);
+/// Longest wait for a message of a peer while fetching the metadata.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Peers asked for the metadata at the same time.
+const MAX_PEERS: usize = 20;
+/// `left` announced while the size of the torrent is unknown. Any
+/// value but zero keeps the trackers from taking the client for a
+/// seeder, which wouldn't get other seeders back.
+const UNKNOWN_LEFT: u64 = 1;
+
+/// Fetches the info dictionary of the magnet from the peers its
+/// trackers return, and builds the torrent from it. The torrent file
+/// is also written to `dir`, so the link doesn't have to be resolved
+/// again. Returns [`None`] if no peer sent the metadata in time.
+pub fn resolve(
+    magnet: &Magnet,
+    dir: &str,
+    external_ip: Option<IpAddr>,
+    port: u16,
+    mut logger: LogHandle,
+) -> Option<TorrentFile> {
+    let name = magnet
+        .name
+        .clone()
+        .unwrap_or_else(|| utils::to_hex(&magnet.info_hash));
+    info!("Fetching the metadata of {}", name);
+    logger.info(&format!("Fetching the metadata of {}", name));
+
+    let info = find_peers(magnet, external_ip, port, &logger)
+        .and_then(|peers| fetch_metadata(magnet.info_hash, peers, port));
+    let info = match info {
+        Some(info) => info,
+        None => {
+            warn!("Couldn't fetch the metadata of {}", name);
+            logger.error(&format!("Couldn't fetch the metadata of {}", name));
+            return None;
+        }
+    };
+
+    let bytes = torrent_bytes(magnet, &info);
+    let metainfo = metainfo::read_torrent(&bytes[..]).ok()?;
+    let path = Path::new(dir).join(format!("{}.torrent", utils::to_hex(&magnet.info_hash)));
+    if let Err(e) = fs::write(&path, &bytes) {
+        warn!("Couldn't save the torrent of {}: {}", name, e);
+        logger.error(&format!("Couldn't save the torrent of {}: {}", name, e));
+    }
+    TorrentFile::from_metainfo(path.to_string_lossy().into_owned(), metainfo).ok()
+}
+
+/// Announces the magnet to its trackers until one of them returns
+/// peers.
+fn find_peers(
+    magnet: &Magnet,
+    external_ip: Option<IpAddr>,
+    port: u16,
+    logger: &LogHandle,
+) -> Option<Vec<Peer>> {
+    let tiers = magnet.trackers.iter().map(|t| vec![t.clone()]).collect();
+    let key = rand::random();
+    AnnounceList::from_tiers(tiers).announce(|tracker| {
+        let mut request = TrackerRequest::new(magnet.info_hash, tracker.url.clone(), port);
+        request.set_ip(external_ip);
+        request.set_event(None);
+        request.set_stats(0, 0, UNKNOWN_LEFT);
+        request.set_key(key);
+        let handler = Handler::new(logger.clone(), &mut request);
+        match handler.tracker_response {
+            Some(TrackerResponse(Response(response_data))) if !response_data.peers.is_empty() => {
+                let peers = response_data.peers.len();
+                Ok((response_data.peers, peers))
+            }
+            Some(TrackerResponse(Response(_))) => Err("The tracker returned no peers".to_string()),
+            Some(TrackerResponse(Failure(reason))) => {
+                Err(format!("The tracker returned a failure: {}", reason))
+            }
+            None => Err(handler.error.unwrap_or_default()),
+        }
+    })
+}
+
+/// Asks the peers for the metadata at the same time, until one of
+/// them completes it or all of them fail.
+fn fetch_metadata(info_hash: [u8; 20], peers: Vec<Peer>, port: u16) -> Option<Vec<u8>> {
+    let metadata = Arc::new(Mutex::new(Metadata::new(info_hash)));
+    let (done_sender, done) = mpsc::channel();
+    for peer in peers.into_iter().take(MAX_PEERS) {
+        let metadata = Arc::clone(&metadata);
+        let done_sender = done_sender.clone();
+        thread::spawn(move || {
+            fetch_from_peer(peer, info_hash, port, &metadata);
+            let _ = done_sender.send(());
+        });
+    }
+    drop(done_sender);
+
+    let deadline = Instant::now() + RESOLVE_TIMEOUT;
+    loop {
+        let finished = done
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .is_err();
+        let info = lock(&metadata).info().map(<[u8]>::to_vec);
+        if info.is_some() || finished {
+            return info;
+        }
+    }
+}
+
+/// Reads from the peer, which answers the requests of the metadata
+/// inside [`PWPStream::read`](crate::pwp::protocol::PWPStream::read),
+/// until the metadata is complete.
+fn fetch_from_peer(
+    peer: Peer,
+    info_hash: [u8; 20],
+    port: u16,
+    metadata: &Arc<Mutex<Metadata>>,
+) -> Option<()> {
+    let mut extensions = ExtensionRegistry::new(port);
+    extensions.register(Box::new(UtMetadata::new(Arc::clone(metadata))));
+    let mut stream = connect_to_useful_peer(peer, info_hash.to_vec(), extensions)?;
+    if !stream.peer_supports(Capability::Extension) {
+        return None;
+    }
+    stream.set_read_timeout(Some(READ_TIMEOUT)).ok()?;
+    while lock(metadata).info().is_none() {
+        if let Some(handshake) = stream.extensions().peer_handshake() {
+            handshake.id(ut_metadata::NAME)?;
+        }
+        stream.read().ok()?;
+    }
+    Some(())
+}
+
+/// Builds the contents of the torrent file, with the trackers of the
+/// magnet. The info dictionary is copied as it was received, so the
+/// info hash doesn't change.
+fn torrent_bytes(magnet: &Magnet, info: &[u8]) -> Vec<u8> {
+    let string = |s: &str| BencodedValue::ByteString(s.as_bytes().to_vec());
+    let announce = magnet
+        .trackers
+        .first()
+        .map(String::as_str)
+        .unwrap_or_default();
+    let tiers = magnet
+        .trackers
+        .iter()
+        .map(|t| BencodedValue::List(vec![string(t)]))
+        .collect();
+
+    let mut bytes = vec![b'd'];
+    bytes.extend(string("announce").encode());
+    bytes.extend(string(announce).encode());
+    bytes.extend(string("announce-list").encode());
+    bytes.extend(BencodedValue::List(tiers).encode());
+    bytes.extend(string("info").encode());
+    bytes.extend_from_slice(info);
+    bytes.push(b'e');
+    bytes
+}
+
+fn lock(metadata: &Mutex<Metadata>) -> MutexGuard<'_, Metadata> {
+    match metadata.lock() {
+        Ok(metadata) => metadata,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn torrent_keeps_the_info_hash_of_the_metadata() {
+        let info = b"d6:lengthi3e4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+        let magnet = Magnet {
+            info_hash: utils::hash_info(info),
+            name: None,
+            trackers: vec!["http://a/announce".to_string(), "udp://b:80".to_string()],
+        };
+
+        let metainfo = metainfo::read_torrent(&torrent_bytes(&magnet, info)[..]).unwrap();
+
+        assert_eq!(metainfo.info_hash(), magnet.info_hash);
+        assert_eq!(metainfo.announce, "http://a/announce");
+        assert_eq!(
+            metainfo.announce_list,
+            Some(vec![
+                vec!["http://a/announce".to_string()],
+                vec!["udp://b:80".to_string()]
+            ])
+        );
+        assert_eq!(metainfo.info().name(), "a");
+        assert_eq!(Magnet::from_metainfo(&metainfo).trackers, magnet.trackers);
+    }
+}
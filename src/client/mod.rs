@@ -2,5 +2,6 @@ pub mod bitfield;
 pub mod bitfield_error;
 pub mod client_error;
 pub mod client_handler;
+pub mod magnet_resolver;
 pub mod torrent_file;
 pub mod torrent_file_error;
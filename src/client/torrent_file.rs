@@ -1,12 +1,23 @@
 use std::fs::File;
+use std::time::Instant;
 
 use crate::client::bitfield::BitField;
 use crate::client::torrent_file_error::TorrentFileError;
 use crate::peer::peer_handler::Peer;
+use crate::pwp::metadata::Metadata;
+use crate::pwp::pex_swarm::PexSwarm;
 use crate::torrent::info::Info;
+use crate::torrent::magnet::Magnet;
 use crate::torrent::metainfo::{self, Metainfo};
+use crate::tracker::announce_list::AnnounceList;
+use crate::tracker::announce_scheduler::AnnounceScheduler;
+use crate::tracker::request::tracker_request::TrackerRequest;
+use crate::tracker::request::tracker_request_event::TrackerRequestEvent;
+use crate::tracker::response::scrape_data::ScrapeData;
 use crate::tracker::response::tracker_response::ResponseData;
-use crate::utils;
+
+/// Most peers found through the peer exchange kept for a torrent.
+const MAX_FOUND_PEERS: usize = 200;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 /// Represents a torrent file.
@@ -23,6 +34,28 @@ pub struct TorrentFile {
     pub count_connections: i32,
     pub peers_connected: Vec<Peer>,
     pub pieces_ammount: usize,
+    /// Trackers of the torrent grouped in tiers, with the status of
+    /// the last announce sent to each one.
+    pub trackers: AnnounceList,
+    /// When the torrent has to be announced next, and with which event.
+    pub scheduler: AnnounceScheduler,
+    /// Bytes sent to other peers since the client started.
+    pub uploaded: u64,
+    /// Bytes of the verified pieces downloaded since the client started.
+    pub downloaded: u64,
+    /// Statistics of the swarm, from the last scrape or announce.
+    pub swarm: Option<ScrapeData>,
+    /// `key` sent in every announce of the torrent.
+    pub key: u32,
+    /// Peers exchanged with the other peers, shared by the clones of
+    /// the torrent.
+    pub pex: PexSwarm,
+    /// Peers found through the peer exchange, which the announces
+    /// don't replace.
+    pub found_peers: Vec<Peer>,
+    /// Info dictionary sent to the peers with `ut_metadata`, as it
+    /// was read.
+    metadata: Option<Metadata>,
 }
 
 impl TorrentFile {
@@ -32,54 +65,289 @@ impl TorrentFile {
 
         let metainfo =
             metainfo::read_torrent(&file).map_err(|_| TorrentFileError::MetainfoError)?;
-        let Info(mode) = metainfo.info.clone();
-        let info = match mode {
-            crate::torrent::info::InfoMode::Empty => todo!(),
-            crate::torrent::info::InfoMode::SingleFile(it) => it,
-        };
+        Self::from_metainfo(file_name, metainfo)
+    }
+
+    /// Creates a new [`TorrentFile`] from a metainfo that was already
+    /// read, like the one built from a magnet link.
+    pub fn from_metainfo(file_name: String, metainfo: Metainfo) -> Result<Self, TorrentFileError> {
+        let pieces_ammount = pieces_ammount(metainfo.info());
+        let trackers = AnnounceList::new(&metainfo);
+        let metadata = Metadata::complete(metainfo.info_hash(), metainfo.info_bytes().to_vec());
 
         Ok(TorrentFile {
             file_name,
             metainfo,
-            bitfield: BitField::new(info.length as usize / info.piece_length as usize)
-                .map_err(|_| TorrentFileError::BitFieldError)?,
+            bitfield: BitField::new(pieces_ammount).map_err(|_| TorrentFileError::BitFieldError)?,
             response: None,
             count_connections: 0,
             peers_connected: Vec::new(),
-            pieces_ammount: info.length as usize / info.piece_length as usize,
+            pieces_ammount,
+            trackers,
+            scheduler: AnnounceScheduler::new(Instant::now()),
+            uploaded: 0,
+            downloaded: 0,
+            swarm: None,
+            key: rand::random(),
+            pex: PexSwarm::new(),
+            found_peers: Vec::new(),
+            metadata,
         })
     }
 
     pub fn get_info_hash(&self) -> Vec<u8> {
-        utils::hash_info(&self.metainfo.info.bencode()).to_vec()
+        self.metainfo.info_hash().to_vec()
     }
+
+    /// Info dictionary to send to the peers that ask for it with
+    /// `ut_metadata`. Returns [`None`] if its bytes don't match the
+    /// info hash.
+    pub fn metadata(&self) -> Option<Metadata> {
+        self.metadata.clone()
+    }
+
+    /// Peers of the torrent, the ones of the last announce followed by
+    /// the ones found through the peer exchange.
+    pub fn peers(&self) -> Vec<Peer> {
+        let mut peers = self
+            .response
+            .as_ref()
+            .map(|r| r.peers.clone())
+            .unwrap_or_default();
+        let found: Vec<Peer> = self
+            .found_peers
+            .iter()
+            .filter(|p| !peers.iter().any(|known| known.addr() == p.addr()))
+            .cloned()
+            .collect();
+        peers.extend(found);
+        peers
+    }
+
+    /// Records the peers found through the peer exchange, up to
+    /// [`MAX_FOUND_PEERS`]. Returns the ones that weren't known.
+    pub fn add_found_peers(&mut self, peers: Vec<Peer>) -> Vec<Peer> {
+        let mut known = self.peers();
+        let mut added = Vec::new();
+        for peer in peers {
+            if self.found_peers.len() >= MAX_FOUND_PEERS {
+                break;
+            }
+            if !known.iter().any(|p| p.addr() == peer.addr()) {
+                known.push(peer.clone());
+                self.found_peers.push(peer.clone());
+                added.push(peer);
+            }
+        }
+        added
+    }
+
+    /// Whether the peers of the torrent can only come from its
+    /// trackers, so the peer exchange is disabled.
+    pub fn is_private(&self) -> bool {
+        self.metainfo.info().private() == Some(true)
+    }
+
+    /// Magnet link of the torrent.
+    pub fn magnet(&self) -> Magnet {
+        Magnet::from_metainfo(&self.metainfo)
+    }
+
+    /// Returns the length of the piece, the last one may be shorter
+    /// than the rest.
+    pub fn piece_size(&self, index: usize) -> u64 {
+        let info = self.metainfo.info();
+        let piece_length = info.piece_length().max(0) as u64;
+        let start = piece_length * index as u64;
+        piece_length.min((info.length().max(0) as u64).saturating_sub(start))
+    }
+
+    /// Returns the amount of bytes of the pieces that are still
+    /// missing.
+    pub fn left(&self) -> u64 {
+        self.bitfield
+            .get_missing()
+            .into_iter()
+            .map(|i| self.piece_size(i))
+            .sum()
+    }
+
+    /// Returns the request of the next announce of the torrent, with
+    /// its event, statistics and key. The url of the tracker is left
+    /// empty, to be set for each tracker of the torrent.
+    pub fn tracker_request(&self, port: u16) -> TrackerRequest {
+        let event = self.scheduler.event();
+        let mut request = TrackerRequest::new(self.metainfo.info_hash(), String::new(), port);
+        if event == Some(TrackerRequestEvent::Stopped) {
+            request.set_numwant(Some(0));
+        }
+        request.set_event(event);
+        request.set_stats(self.uploaded, self.downloaded, self.left());
+        request.set_key(self.key);
+        request
+    }
+}
+
+/// Returns the number of pieces described by the info dictionary,
+/// regardless of it being in single or multiple file mode. The last
+/// one may be shorter than the rest. Returns zero if the piece length
+/// is invalid.
+fn pieces_ammount(info: &Info) -> usize {
+    if info.piece_length() <= 0 {
+        return 0;
+    }
+    (info.length().max(0) as usize).div_ceil(info.piece_length() as usize)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::peer::peer_source::PeerSource;
+    use crate::tracker::request::tracker_request_event::TrackerRequestEvent;
+
+    /// The list with the trackers of each tier sorted, as they are
+    /// shuffled when the file is read.
+    fn sorted_tiers(list: &AnnounceList) -> AnnounceList {
+        let mut tiers: Vec<Vec<String>> = Vec::new();
+        for tracker in list.trackers() {
+            tiers.resize(tiers.len().max(tracker.tier + 1), Vec::new());
+            tiers[tracker.tier].push(tracker.url.clone());
+        }
+        tiers.iter_mut().for_each(|t| t.sort());
+        AnnounceList::from_tiers(tiers)
+    }
+
     #[test]
     fn create_a_torrent_file() {
+        let before = Instant::now();
         let got = TorrentFile::new("debian-11.3.0-arm64-netinst.iso.torrent".to_string()).unwrap();
+        let after = Instant::now();
+        // The key is random, see `every_torrent_has_its_own_key`
+        let got = TorrentFile {
+            trackers: sorted_tiers(&got.trackers),
+            key: 0,
+            ..got
+        };
 
         let file = File::open("debian-11.3.0-arm64-netinst.iso.torrent".to_string()).unwrap();
         let metainfo = metainfo::read_torrent(&file).unwrap();
-        let Info(mode) = metainfo.info.clone();
-        let info = match mode {
-            crate::torrent::info::InfoMode::Empty => todo!(),
-            crate::torrent::info::InfoMode::SingleFile(it) => it,
+        let pieces = pieces_ammount(metainfo.info());
+        let metadata = Metadata::complete(metainfo.info_hash(), metainfo.info_bytes().to_vec());
+        assert!(metadata.is_some());
+        let tiers = match metainfo.announce_list {
+            Some(ref tiers) => tiers.clone(),
+            None => vec![vec![metainfo.announce.clone()]],
         };
+        // The first announce, with the started event, is due as soon
+        // as the file is read
+        let next_announce = got.scheduler.next_announce();
+        assert!(before <= next_announce && next_announce <= after);
+        assert_eq!(got.scheduler.event(), Some(TrackerRequestEvent::Started));
 
         let want = TorrentFile {
             file_name: "debian-11.3.0-arm64-netinst.iso.torrent".to_string(),
             metainfo,
-            bitfield: BitField::new(info.length as usize / info.piece_length as usize).unwrap(),
+            bitfield: BitField::new(pieces).unwrap(),
             response: None,
             count_connections: 0,
             peers_connected: Vec::new(),
-            pieces_ammount: info.length as usize / info.piece_length as usize,
+            pieces_ammount: pieces,
+            trackers: sorted_tiers(&AnnounceList::from_tiers(tiers)),
+            scheduler: AnnounceScheduler::new(next_announce),
+            uploaded: 0,
+            downloaded: 0,
+            swarm: None,
+            key: 0,
+            pex: PexSwarm::new(),
+            found_peers: Vec::new(),
+            metadata,
         };
 
         assert_eq!(got, want);
     }
+
+    #[test]
+    fn every_torrent_has_its_own_key() {
+        let torrent = b"d8:announce17:http://a/announce4:infod6:lengthi16384e4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = metainfo::read_torrent(&torrent[..]).unwrap();
+
+        let first = TorrentFile::from_metainfo("a.torrent".to_string(), metainfo.clone()).unwrap();
+        let second = TorrentFile::from_metainfo("a.torrent".to_string(), metainfo).unwrap();
+        assert_ne!(first.key, second.key);
+
+        // Every announce of the torrent, retries included, carries its key
+        let key = |request: TrackerRequest| {
+            let querystring = request.generate_querystring().unwrap().0;
+            querystring
+                .split('&')
+                .find(|param| param.starts_with("key="))
+                .map(str::to_string)
+        };
+        let started = key(first.tracker_request(6881));
+        let mut retried = first.clone();
+        retried.scheduler.failed(Instant::now());
+        assert_eq!(started, Some(format!("key={:08x}", first.key)));
+        assert_eq!(key(retried.tracker_request(6881)), started);
+        assert_ne!(key(second.tracker_request(6881)), started);
+    }
+
+    #[test]
+    fn found_peers_are_kept_along_with_the_announced_ones() {
+        let torrent = b"d8:announce17:http://a/announce4:infod6:lengthi16384e4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = metainfo::read_torrent(&torrent[..]).unwrap();
+        let mut torrent = TorrentFile::from_metainfo("a.torrent".to_string(), metainfo).unwrap();
+        let peer = |port: u16| Peer {
+            peer_id: None,
+            ip: Some("10.0.0.1".parse().unwrap()),
+            port,
+            source: PeerSource::Pex,
+        };
+
+        assert_eq!(
+            torrent.add_found_peers(vec![peer(1), peer(1)]),
+            vec![peer(1)]
+        );
+        let added = torrent.add_found_peers((1..=MAX_FOUND_PEERS as u16 * 2).map(peer).collect());
+        assert_eq!(added.len(), MAX_FOUND_PEERS - 1);
+        assert_eq!(added[0], peer(2));
+
+        // The torrent the download restarts with has them too
+        let restarted = torrent.clone();
+        assert_eq!(restarted.peers().len(), MAX_FOUND_PEERS);
+        assert_eq!(restarted.peers()[0], peer(1));
+    }
+
+    #[test]
+    fn last_piece_is_counted_when_it_is_shorter() {
+        let torrent = b"d8:announce17:http://a/announce4:infod6:lengthi40000e4:name1:a12:piece lengthi16384e6:pieces60:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = metainfo::read_torrent(&torrent[..]).unwrap();
+        let mut torrent = TorrentFile::from_metainfo("a.torrent".to_string(), metainfo).unwrap();
+
+        assert_eq!(torrent.pieces_ammount, 3);
+        assert_eq!(torrent.piece_size(2), 40000 - 2 * 16384);
+        assert_eq!(torrent.left(), 40000);
+
+        torrent.bitfield.set_piece(0);
+        torrent.bitfield.set_piece(1);
+        assert_eq!(torrent.left(), 40000 - 2 * 16384);
+        torrent.bitfield.set_piece(2);
+        assert_eq!(torrent.left(), 0);
+    }
+
+    #[test]
+    fn metadata_is_the_info_dictionary_as_it_was_read() {
+        // The keys of the info dictionary aren't sorted
+        let info =
+            b"d4:name1:a6:lengthi16384e12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+        let mut torrent = b"d8:announce17:http://a/announce4:info".to_vec();
+        torrent.extend_from_slice(info);
+        torrent.push(b'e');
+        let metainfo = metainfo::read_torrent(&torrent[..]).unwrap();
+
+        let torrent = TorrentFile::from_metainfo("a.torrent".to_string(), metainfo).unwrap();
+
+        let metadata = torrent.metadata().unwrap();
+        assert_eq!(metadata.info_hash(), torrent.metainfo.info_hash());
+        assert_eq!(metadata.info(), Some(&info[..]));
+    }
 }
@@ -1,10 +1,12 @@
 use log::{error, info};
-use std::fs::File;
-use std::io::{self, Read, Seek, Write};
-use std::path::Path;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::{mpsc, Arc, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 use crate::client::bitfield::BitField;
 use crate::client::torrent_file::TorrentFile;
@@ -12,14 +14,30 @@ use crate::download::bitfield_download::{BitFieldDownload, Status};
 
 use crate::log::logger::LogHandle;
 use crate::peer::peer_handler::Peer;
+use crate::pwp::extension_registry::ExtensionRegistry;
 use crate::pwp::message::PWPMessage;
+use crate::pwp::pex_swarm::{FLAG_REACHABLE, FLAG_SEED};
 use crate::pwp::protocol::PWPStream;
+use crate::pwp::reserved::Capability;
+use crate::pwp::ut_metadata::UtMetadata;
+use crate::pwp::ut_pex::UtPex;
 use crate::storage::piece::Piece;
-use crate::utils;
 
 const BLOCK_SIZE: u32 = 16384; //2^14
-
-pub struct HandlerDownload;
+/// Most peers a torrent is connected to at the same time.
+const MAX_PEER_CONNECTIONS: usize = 30;
+/// Longest a peer can stay silent before the connection is dropped.
+/// Peers send a keep alive every two minutes.
+const READ_TIMEOUT: Duration = Duration::from_secs(3 * 60);
+
+/// Downloads the pieces of a torrent. The peers of the first announce
+/// are used right away, and the ones returned by later announces can
+/// be added while the download is running. At most
+/// [`MAX_PEER_CONNECTIONS`] peers are connected at once, the rest wait
+/// for a connection to end.
+pub struct HandlerDownload {
+    peers: Sender<Vec<Peer>>,
+}
 
 pub enum HandlerMessage {
     Piece(usize),
@@ -45,9 +63,11 @@ impl HandlerDownload {
         i: usize,
         directory: String,
         torrent: TorrentFile,
+        port: u16,
     ) -> Self {
         let (ui_sender, ui_receiver) = mpsc::channel();
-        let info = utils::get_info_from_torrentfile(torrent.metainfo.info.clone());
+        let (peers_sender, peers_receiver) = mpsc::channel::<Vec<Peer>>();
+        let info = torrent.metainfo.info().clone();
 
         listen_peers(
             Arc::new(Mutex::new(ui_receiver)),
@@ -63,51 +83,93 @@ impl HandlerDownload {
                 Ok(b) => b,
                 Err(_) => return,
             };
-            let data = match torrent.response.clone() {
-                Some(data) => data,
-                None => return,
-            };
+            let peers = torrent.peers();
             let handler_bitfield = Arc::new(Mutex::new(bit));
             let mut threads = Vec::<JoinHandle<()>>::new();
-            for p in data.peers {
-                let info_hash =
-                    utils::hash_info(&torrent.clone().metainfo.info.clone().bencode()).to_vec();
+            let (ended_sender, ended_receiver) = mpsc::channel();
+            let mut connections = 0;
+            let mut known = HashSet::new();
+            // Runs until the sender of new peers is dropped
+            let peers = peers
+                .into_iter()
+                .chain(peers_receiver.into_iter().flatten())
+                .filter(|p| known.insert((p.ip, p.port)));
+            for p in peers {
+                if connections == MAX_PEER_CONNECTIONS {
+                    if ended_receiver.recv().is_err() {
+                        break;
+                    }
+                    connections -= 1;
+                }
+                connections += 1;
+                let slot = ConnectionSlot(ended_sender.clone());
+                let info_hash = torrent.get_info_hash();
 
                 let handler_bitfield = Arc::clone(&handler_bitfield);
 
-                let file_name = info.name.clone();
-                let pieces = info.pieces.clone();
+                let file_name = info.name();
+                let pieces = info.pieces().to_vec();
 
                 let ui_sender = ui_sender.clone();
                 let directory = directory.clone();
                 let log_handle = logger.clone();
                 let torrent = torrent.clone();
                 let thread = thread::spawn(move || {
-                    let mut stream =
-                        match stream_peers(p, info_hash, log_handle.clone(), ui_sender.clone()) {
-                            Some(stream) => stream,
-                            None => return,
-                        };
+                    let _slot = slot;
+                    let mut extensions = ExtensionRegistry::new(port);
+                    if let Some(metadata) = torrent.metadata() {
+                        extensions
+                            .register(Box::new(UtMetadata::new(Arc::new(Mutex::new(metadata)))));
+                    }
+                    let addr = p.addr();
+                    if let Some(addr) = addr.filter(|_| !torrent.is_private()) {
+                        extensions.register(Box::new(UtPex::new(torrent.pex.clone(), addr)));
+                    }
+                    let mut stream = match stream_peers(
+                        p,
+                        info_hash,
+                        extensions,
+                        log_handle.clone(),
+                        ui_sender.clone(),
+                    ) {
+                        Some(stream) => stream,
+                        None => return,
+                    };
+                    // The peer leaves the swarm when the thread ends
+                    let member = addr.map(|addr| torrent.pex.join(addr, FLAG_REACHABLE));
                     let mut peer_bitfield = match BitField::new(torrent.pieces_ammount as usize) {
                         Ok(bit) => bit,
                         Err(_) => return,
                     };
-                    (stream, peer_bitfield) =
-                        match stream_unchoke(stream, peer_bitfield, torrent.clone()) {
-                            Some(stream) => stream,
-                            None => return,
-                        };
+                    let fast_piece;
+                    (stream, peer_bitfield, fast_piece) = match stream_unchoke(
+                        stream,
+                        peer_bitfield,
+                        torrent.clone(),
+                        &handler_bitfield,
+                    ) {
+                        Some(stream) => stream,
+                        None => return,
+                    };
+                    // The other peers are told which ones are seeders
+                    if let Some(member) = &member {
+                        if peer_bitfield.has_all_pieces() {
+                            member.set_flags(FLAG_REACHABLE | FLAG_SEED);
+                        }
+                    }
 
                     let mut handle_miss = Vec::new();
                     if let Ok(bit) = handler_bitfield.lock() {
                         handle_miss = bit.get_missing()
                     }
-                    let piece_index = match piece_miss(handle_miss, peer_bitfield.get_available()) {
+                    let piece_index = match fast_piece
+                        .or_else(|| piece_miss(handle_miss, peer_bitfield.get_available()))
+                    {
                         Some(index) => index,
                         None => return,
                     };
                     let piece = Piece::new(
-                        info.piece_length as i64,
+                        torrent.piece_size(piece_index) as i64,
                         piece_index as i64,
                         pieces[piece_index as usize * 20..piece_index as usize * 20 + 20].to_vec(),
                         file_name.clone(),
@@ -135,7 +197,24 @@ impl HandlerDownload {
             }
         });
 
-        Self
+        Self {
+            peers: peers_sender,
+        }
+    }
+
+    /// Connects to the peers that weren't tried yet.
+    pub fn add_peers(&self, peers: Vec<Peer>) {
+        let _ = self.peers.send(peers);
+    }
+}
+
+/// A connection of the download, which tells it that the connection
+/// ended when dropped, however its thread returns.
+struct ConnectionSlot(Sender<()>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let _ = self.0.send(());
     }
 }
 
@@ -150,16 +229,21 @@ fn piece_miss(handle_miss: Vec<usize>, peer_miss: Vec<usize>) -> Option<usize> {
     None
 }
 
+/// Waits for the peer to unchoke the client, keeping track of the
+/// pieces it has. With the fast extension it returns early if one of
+/// the allowed fast pieces can be downloaded while choked, along with
+/// that piece.
 fn stream_unchoke(
     mut stream: PWPStream,
     mut peer_bitfield: BitField,
     torrent: TorrentFile,
-) -> Option<(PWPStream, BitField)> {
-    let mut unchoke = false;
-    while !unchoke {
+    bitfield: &Arc<Mutex<BitFieldDownload>>,
+) -> Option<(PWPStream, BitField, Option<usize>)> {
+    let mut allowed_fast = Vec::new();
+    loop {
         match stream.read() {
             Ok(it) => match it {
-                PWPMessage::Unchoke => unchoke = true,
+                PWPMessage::Unchoke => return Some((stream, peer_bitfield, None)),
                 PWPMessage::Have(piece) => {
                     peer_bitfield.set_piece(piece as usize);
                     match stream.send(PWPMessage::Interested) {
@@ -175,27 +259,73 @@ fn stream_unchoke(
                         Err(_) => return None,
                     }
                 }
+                PWPMessage::HaveAll => {
+                    for piece in 0..torrent.pieces_ammount {
+                        peer_bitfield.set_piece(piece);
+                    }
+                    match stream.send(PWPMessage::Interested) {
+                        Ok(_) => (),
+                        Err(_) => return None,
+                    }
+                }
+                PWPMessage::AllowedFast(piece) if piece < torrent.pieces_ammount as u32 => {
+                    allowed_fast.push(piece as usize)
+                }
+                PWPMessage::HaveNone
+                | PWPMessage::Suggest(_)
+                | PWPMessage::Reject(..)
+                | PWPMessage::AllowedFast(_) => (),
                 _ => break,
             },
             Err(_) => return None,
         }
+
+        let missing = match bitfield.lock() {
+            Ok(bit) => bit.get_missing(),
+            Err(_) => return None,
+        };
+        let fast_piece = allowed_fast
+            .iter()
+            .copied()
+            .find(|piece| peer_bitfield.has_piece(*piece) && missing.contains(piece));
+        if fast_piece.is_some() {
+            return Some((stream, peer_bitfield, fast_piece));
+        }
+    }
+    Some((stream, peer_bitfield, None))
+}
+
+/// Reads until the answer to the request of the block at `begin` of
+/// the piece `index`. Returns `None` if the peer rejects it, or if it
+/// chokes the client without the fast extension, as the requests are
+/// then dropped. Any other message is skipped.
+fn read_block(stream: &mut PWPStream, index: u32, begin: u32) -> Option<Vec<u8>> {
+    let fast = stream.peer_supports(Capability::Fast);
+    loop {
+        match stream.read().ok()? {
+            PWPMessage::Piece(i, b, block) if i == index && b == begin => return Some(block),
+            PWPMessage::Reject(i, b, _) if i == index && b == begin => return None,
+            PWPMessage::Choke if !fast => return None,
+            _ => continue,
+        }
     }
-    Some((stream, peer_bitfield))
 }
 
 fn stream_peers(
     p: Peer,
     info_hash: Vec<u8>,
+    extensions: ExtensionRegistry,
     mut log_handle: LogHandle,
     ui_sender: Sender<HandlerMessage>,
 ) -> Option<PWPStream> {
-    match connect_to_useful_peer(p.clone(), info_hash) {
+    match connect_to_useful_peer(p.clone(), info_hash, extensions) {
         Some(it) => {
-            let ip =
-                p.ip.map(|ip| ip.to_string())
-                    .unwrap_or_else(|| "-".to_string());
-            info!("Connected to peer: {}", ip);
-            log_handle.info(&format!("Connected to peer: {}", ip));
+            let ip = p
+                .addr()
+                .map(|addr| addr.to_string())
+                .unwrap_or_else(|| "-".to_string());
+            info!("Connected to peer: {} ({})", ip, p.source);
+            log_handle.info(&format!("Connected to peer: {} ({})", ip, p.source));
 
             match ui_sender.send(HandlerMessage::PeerConnected(p)) {
                 Ok(_) => (),
@@ -204,11 +334,12 @@ fn stream_peers(
             Some(it)
         }
         None => {
-            let ip =
-                p.ip.map(|ip| ip.to_string())
-                    .unwrap_or_else(|| "-".to_string());
-            info!("Couldn't connect to peer: {}", ip);
-            log_handle.info(&format!("Couldn't connect to peer: {}", ip));
+            let ip = p
+                .addr()
+                .map(|addr| addr.to_string())
+                .unwrap_or_else(|| "-".to_string());
+            info!("Couldn't connect to peer: {} ({})", ip, p.source);
+            log_handle.info(&format!("Couldn't connect to peer: {} ({})", ip, p.source));
             None
         }
     }
@@ -222,7 +353,8 @@ fn listen_peers(
     torrent: TorrentFile,
 ) -> Option<JoinHandle<()>> {
     let mut log_handle = logger;
-    let info = utils::get_info_from_torrentfile(torrent.metainfo.info);
+    let info = torrent.metainfo.info().clone();
+    let name = info.name();
     Some(thread::spawn(move || loop {
         if let Ok(receiver) = shared_ui_rx.lock() {
             match receiver.try_recv() {
@@ -230,20 +362,21 @@ fn listen_peers(
                     match it {
                         HandlerMessage::Piece(index) => {
                             if let Ok(mut torrents) = torrents.lock() {
-                                (*torrents)[i].bitfield.set_piece(index);
+                                let torrent = &mut (*torrents)[i];
+                                torrent.bitfield.set_piece(index);
+                                torrent.downloaded += torrent.piece_size(index);
                             }
                         }
                         HandlerMessage::HaveAllPieces => {
                             store_file(
-                                info.name.clone(),
-                                info.piece_length as u64 * torrent.pieces_ammount as u64,
+                                name.clone(),
+                                info.files(),
                                 torrent.pieces_ammount as i32,
-                                info.piece_length as u64,
                                 &directory,
                                 log_handle.clone(),
                             );
-                            info!("Torrent {} downloaded!", info.name);
-                            log_handle.info(&format!("Torrent {} downloaded", info.name));
+                            info!("Torrent {} downloaded!", name);
+                            log_handle.info(&format!("Torrent {} downloaded", name));
                         }
                         HandlerMessage::PeerConnected(p) => {
                             if let Ok(mut torrents) = torrents.lock() {
@@ -258,23 +391,20 @@ fn listen_peers(
         }
     }))
 }
+/// Joins the downloaded pieces into the files described by the
+/// torrent. The pieces are treated as a single stream of bytes that
+/// is split among the files in order, so a piece may end up in more
+/// than one file. Missing directories, and the empty files at the
+/// end, are created.
 pub fn store_file(
     file_name: String,
-    file_length: u64,
+    files: Vec<(PathBuf, i64)>,
     pieces: i32,
-    piece_length: u64,
     directory: &str,
     mut logger: LogHandle,
 ) {
-    let mut file = match File::create(Path::new(&format!("{}{}", directory, file_name))) {
-        Ok(file) => file,
-        Err(_) => return,
-    };
-
-    match file.set_len(file_length) {
-        Ok(_) => (),
-        Err(_) => return,
-    };
+    let mut files = files.into_iter();
+    let mut current: Option<(File, u64)> = None;
 
     for i in 0..pieces {
         let mut piece = match File::open(Path::new(&format!(
@@ -295,18 +425,47 @@ pub fn store_file(
             Ok(_) => (),
             Err(_) => return,
         };
-        let offset = i as u64 * piece_length as u64;
-        match file.seek(io::SeekFrom::Start(offset)) {
-            Ok(_) => (),
-            Err(_) => return,
-        };
-        match file.write_all(&buf) {
-            Ok(_) => (),
-            Err(_) => return,
-        };
+
+        let mut data = &buf[..];
+        while !data.is_empty() {
+            let (mut file, left) = match current.take() {
+                Some((file, left)) if left > 0 => (file, left),
+                _ => match files.next().and_then(|f| create_file(directory, f)) {
+                    Some(it) => it,
+                    None => return, // More data than files
+                },
+            };
+            let n = std::cmp::min(left, data.len() as u64) as usize;
+            match file.write_all(&data[..n]) {
+                Ok(_) => (),
+                Err(_) => return,
+            };
+            data = &data[n..];
+            current = Some((file, left - n as u64));
+        }
+    }
+    // Empty files after the last byte of data
+    for file in files {
+        if create_file(directory, file).is_none() {
+            return;
+        }
     }
 }
 
+/// Creates one of the files of the torrent, and its parent
+/// directories, with its final length. Returns the file along with
+/// the number of bytes that still have to be written.
+fn create_file(directory: &str, file: (PathBuf, i64)) -> Option<(File, u64)> {
+    let (path, length) = file;
+    let path = Path::new(directory).join(path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok()?;
+    }
+    let file = File::create(path).ok()?;
+    file.set_len(length as u64).ok()?;
+    Some((file, length as u64))
+}
+
 fn download_piece(
     mut stream: PWPStream,
     mut piece: Piece,
@@ -318,31 +477,21 @@ fn download_piece(
 ) {
     let bitfield = bitfields.0;
     let peer_bitfield = bitfields.1;
-    let info = utils::get_info_from_torrentfile(torrent.metainfo.info.clone());
-    let blocks_ammount = ((info.piece_length as f64) / (BLOCK_SIZE as f64)).ceil() as u32;
+    let blocks_ammount = ((piece.length as f64) / (BLOCK_SIZE as f64)).ceil() as u32;
 
     for i in 0..blocks_ammount - 1 {
         match stream.send(PWPMessage::Request(piece.index as u32, i * 16384, 16384)) {
             Ok(_) => (),
             Err(_) => return,
         };
-        match stream.read() {
-            Ok(msg) => match msg {
-                PWPMessage::Piece(_, _, block) => {
-                    match piece.store(i, block) {
-                        Ok(_) => (),
-                        Err(_) => return,
-                    };
-                }
-                _ => {
-                    if let Ok(mut bit) = bitfield.lock() {
-                        bit.set_piece(piece.index as usize, Status::NotDownload);
-                    }
-                    return;
-                }
-            },
-
-            Err(_) => {
+        match read_block(&mut stream, piece.index as u32, i * 16384) {
+            Some(block) => {
+                match piece.store(i, block) {
+                    Ok(_) => (),
+                    Err(_) => return,
+                };
+            }
+            None => {
                 if let Ok(mut bit) = bitfield.lock() {
                     bit.set_piece(piece.index as usize, Status::NotDownload);
                 }
@@ -364,23 +513,25 @@ fn download_piece(
         (blocks_ammount - 1) * 16384,
         lenght,
     ));
-    if let Ok(msg) = stream.read() {
-        match msg {
-            PWPMessage::Piece(_, _, data) => {
-                next_piece(
-                    stream,
-                    (piece, data),
-                    sender,
-                    directory,
-                    logger,
-                    torrent,
-                    (bitfield, peer_bitfield),
-                );
-            }
-            _ => {
-                if let Ok(mut bit) = bitfield.lock() {
-                    bit.set_piece(piece.index as usize, Status::NotDownload);
-                }
+    match read_block(
+        &mut stream,
+        piece.index as u32,
+        (blocks_ammount - 1) * 16384,
+    ) {
+        Some(data) => {
+            next_piece(
+                stream,
+                (piece, data),
+                sender,
+                directory,
+                logger,
+                torrent,
+                (bitfield, peer_bitfield),
+            );
+        }
+        None => {
+            if let Ok(mut bit) = bitfield.lock() {
+                bit.set_piece(piece.index as usize, Status::NotDownload);
             }
         }
     }
@@ -400,9 +551,9 @@ fn next_piece(
     let bitfield = bitfields.0;
     let peer_bitfield = bitfields.1;
 
-    let info = utils::get_info_from_torrentfile(torrent.metainfo.info.clone());
+    let info = torrent.metainfo.info().clone();
 
-    let blocks_ammount = ((info.piece_length as f64) / (BLOCK_SIZE as f64)).ceil() as u32;
+    let blocks_ammount = ((piece.length as f64) / (BLOCK_SIZE as f64)).ceil() as u32;
 
     match piece.store(blocks_ammount - 1, data) {
         Ok(_) => (),
@@ -412,18 +563,18 @@ fn next_piece(
         Ok(_) => (),
         Err(_) => return,
     };
-    if let Ok(peer) = stream.0.peer_addr() {
+    if let Ok(peer) = stream.peer_addr() {
         info!(
             "Downloaded piece {} from peer {} for {}",
             piece.index,
             peer.ip(),
-            info.name
+            info.name()
         );
         logger.info(&format!(
             "Downloaded piece {} from peer {} for {} ",
             piece.index,
             peer.ip(),
-            info.name,
+            info.name(),
         ));
     }
 
@@ -454,10 +605,10 @@ fn next_piece(
     }
 
     let piece = Piece::new(
-        piece.length,
+        torrent.piece_size(piece_index) as i64,
         piece_index as i64,
-        info.pieces[piece_index as usize * 20..piece_index as usize * 20 + 20].to_vec(),
-        info.name.clone(),
+        info.pieces()[piece_index as usize * 20..piece_index as usize * 20 + 20].to_vec(),
+        info.name(),
         directory.to_string(),
     );
     if let Ok(mut bit) = bitfield.lock() {
@@ -483,22 +634,99 @@ fn next_piece(
 //     }
 // }
 
-pub fn connect_to_useful_peer(peer: Peer, hash: Vec<u8>) -> Option<PWPStream> {
-    let mut stream = match PWPStream::connect(&peer, hash.clone()) {
+/// Connects to the peer and exchanges the handshakes, checking that
+/// it shares the torrent. `extensions` are the ones offered to the
+/// peer if it supports the extension protocol. Reads time out after
+/// [`READ_TIMEOUT`], so idle peers don't keep their connection slot.
+pub fn connect_to_useful_peer(
+    peer: Peer,
+    hash: Vec<u8>,
+    extensions: ExtensionRegistry,
+) -> Option<PWPStream> {
+    let mut stream = match PWPStream::connect(&peer, hash.clone(), extensions) {
         Ok(it) => it,
         Err(_) => return None,
     };
+    stream.set_read_timeout(Some(READ_TIMEOUT)).ok()?;
     let handshake_msg = match stream.read_handshake() {
         Ok(it) => match it {
-            PWPMessage::Handshake(info_hash, peer_id) => PWPMessage::Handshake(info_hash, peer_id),
+            PWPMessage::Handshake(reserved, info_hash, peer_id) => {
+                PWPMessage::Handshake(reserved, info_hash, peer_id)
+            }
             _ => return None,
         },
         Err(_) => return None,
     };
-    if let PWPMessage::Handshake(has_info, _) = handshake_msg {
+    if let PWPMessage::Handshake(_, has_info, _) = handshake_msg {
         if has_info != hash {
             return None;
         }
     }
+    stream.send_extended_handshake().ok()?;
     Some(stream)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::logger::Logger;
+    use std::env;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("download_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn read_block_skips_the_messages_sent_before_the_piece() {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let peer = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut stream = PWPStream::new(listener.accept().unwrap().0, ExtensionRegistry::new(0));
+        let mut peer = PWPStream::new(peer, ExtensionRegistry::new(0));
+
+        for msg in [
+            PWPMessage::Interested,
+            PWPMessage::HaveAll,
+            PWPMessage::Port(6881),
+            PWPMessage::Request(0, 0, 4),
+            PWPMessage::Piece(0, 4, b"efgh".to_vec()),
+            PWPMessage::Piece(0, 0, b"abcd".to_vec()),
+            PWPMessage::Reject(0, 0, 4),
+        ] {
+            peer.send(msg).unwrap();
+        }
+
+        assert_eq!(read_block(&mut stream, 0, 0), Some(b"abcd".to_vec()));
+        assert_eq!(read_block(&mut stream, 0, 0), None);
+    }
+
+    #[test]
+    fn store_the_empty_files_after_the_data() {
+        let dir = temp_dir("empty_files");
+        let directory = format!("{}/", dir.display());
+        fs::write(dir.join("piece0-t"), b"abcd").unwrap();
+        fs::write(dir.join("piece1-t"), b"ef").unwrap();
+        let logger = Logger::new(std::io::sink());
+
+        store_file(
+            "t".to_string(),
+            vec![
+                (PathBuf::from("t/a"), 3),
+                (PathBuf::from("t/b"), 3),
+                (PathBuf::from("t/c"), 0),
+                (PathBuf::from("t/d/e"), 0),
+            ],
+            2,
+            &directory,
+            logger.new_handler(),
+        );
+
+        assert_eq!(fs::read(dir.join("t/a")).unwrap(), b"abc");
+        assert_eq!(fs::read(dir.join("t/b")).unwrap(), b"def");
+        assert_eq!(fs::read(dir.join("t/c")).unwrap(), b"");
+        assert_eq!(fs::read(dir.join("t/d/e")).unwrap(), b"");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
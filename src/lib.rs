@@ -4,6 +4,7 @@ pub mod config;
 pub mod download;
 pub mod log;
 pub mod peer;
+pub mod proxy;
 pub mod pwp;
 pub mod server;
 pub mod storage;
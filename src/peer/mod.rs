@@ -1,2 +1,4 @@
 pub mod peer_builder;
 pub mod peer_handler;
+pub mod peer_id;
+pub mod peer_source;
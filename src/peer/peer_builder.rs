@@ -1,9 +1,7 @@
-use std::{
-    net::{IpAddr, Ipv4Addr},
-    str::FromStr,
-};
+use std::{net::IpAddr, str::FromStr};
 
 use crate::peer::peer_handler::Peer;
+use crate::peer::peer_source::PeerSource;
 #[derive(Debug, PartialEq, Eq, Clone)]
 
 /// Represents a constructor of a Peer.
@@ -14,6 +12,8 @@ pub struct PeerBuilder {
     pub ip: Option<IpAddr>,
     /// peer's port number
     pub port: u16,
+    /// where the client learned about the peer
+    pub source: PeerSource,
 }
 
 impl Default for PeerBuilder {
@@ -28,6 +28,7 @@ impl PeerBuilder {
             peer_id: None,
             ip: None,
             port: 0,
+            source: PeerSource::Tracker,
         }
     }
 
@@ -37,21 +38,19 @@ impl PeerBuilder {
         self
     }
 
-    /// Sets the ip address of the peer.
+    /// Sets the ip address of the peer from its textual form, like
+    /// `192.168.0.1` or `2001:db8::1`. The ip is unknown if it can't
+    /// be parsed.
     pub fn ip(&'_ mut self, ip: Vec<u8>) -> &'_ mut Self {
-        if ip.len() == 4 {
-            self.ip = Some(IpAddr::V4(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3])));
-        } else {
-            let peer_ip = match String::from_utf8_lossy(&ip) {
-                std::borrow::Cow::Borrowed(it) => it.to_string(),
-                std::borrow::Cow::Owned(it) => it,
-            };
+        self.ip = std::str::from_utf8(&ip)
+            .ok()
+            .and_then(|ip| IpAddr::from_str(ip).ok());
+        self
+    }
 
-            self.ip = match IpAddr::from_str(&peer_ip) {
-                Ok(it) => Some(it),
-                Err(_) => None,
-            };
-        }
+    /// Sets the ip address of the peer.
+    pub fn ip_addr(&'_ mut self, ip: IpAddr) -> &'_ mut Self {
+        self.ip = Some(ip);
         self
     }
 
@@ -61,25 +60,32 @@ impl PeerBuilder {
         self
     }
 
+    /// Sets where the client learned about the peer.
+    pub fn source(&'_ mut self, source: PeerSource) -> &'_ mut Self {
+        self.source = source;
+        self
+    }
+
     /// Initialices the atributes of the peer.
     pub fn build(self) -> Peer {
         Peer {
             peer_id: self.peer_id,
             ip: self.ip,
             port: self.port,
+            source: self.source,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::net::Ipv4Addr;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     use super::*;
     #[test]
     fn create_a_peer_with_ip_and_port_and_id() {
         let mut builder = PeerBuilder::new();
-        builder.ip([127, 0, 0, 1].to_vec());
+        builder.ip(b"127.0.0.1".to_vec());
         builder.port(5419);
         builder.peer_id([
             0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8,
@@ -90,8 +96,31 @@ mod tests {
             peer_id: Some([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
             ip: Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
             port: 5419,
+            source: PeerSource::Tracker,
         };
 
         assert_eq!(got, want)
     }
+
+    #[test]
+    fn ip_is_always_read_as_text() {
+        let mut builder = PeerBuilder::new();
+
+        // As long as raw IPv6 octets
+        builder.ip(b"2001:db8:85a3::8".to_vec());
+        assert_eq!(
+            builder.ip,
+            Some(IpAddr::V6(Ipv6Addr::new(
+                0x2001, 0xdb8, 0x85a3, 0, 0, 0, 0, 8
+            )))
+        );
+        // As long as raw IPv4 octets
+        builder.ip(b"1::1".to_vec());
+        assert_eq!(
+            builder.ip,
+            Some(IpAddr::V6(Ipv6Addr::new(1, 0, 0, 0, 0, 0, 0, 1)))
+        );
+        builder.ip(vec![127, 0, 0, 1]);
+        assert_eq!(builder.ip, None);
+    }
 }
@@ -1,10 +1,15 @@
 use std::collections::HashMap;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 
 use crate::bencode::bencoded_value::BencodedValue;
 use crate::peer::peer_builder::PeerBuilder;
+use crate::peer::peer_source::PeerSource;
 
 static PEERS_REQUIRED_KEYS: [&[u8]; 2] = [b"ip", b"port"];
+/// Length of a peer in the compact format, with an IPv4 address.
+const COMPACT_PEER_LENGTH: usize = 6;
+/// Length of a peer in the compact format, with an IPv6 address.
+const COMPACT_PEER6_LENGTH: usize = 18;
 
 /// Contains the data of a peer. Only the ip and
 /// port fields are required.
@@ -16,32 +21,59 @@ pub struct Peer {
     pub ip: Option<IpAddr>,
     /// peer's port number
     pub port: u16,
+    /// where the client learned about the peer
+    pub source: PeerSource,
 }
 
 impl Peer {
-    /// Creates a new Peer structure from an ip and a port.
+    /// Creates a new Peer structure from the octets of an IPv4 or
+    /// IPv6 address and a port, as they are in the compact format. Its
+    /// peer id is unknown until it answers the handshake.
     /// Returns [`Some`] if no errors occur while building the
     /// instance; otherwise returns [`None`].
-    pub fn new_byte_string(ip: &[u8], port: &[u8], peer_id: [u8; 20]) -> Option<Self> {
+    pub fn new_byte_string(ip: &[u8], port: &[u8]) -> Option<Self> {
         let mut peer_build = PeerBuilder::new();
 
         //Port
-        peer_build.port(port[0] as u16 * 256 + port[1] as u16);
-
-        //Peer id
-        peer_build.peer_id(peer_id);
+        peer_build.port(u16::from_be_bytes(port.try_into().ok()?));
 
         //Ip
-        let ip = BencodedValue::ByteString(ip.to_vec()).byte_string()?;
-        peer_build.ip(ip);
+        let ip = match ip.len() {
+            4 => IpAddr::from(<[u8; 4]>::try_from(ip).ok()?),
+            16 => IpAddr::from(<[u8; 16]>::try_from(ip).ok()?),
+            _ => return None,
+        };
+        peer_build.ip_addr(ip);
 
         Some(peer_build.build())
     }
 
-    /// Creates a new Peer structure from a bencoded dictionary.
+    /// Creates the peers of a compact peer list (BEP 23), or of a
+    /// compact `peers6` list (BEP 7) if `ipv6` is true. Every peer is
+    /// its address followed by its port, in network byte order. Any
+    /// trailing bytes that don't make up a whole peer are ignored.
+    pub fn new_compact_list(peers: &[u8], ipv6: bool) -> Vec<Self> {
+        let length = if ipv6 {
+            COMPACT_PEER6_LENGTH
+        } else {
+            COMPACT_PEER_LENGTH
+        };
+        peers
+            .chunks_exact(length)
+            .filter_map(|p| Self::new_byte_string(&p[..length - 2], &p[length - 2..]))
+            .collect()
+    }
+
+    /// Returns the socket address of the peer, if its ip is known.
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.ip.map(|ip| SocketAddr::new(ip, self.port))
+    }
+
+    /// Creates a new Peer structure from a bencoded dictionary, with
+    /// the `peer id` of the dictionary if it has one.
     /// Returns [`Some`] if no errors occur while building the
     /// instance; otherwise returns [`None`].
-    pub fn new_dict(peers: Vec<(BencodedValue, BencodedValue)>, peer_id: [u8; 20]) -> Option<Self> {
+    pub fn new_dict(peers: Vec<(BencodedValue, BencodedValue)>) -> Option<Self> {
         let dict = peers
             .into_iter()
             .collect::<HashMap<BencodedValue, BencodedValue>>();
@@ -51,7 +83,6 @@ impl Peer {
         let has_required = required.all(|k| dict.contains_key(&k));
         if has_required {
             let mut peer_build = PeerBuilder::new();
-            peer_build.peer_id(peer_id);
             for (k, v) in dict {
                 if let BencodedValue::ByteString(s) = k {
                     build_peer_fields(&mut peer_build, &s[..], v)?;
@@ -94,27 +125,64 @@ fn build_peer_fields<'a>(
 
 #[cfg(test)]
 mod tests {
-    use std::net::Ipv4Addr;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     use super::*;
     #[test]
-    fn create_a_peer_with_ip_and_port_and_id() {
-        let peer_1 = Peer::new_byte_string(
-            &[127, 0, 0, 1],
-            &[21, 43],
-            [
-                0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8,
-                0u8, 0u8, 0u8, 0u8,
-            ],
-        )
-        .unwrap();
+    fn create_a_peer_with_ip_and_port() {
+        let peer_1 = Peer::new_byte_string(&[127, 0, 0, 1], &[21, 43]).unwrap();
 
         let peer_2 = Peer {
-            peer_id: Some([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            peer_id: None,
             ip: Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
             port: 5419,
+            source: PeerSource::Tracker,
         };
 
         assert_eq!(peer_1, peer_2)
     }
+
+    #[test]
+    fn create_peers_from_compact_lists() {
+        let peers =
+            Peer::new_compact_list(&[127, 0, 0, 1, 0x1a, 0xe1, 10, 0, 0, 2, 0, 80, 9], false);
+        let addrs: Vec<_> = peers.iter().flat_map(Peer::addr).collect();
+        assert_eq!(
+            addrs,
+            vec![
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 6881),
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)), 80),
+            ]
+        );
+
+        let mut peer6 = Ipv6Addr::LOCALHOST.octets().to_vec();
+        peer6.extend_from_slice(&[0x1a, 0xe1]);
+        let peers = Peer::new_compact_list(&peer6, true);
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].ip, Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+        assert_eq!(peers[0].port, 6881);
+        assert_eq!(peers[0].addr().unwrap().to_string(), "[::1]:6881");
+    }
+
+    #[test]
+    fn dictionary_peers_have_a_textual_ip() {
+        let peer = Peer::new_dict(vec![
+            (
+                BencodedValue::ByteString(b"ip".to_vec()),
+                BencodedValue::ByteString(b"2001:db8:85a3::8".to_vec()),
+            ),
+            (
+                BencodedValue::ByteString(b"port".to_vec()),
+                BencodedValue::Integer(6881),
+            ),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            peer.ip,
+            Some(IpAddr::V6(Ipv6Addr::new(
+                0x2001, 0xdb8, 0x85a3, 0, 0, 0, 0, 8
+            )))
+        );
+    }
 }
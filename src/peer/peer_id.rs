@@ -0,0 +1,77 @@
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use std::sync::OnceLock;
+
+/// Code of the client in the peer ids, following the Azureus-style
+/// `-XXvvvv-` prefix.
+const CLIENT_CODE: &str = "CG";
+/// Name of the client shown to the peers.
+const CLIENT_NAME: &str = "fRUSTrados";
+
+static SESSION_PEER_ID: OnceLock<[u8; 20]> = OnceLock::new();
+
+/// Returns the peer id of the client. It is generated the first time
+/// it is needed and then kept for the rest of the session, so the
+/// trackers and the peers see the same id.
+pub fn session_peer_id() -> [u8; 20] {
+    *SESSION_PEER_ID.get_or_init(|| {
+        generate(
+            env!("CARGO_PKG_VERSION_MAJOR"),
+            env!("CARGO_PKG_VERSION_MINOR"),
+            env!("CARGO_PKG_VERSION_PATCH"),
+        )
+    })
+}
+
+/// Name and version of the client, as sent in the extended
+/// handshake.
+pub fn client_version() -> String {
+    format!("{} {}", CLIENT_NAME, env!("CARGO_PKG_VERSION"))
+}
+
+/// Generates a peer id with the Azureus-style prefix of the given
+/// version followed by random alphanumeric characters.
+fn generate(major: &str, minor: &str, patch: &str) -> [u8; 20] {
+    let prefix = format!(
+        "-{}{}{}{}0-",
+        CLIENT_CODE,
+        version_char(major),
+        version_char(minor),
+        version_char(patch)
+    );
+    let mut peer_id = [0u8; 20];
+    peer_id[..8].copy_from_slice(prefix.as_bytes());
+    thread_rng()
+        .sample_iter(Alphanumeric)
+        .zip(peer_id[8..].iter_mut())
+        .for_each(|(c, b)| *b = c);
+    peer_id
+}
+
+/// Encodes a number of the version as a single character: `0`-`9`
+/// and then `A`-`Z`. Larger numbers are shown as `Z`.
+fn version_char(number: &str) -> char {
+    let number = number.parse::<u32>().unwrap_or_default().min(35);
+    std::char::from_digit(number, 36)
+        .unwrap_or('Z')
+        .to_ascii_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_id_has_the_client_prefix_and_version() {
+        let peer_id = generate("1", "12", "3");
+
+        assert_eq!(&peer_id[..8], b"-CG1C30-");
+        assert!(peer_id[8..].iter().all(u8::is_ascii_alphanumeric));
+    }
+
+    #[test]
+    fn session_peer_id_is_stable() {
+        assert_eq!(session_peer_id(), session_peer_id());
+        assert_eq!(&session_peer_id()[..3], b"-CG");
+    }
+}
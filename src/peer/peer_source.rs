@@ -0,0 +1,20 @@
+use std::fmt;
+
+/// Where the client learned about a peer.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum PeerSource {
+    /// Returned by a tracker in an announce.
+    #[default]
+    Tracker,
+    /// Sent by another peer through the peer exchange (BEP 11).
+    Pex,
+}
+
+impl fmt::Display for PeerSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerSource::Tracker => write!(f, "tracker"),
+            PeerSource::Pex => write!(f, "pex"),
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::proxy::proxy_config::ProxyConfig;
+use crate::proxy::proxy_error::ProxyError;
+use log::warn;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Proxy used by the outbound connections of the client.
+static PROXY: RwLock<Option<ProxyConfig>> = RwLock::new(None);
+
+/// Sets the proxy used from now on by [`connect_tracker`] and
+/// [`connect_peer`]. With [`None`] every connection is direct.
+pub fn set_proxy(proxy: Option<ProxyConfig>) {
+    match PROXY.write() {
+        Ok(mut p) => *p = proxy,
+        Err(poisoned) => *poisoned.into_inner() = proxy,
+    }
+}
+
+fn proxy() -> Option<ProxyConfig> {
+    match PROXY.read() {
+        Ok(p) => p.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+/// Opens a connection to a tracker, through the proxy if there is one.
+pub fn connect_tracker(host: &str, port: u16, timeout: Duration) -> Result<TcpStream, ProxyError> {
+    let proxy = proxy();
+    connect(proxy.as_ref(), true, host, port, timeout)
+}
+
+/// Opens a connection to a peer, through the proxy if it's configured
+/// for peers or if only connections through it are allowed.
+pub fn connect_peer(addr: SocketAddr, timeout: Duration) -> Result<TcpStream, ProxyError> {
+    let proxy = proxy();
+    let through_proxy = proxy.as_ref().is_some_and(|p| p.peers || p.only);
+    connect(
+        proxy.as_ref(),
+        through_proxy,
+        &addr.ip().to_string(),
+        addr.port(),
+        timeout,
+    )
+}
+
+/// Returns false if only connections through the proxy are allowed,
+/// as the proxies supported can't carry UDP.
+pub fn allows_udp() -> bool {
+    !proxy().is_some_and(|p| p.only)
+}
+
+/// Connects to `host` and `port` through `proxy` if `through_proxy`
+/// is true. If the proxy can't be reached the connection is direct,
+/// unless the proxy only allows connections through it.
+pub fn connect(
+    proxy: Option<&ProxyConfig>,
+    through_proxy: bool,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<TcpStream, ProxyError> {
+    let proxy = match proxy {
+        Some(proxy) if through_proxy => proxy,
+        Some(proxy) if proxy.only => return Err(ProxyError::ProxyOnly),
+        _ => return connect_direct(host, port, timeout),
+    };
+    match proxy.connect(host, port, timeout) {
+        Err(ProxyError::Unavailable) if !proxy.only => {
+            warn!(
+                "Proxy {}:{} unavailable, connecting directly to {}:{}",
+                proxy.host, proxy.port, host, port
+            );
+            connect_direct(host, port, timeout)
+        }
+        result => result,
+    }
+}
+
+/// Connects to the first address of `host` that answers.
+pub fn connect_direct(host: &str, port: u16, timeout: Duration) -> Result<TcpStream, ProxyError> {
+    (host, port)
+        .to_socket_addrs()
+        .map_err(|_| ProxyError::Host)?
+        .find_map(|addr| TcpStream::connect_timeout(&addr, timeout).ok())
+        .ok_or(ProxyError::Connection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::proxy_config::ProxyKind;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn proxy(kind: ProxyKind, port: u16, only: bool) -> ProxyConfig {
+        ProxyConfig {
+            kind,
+            host: "127.0.0.1".to_string(),
+            port,
+            credentials: Some(("user".to_string(), "pass".to_string())),
+            peers: true,
+            only,
+        }
+    }
+
+    /// Port where nothing is listening.
+    fn closed_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    /// Runs `stand_in` on the first connection to a local listener,
+    /// returning the port of the listener.
+    fn serve<F>(stand_in: F) -> (u16, thread::JoinHandle<()>)
+    where
+        F: FnOnce(TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || stand_in(listener.accept().unwrap().0));
+        (port, handle)
+    }
+
+    fn read_n(stream: &mut TcpStream, n: usize) -> Vec<u8> {
+        let mut buf = vec![0; n];
+        stream.read_exact(&mut buf).unwrap();
+        buf
+    }
+
+    fn assert_tunnel(mut stream: TcpStream) {
+        stream.write_all(b"ping").unwrap();
+        assert_eq!(read_n(&mut stream, 4), b"pong");
+    }
+
+    #[test]
+    fn socks5_with_authentication() {
+        let (port, handle) = serve(|mut s| {
+            assert_eq!(read_n(&mut s, 4), [5, 2, 0, 2]);
+            s.write_all(&[5, 2]).unwrap();
+            assert_eq!(read_n(&mut s, 11), b"\x01\x04user\x04pass");
+            s.write_all(&[1, 0]).unwrap();
+            let mut request = vec![5, 1, 0, 3, 15];
+            request.extend_from_slice(b"tracker.example");
+            request.extend_from_slice(&80u16.to_be_bytes());
+            assert_eq!(read_n(&mut s, request.len()), request);
+            s.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0x1a, 0xe1])
+                .unwrap();
+            assert_eq!(read_n(&mut s, 4), b"ping");
+            s.write_all(b"pong").unwrap();
+        });
+
+        let proxy = proxy(ProxyKind::Socks5, port, true);
+        let stream = connect(Some(&proxy), true, "tracker.example", 80, TIMEOUT).unwrap();
+        assert_tunnel(stream);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn socks5_refused_connection() {
+        let (port, handle) = serve(|mut s| {
+            assert_eq!(read_n(&mut s, 3), [5, 1, 0]);
+            s.write_all(&[5, 0]).unwrap();
+            assert_eq!(read_n(&mut s, 10)[..4], [5, 1, 0, 1]);
+            s.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+        });
+
+        let mut proxy = proxy(ProxyKind::Socks5, port, false);
+        proxy.credentials = None;
+        assert_eq!(
+            connect(Some(&proxy), true, "10.0.0.1", 6881, TIMEOUT).unwrap_err(),
+            ProxyError::Refused("connection refused".to_string())
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn http_connect_tunnel() {
+        let (port, handle) = serve(|s| {
+            let mut reader = BufReader::new(s);
+            let mut head = String::new();
+            while !head.ends_with("\r\n\r\n") {
+                reader.read_line(&mut head).unwrap();
+            }
+            assert!(head.starts_with("CONNECT [::1]:6881 HTTP/1.1\r\n"));
+            assert!(head.contains("Proxy-Authorization: Basic dXNlcjpwYXNz\r\n"));
+            let mut s = reader.into_inner();
+            s.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .unwrap();
+            assert_eq!(read_n(&mut s, 4), b"ping");
+            s.write_all(b"pong").unwrap();
+        });
+
+        let proxy = proxy(ProxyKind::Http, port, true);
+        let stream = connect(Some(&proxy), true, "::1", 6881, TIMEOUT).unwrap();
+        assert_tunnel(stream);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn http_connect_needs_authentication() {
+        let (port, handle) = serve(|mut s| {
+            let mut buf = [0; 1024];
+            let _ = s.read(&mut buf).unwrap();
+            s.write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                .unwrap();
+        });
+
+        let proxy = proxy(ProxyKind::Http, port, true);
+        assert_eq!(
+            connect(Some(&proxy), true, "127.0.0.1", 6881, TIMEOUT).unwrap_err(),
+            ProxyError::Auth
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn unavailable_proxy_falls_back_unless_proxy_only() {
+        let (target, handle) = serve(|mut s| {
+            assert_eq!(read_n(&mut s, 4), b"ping");
+            s.write_all(b"pong").unwrap();
+        });
+
+        let only = proxy(ProxyKind::Socks5, closed_port(), true);
+        assert_eq!(
+            connect(Some(&only), true, "127.0.0.1", target, TIMEOUT).unwrap_err(),
+            ProxyError::Unavailable
+        );
+        assert_eq!(
+            connect(Some(&only), false, "127.0.0.1", target, TIMEOUT).unwrap_err(),
+            ProxyError::ProxyOnly
+        );
+
+        let fallback = proxy(ProxyKind::Socks5, closed_port(), false);
+        let stream = connect(Some(&fallback), true, "127.0.0.1", target, TIMEOUT).unwrap();
+        assert_tunnel(stream);
+        handle.join().unwrap();
+    }
+}
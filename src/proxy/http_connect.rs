@@ -0,0 +1,64 @@
+use crate::proxy::proxy_error::ProxyError;
+use crate::utils::to_base64;
+use std::io::{Read, Write};
+use std::net::IpAddr;
+
+/// Longest head accepted in the answer of the proxy.
+const MAX_HEAD_LENGTH: usize = 8 * 1024;
+
+/// Asks an HTTP proxy to open a tunnel to `host` and `port` with the
+/// `CONNECT` method, authenticating with the credentials if there are
+/// any. Once it returns the stream is connected to the destination.
+pub fn handshake<S: Read + Write>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: Option<&(String, String)>,
+) -> Result<(), ProxyError> {
+    let authority = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+        _ => format!("{}:{}", host, port),
+    };
+    let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+    if let Some((user, password)) = credentials {
+        let token = to_base64(format!("{}:{}", user, password).as_bytes());
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|_| ProxyError::Unavailable)?;
+
+    let head = read_head(stream)?;
+    let status_line = head.lines().next().unwrap_or_default();
+    let mut parts = status_line.splitn(3, ' ');
+    if !parts.next().unwrap_or_default().starts_with("HTTP/1.") {
+        return Err(ProxyError::InvalidResponse);
+    }
+    let status: u16 = parts
+        .next()
+        .and_then(|s| s.parse().ok())
+        .ok_or(ProxyError::InvalidResponse)?;
+    match status {
+        200..=299 => Ok(()),
+        407 => Err(ProxyError::Auth),
+        _ => Err(ProxyError::Refused(status_line.to_string())),
+    }
+}
+
+/// Reads the status line and the headers, one byte at a time so
+/// nothing sent through the tunnel is consumed.
+fn read_head<S: Read>(stream: &mut S) -> Result<String, ProxyError> {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") && !head.ends_with(b"\n\n") {
+        if head.len() == MAX_HEAD_LENGTH {
+            return Err(ProxyError::InvalidResponse);
+        }
+        stream
+            .read_exact(&mut byte)
+            .map_err(|_| ProxyError::InvalidResponse)?;
+        head.push(byte[0]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
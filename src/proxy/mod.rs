@@ -0,0 +1,5 @@
+pub mod connector;
+mod http_connect;
+pub mod proxy_config;
+pub mod proxy_error;
+mod socks5;
@@ -0,0 +1,45 @@
+use std::fmt;
+
+/// Represents the possible errors that can occur while opening a
+/// connection, directly or through a proxy.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProxyError {
+    /// The address couldn't be resolved
+    Host,
+    /// The direct connection couldn't be established
+    Connection,
+    /// The proxy couldn't be reached
+    Unavailable,
+    /// The proxy rejected the credentials, or needs some that weren't
+    /// configured
+    Auth,
+    /// The proxy couldn't connect to the destination
+    Refused(String),
+    /// The proxy sent a malformed answer
+    InvalidResponse,
+    /// The connection would have to skip the proxy, and only
+    /// connections through it are allowed
+    ProxyOnly,
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::Host => write!(f, "Couldn't resolve the address"),
+            ProxyError::Connection => write!(f, "Couldn't establish the connection"),
+            ProxyError::Unavailable => write!(f, "Couldn't connect to the proxy"),
+            ProxyError::Auth => write!(f, "The proxy rejected the authentication"),
+            ProxyError::Refused(reason) => {
+                write!(
+                    f,
+                    "The proxy couldn't connect to the destination: {}",
+                    reason
+                )
+            }
+            ProxyError::InvalidResponse => write!(f, "The answer of the proxy is invalid"),
+            ProxyError::ProxyOnly => {
+                write!(f, "Only connections through the proxy are allowed")
+            }
+        }
+    }
+}
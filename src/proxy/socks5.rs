@@ -0,0 +1,136 @@
+use crate::proxy::proxy_error::ProxyError;
+use std::io::{Read, Write};
+use std::net::IpAddr;
+
+const VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const USER_PASSWORD: u8 = 2;
+const NO_ACCEPTABLE_METHOD: u8 = 0xff;
+const USER_PASSWORD_VERSION: u8 = 1;
+const CMD_CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+
+/// Asks a SOCKS5 proxy to connect to `host` and `port`, authenticating
+/// with the credentials if there are any. Once it returns the stream
+/// is connected to the destination.
+pub fn handshake<S: Read + Write>(
+    stream: &mut S,
+    host: &str,
+    port: u16,
+    credentials: Option<&(String, String)>,
+) -> Result<(), ProxyError> {
+    let methods: &[u8] = match credentials {
+        Some(_) => &[NO_AUTH, USER_PASSWORD],
+        None => &[NO_AUTH],
+    };
+    let mut greeting = vec![VERSION, methods.len() as u8];
+    greeting.extend_from_slice(methods);
+    write(stream, &greeting)?;
+
+    let [version, method] = read::<_, 2>(stream)?;
+    if version != VERSION {
+        return Err(ProxyError::InvalidResponse);
+    }
+    match (method, credentials) {
+        (NO_AUTH, _) => (),
+        (USER_PASSWORD, Some((user, password))) => authenticate(stream, user, password)?,
+        (NO_ACCEPTABLE_METHOD, _) | (USER_PASSWORD, None) => return Err(ProxyError::Auth),
+        _ => return Err(ProxyError::InvalidResponse),
+    }
+
+    write(stream, &connect_request(host, port)?)?;
+    let [version, reply, _, atyp] = read::<_, 4>(stream)?;
+    if version != VERSION {
+        return Err(ProxyError::InvalidResponse);
+    }
+    if reply != 0 {
+        return Err(ProxyError::Refused(reply_message(reply)));
+    }
+    // The address the proxy bound, which isn't needed
+    let length = match atyp {
+        ATYP_IPV4 => 4,
+        ATYP_IPV6 => 16,
+        ATYP_DOMAIN => read::<_, 1>(stream)?[0] as usize,
+        _ => return Err(ProxyError::InvalidResponse),
+    };
+    let mut bound = vec![0; length + 2];
+    stream
+        .read_exact(&mut bound)
+        .map_err(|_| ProxyError::InvalidResponse)
+}
+
+/// Username and password authentication, RFC 1929.
+fn authenticate<S: Read + Write>(
+    stream: &mut S,
+    user: &str,
+    password: &str,
+) -> Result<(), ProxyError> {
+    if user.len() > u8::MAX as usize || password.len() > u8::MAX as usize {
+        return Err(ProxyError::Auth);
+    }
+    let mut request = vec![USER_PASSWORD_VERSION, user.len() as u8];
+    request.extend_from_slice(user.as_bytes());
+    request.push(password.len() as u8);
+    request.extend_from_slice(password.as_bytes());
+    write(stream, &request)?;
+
+    match read::<_, 2>(stream)? {
+        [_, 0] => Ok(()),
+        _ => Err(ProxyError::Auth),
+    }
+}
+
+/// Builds the `CONNECT` request. Hosts that aren't ip addresses are
+/// sent as domain names, for the proxy to resolve them.
+fn connect_request(host: &str, port: u16) -> Result<Vec<u8>, ProxyError> {
+    let mut request = vec![VERSION, CMD_CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(ATYP_IPV4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(ATYP_IPV6);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) => {
+            if host.is_empty() || host.len() > u8::MAX as usize {
+                return Err(ProxyError::Host);
+            }
+            request.push(ATYP_DOMAIN);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    Ok(request)
+}
+
+fn reply_message(reply: u8) -> String {
+    match reply {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+    .to_string()
+}
+
+fn write<S: Write>(stream: &mut S, bytes: &[u8]) -> Result<(), ProxyError> {
+    stream.write_all(bytes).map_err(|_| ProxyError::Unavailable)
+}
+
+fn read<S: Read, const N: usize>(stream: &mut S) -> Result<[u8; N], ProxyError> {
+    let mut buf = [0; N];
+    stream
+        .read_exact(&mut buf)
+        .map_err(|_| ProxyError::InvalidResponse)?;
+    Ok(buf)
+}
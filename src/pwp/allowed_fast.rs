@@ -0,0 +1,58 @@
+use crate::utils::hash_info;
+use std::net::IpAddr;
+
+/// Pieces in the allowed fast set of each peer.
+pub const ALLOWED_FAST_COUNT: usize = 10;
+
+/// Computes the allowed fast set of a peer (BEP 6): the pieces it can
+/// download while choked. It only depends on the ip of the peer and
+/// the torrent, so both ends compute the same set. Peers with an IPv6
+/// address get an empty set, as the algorithm is only defined for
+/// IPv4.
+pub fn allowed_fast_set(ip: IpAddr, info_hash: &[u8], pieces: u32, k: usize) -> Vec<u32> {
+    let ip = match ip {
+        IpAddr::V4(ip) => ip,
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => ip,
+            None => return Vec::new(),
+        },
+    };
+    let k = k.min(pieces as usize);
+    let mut set = Vec::with_capacity(k);
+    let mut x = (u32::from(ip) & 0xffffff00).to_be_bytes().to_vec();
+    x.extend_from_slice(info_hash);
+    while set.len() < k {
+        x = hash_info(&x).to_vec();
+        for y in x.chunks_exact(4) {
+            if set.len() == k {
+                break;
+            }
+            let index = u32::from_be_bytes([y[0], y[1], y[2], y[3]]) % pieces;
+            if !set.contains(&index) {
+                set.push(index);
+            }
+        }
+    }
+    set
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowed_fast_set_matches_the_specification() {
+        let ip = "80.4.4.200".parse().unwrap();
+
+        assert_eq!(
+            allowed_fast_set(ip, &[0xaa; 20], 1313, 7),
+            vec![1059, 431, 808, 1217, 287, 376, 1188]
+        );
+        assert_eq!(
+            allowed_fast_set(ip, &[0xaa; 20], 1313, 9),
+            vec![1059, 431, 808, 1217, 287, 376, 1188, 353, 508]
+        );
+        assert_eq!(allowed_fast_set(ip, &[0xaa; 20], 3, 10).len(), 3);
+        assert!(allowed_fast_set("::1".parse().unwrap(), &[0xaa; 20], 1313, 7).is_empty());
+    }
+}
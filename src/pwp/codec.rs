@@ -0,0 +1,421 @@
+use crate::pwp::codec_error::CodecError;
+use crate::pwp::message::PWPMessage;
+use crate::pwp::protocol::handshake_msg;
+use crate::pwp::reserved::Reserved;
+use crate::utils;
+
+/// Largest message accepted by default. It fits a `piece` with a block
+/// of 16 KiB and the bitfield of a torrent with millions of pieces.
+pub const DEFAULT_MAX_LENGTH: u32 = 1024 * 1024;
+/// Protocol string of the handshake.
+const PSTR: &[u8] = b"BitTorrent protocol";
+/// Length of the handshake: the length of the protocol string, the
+/// string, the reserved bytes, the info hash and the peer id.
+const HANDSHAKE_LENGTH: usize = 1 + PSTR.len() + 8 + 20 + 20;
+
+/// # PWPCodec
+/// Encoder and decoder of the messages of the peer wire protocol. The
+/// bytes received are added with [`PWPCodec::extend`], in pieces of
+/// any size, and the messages are taken out once they are complete
+/// with [`PWPCodec::decode`]. It doesn't read or write by itself, so
+/// it can be used with any transport.
+#[derive(Debug)]
+pub struct PWPCodec {
+    /// Bytes received that don't make up a whole message yet
+    buffer: Vec<u8>,
+    max_length: u32,
+}
+
+impl Default for PWPCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PWPCodec {
+    pub fn new() -> Self {
+        Self::with_max_length(DEFAULT_MAX_LENGTH)
+    }
+
+    /// Creates a codec that rejects the messages longer than
+    /// `max_length`, not counting the length prefix.
+    pub fn with_max_length(max_length: u32) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_length,
+        }
+    }
+
+    /// Adds bytes received from the peer.
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Amount of bytes received that haven't been decoded yet.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Takes the next message out of the bytes received. Returns
+    /// [`None`] if the message isn't complete yet. Messages with an
+    /// unknown id are returned as [`PWPMessage::Unknown`].
+    pub fn decode(&mut self) -> Result<Option<PWPMessage>, CodecError> {
+        let length = match self.buffer.get(..4) {
+            Some(prefix) => u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]),
+            None => return Ok(None),
+        };
+        if length > self.max_length {
+            return Err(CodecError::TooLong(length));
+        }
+        let end = 4 + length as usize;
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+
+        let msg = match self.buffer[4..end].split_first() {
+            Some((id, payload)) => decode_payload(*id, payload),
+            None => Ok(PWPMessage::KeepAlive),
+        };
+        self.buffer.drain(..end);
+        msg.map(Some)
+    }
+
+    /// Takes the handshake out of the bytes received. Returns [`None`]
+    /// if it isn't complete yet. It must be the first thing a peer
+    /// sends, and it has no length prefix.
+    pub fn decode_handshake(&mut self) -> Result<Option<PWPMessage>, CodecError> {
+        if let Some(pstrlen) = self.buffer.first() {
+            if *pstrlen as usize != PSTR.len() {
+                return Err(CodecError::InvalidHandshake);
+            }
+        }
+        if self.buffer.len() < HANDSHAKE_LENGTH {
+            return Ok(None);
+        }
+        if &self.buffer[1..1 + PSTR.len()] != PSTR {
+            return Err(CodecError::InvalidHandshake);
+        }
+
+        let handshake: Vec<u8> = self.buffer.drain(..HANDSHAKE_LENGTH).collect();
+        let mut reserved = [0u8; 8];
+        reserved.copy_from_slice(&handshake[20..28]);
+        let info_hash = handshake[28..48].to_vec();
+        let peer_id = handshake[48..].to_vec();
+        Ok(Some(PWPMessage::Handshake(
+            Reserved::new(reserved),
+            info_hash,
+            peer_id,
+        )))
+    }
+
+    /// msg format <length prefix><message ID><payload>
+    ///
+    /// We have decided to keep the fully match arm without any modularization because it's much
+    /// less complex to understand what is going on with each message.
+    ///
+    pub fn encode(msg: PWPMessage) -> Vec<u8> {
+        match msg {
+            PWPMessage::KeepAlive => 0u32.to_be_bytes().into(),
+            PWPMessage::Choke => {
+                let mut b: Vec<u8> = vec![0u8, 0u8, 0u8, 1u8];
+                b.push(0);
+                b
+            }
+            PWPMessage::Unchoke => {
+                let mut b: Vec<u8> = vec![0u8, 0u8, 0u8, 1u8];
+                b.push(1);
+                b
+            }
+            PWPMessage::Interested => {
+                let mut b: Vec<u8> = vec![0u8, 0u8, 0u8, 1u8];
+                b.push(2);
+                b
+            }
+            PWPMessage::NotInterested => {
+                let mut b: Vec<u8> = vec![0u8, 0u8, 0u8, 1u8];
+                b.push(3);
+                b
+            }
+            PWPMessage::Have(piece_index) => {
+                let mut b = vec![0u8, 0u8, 0u8, 5u8, 4u8];
+                b.append(&mut piece_index.to_be_bytes().into());
+                b
+            }
+            PWPMessage::Bitfield(mut bitfield) => {
+                let len = 1u32 + bitfield.len() as u32;
+                let mut b: Vec<u8> = len.to_be_bytes().into();
+                b.push(5u8);
+                b.append(&mut bitfield);
+                b
+            }
+            PWPMessage::Request(index, begin, length) => {
+                let mut b: Vec<u8> = 13u32.to_be_bytes().into();
+                b.push(6);
+                utils::append!(
+                    b,
+                    index.to_be_bytes().to_vec(),
+                    begin.to_be_bytes().to_vec(),
+                    length.to_be_bytes().to_vec()
+                )
+            }
+            PWPMessage::Piece(index, begin, mut block) => {
+                let mut b: Vec<u8> = (9u32 + block.len() as u32).to_be_bytes().into();
+                b.push(7);
+                b.append(&mut index.to_be_bytes().into());
+                b.append(&mut begin.to_be_bytes().into());
+                b.append(&mut block);
+                b
+            }
+            PWPMessage::Cancel(index, begin, length) => {
+                let mut b: Vec<u8> = 13u32.to_be_bytes().into();
+                b.push(8);
+                b.append(&mut index.to_be_bytes().into());
+                b.append(&mut begin.to_be_bytes().into());
+                b.append(&mut length.to_be_bytes().into());
+                b
+            }
+            PWPMessage::Port(port) => {
+                let mut b: Vec<u8> = 3u32.to_be_bytes().into();
+                b.push(9);
+                b.append(&mut port.to_be_bytes().into());
+                b
+            }
+            PWPMessage::Suggest(piece_index) => {
+                let mut b = vec![0u8, 0u8, 0u8, 5u8, 13u8];
+                b.append(&mut piece_index.to_be_bytes().into());
+                b
+            }
+            PWPMessage::HaveAll => {
+                let mut b: Vec<u8> = vec![0u8, 0u8, 0u8, 1u8];
+                b.push(14);
+                b
+            }
+            PWPMessage::HaveNone => {
+                let mut b: Vec<u8> = vec![0u8, 0u8, 0u8, 1u8];
+                b.push(15);
+                b
+            }
+            PWPMessage::Reject(index, begin, length) => {
+                let mut b: Vec<u8> = 13u32.to_be_bytes().into();
+                b.push(16);
+                b.append(&mut index.to_be_bytes().into());
+                b.append(&mut begin.to_be_bytes().into());
+                b.append(&mut length.to_be_bytes().into());
+                b
+            }
+            PWPMessage::AllowedFast(piece_index) => {
+                let mut b = vec![0u8, 0u8, 0u8, 5u8, 17u8];
+                b.append(&mut piece_index.to_be_bytes().into());
+                b
+            }
+            PWPMessage::Extended(id, mut payload) => {
+                let mut b: Vec<u8> = (2u32 + payload.len() as u32).to_be_bytes().into();
+                b.push(20);
+                b.push(id);
+                b.append(&mut payload);
+                b
+            }
+            PWPMessage::Unknown(id, mut payload) => {
+                let mut b: Vec<u8> = (1u32 + payload.len() as u32).to_be_bytes().into();
+                b.push(id);
+                b.append(&mut payload);
+                b
+            }
+            PWPMessage::Handshake(reserved, info_hash, peer_id) => {
+                handshake_msg(reserved, info_hash, &peer_id)
+            }
+        }
+    }
+}
+
+/// Builds the message with id `id`, checking that the payload has the
+/// length the message needs.
+pub fn decode_payload(id: u8, payload: &[u8]) -> Result<PWPMessage, CodecError> {
+    let invalid = || CodecError::InvalidLength {
+        id,
+        length: payload.len(),
+    };
+    let u32_at = |i: usize| {
+        payload
+            .get(i..i + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(invalid)
+    };
+    let fixed = |length: usize| {
+        if payload.len() == length {
+            Ok(())
+        } else {
+            Err(invalid())
+        }
+    };
+
+    let msg = match id {
+        0 => fixed(0).map(|_| PWPMessage::Choke)?,
+        1 => fixed(0).map(|_| PWPMessage::Unchoke)?,
+        2 => fixed(0).map(|_| PWPMessage::Interested)?,
+        3 => fixed(0).map(|_| PWPMessage::NotInterested)?,
+        4 => {
+            fixed(4)?;
+            PWPMessage::Have(u32_at(0)?)
+        }
+        5 => PWPMessage::Bitfield(payload.to_vec()),
+        6 => {
+            fixed(12)?;
+            PWPMessage::Request(u32_at(0)?, u32_at(4)?, u32_at(8)?)
+        }
+        7 => PWPMessage::Piece(u32_at(0)?, u32_at(4)?, payload[8..].to_vec()),
+        8 => {
+            fixed(12)?;
+            PWPMessage::Cancel(u32_at(0)?, u32_at(4)?, u32_at(8)?)
+        }
+        9 => {
+            fixed(2)?;
+            PWPMessage::Port(u16::from_be_bytes([payload[0], payload[1]]))
+        }
+        13 => {
+            fixed(4)?;
+            PWPMessage::Suggest(u32_at(0)?)
+        }
+        14 => fixed(0).map(|_| PWPMessage::HaveAll)?,
+        15 => fixed(0).map(|_| PWPMessage::HaveNone)?,
+        16 => {
+            fixed(12)?;
+            PWPMessage::Reject(u32_at(0)?, u32_at(4)?, u32_at(8)?)
+        }
+        17 => {
+            fixed(4)?;
+            PWPMessage::AllowedFast(u32_at(0)?)
+        }
+        20 => match payload.split_first() {
+            Some((extension, payload)) => PWPMessage::Extended(*extension, payload.to_vec()),
+            None => return Err(invalid()),
+        },
+        _ => PWPMessage::Unknown(id, payload.to_vec()),
+    };
+    Ok(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(codec: &mut PWPCodec) -> Vec<PWPMessage> {
+        let mut messages = Vec::new();
+        while let Some(msg) = codec.decode().unwrap() {
+            messages.push(msg);
+        }
+        messages
+    }
+
+    #[test]
+    fn encode_and_decode_every_message() {
+        let messages = vec![
+            PWPMessage::KeepAlive,
+            PWPMessage::Choke,
+            PWPMessage::Unchoke,
+            PWPMessage::Interested,
+            PWPMessage::NotInterested,
+            PWPMessage::Have(7),
+            PWPMessage::Bitfield(vec![0xff, 0x80]),
+            PWPMessage::Request(1, 16384, 16384),
+            PWPMessage::Piece(1, 0, vec![9; 256]),
+            PWPMessage::Cancel(1, 16384, 16384),
+            PWPMessage::Port(6881),
+            PWPMessage::Suggest(2),
+            PWPMessage::HaveAll,
+            PWPMessage::HaveNone,
+            PWPMessage::Reject(1, 16384, 16384),
+            PWPMessage::AllowedFast(5),
+            PWPMessage::Extended(0, b"de".to_vec()),
+            PWPMessage::Unknown(42, vec![1, 2]),
+        ];
+        let mut codec = PWPCodec::new();
+        for msg in messages.clone() {
+            codec.extend(&PWPCodec::encode(msg));
+        }
+
+        assert_eq!(decode_all(&mut codec), messages);
+        assert_eq!(codec.buffered(), 0);
+    }
+
+    #[test]
+    fn partial_frames_are_buffered() {
+        let bytes = PWPCodec::encode(PWPMessage::Piece(3, 0, vec![1; 256]));
+        let mut codec = PWPCodec::new();
+
+        for byte in &bytes[..bytes.len() - 1] {
+            codec.extend(&[*byte]);
+            assert_eq!(codec.decode(), Ok(None));
+        }
+        codec.extend(&bytes[bytes.len() - 1..]);
+
+        assert_eq!(
+            codec.decode(),
+            Ok(Some(PWPMessage::Piece(3, 0, vec![1; 256])))
+        );
+    }
+
+    #[test]
+    fn length_multiple_of_256_is_not_a_keep_alive() {
+        let mut codec = PWPCodec::new();
+        codec.extend(&PWPCodec::encode(PWPMessage::Bitfield(vec![0; 255])));
+
+        assert_eq!(codec.decode(), Ok(Some(PWPMessage::Bitfield(vec![0; 255]))));
+    }
+
+    #[test]
+    fn invalid_messages_return_errors() {
+        let mut codec = PWPCodec::with_max_length(100);
+        codec.extend(&101u32.to_be_bytes());
+        assert_eq!(codec.decode(), Err(CodecError::TooLong(101)));
+
+        let mut codec = PWPCodec::new();
+        codec.extend(&[0, 0, 0, 5, 6, 0, 0, 0, 1]);
+        assert_eq!(
+            codec.decode(),
+            Err(CodecError::InvalidLength { id: 6, length: 4 })
+        );
+
+        let mut codec = PWPCodec::new();
+        codec.extend(&[0, 0, 0, 1, 20]);
+        assert_eq!(
+            codec.decode(),
+            Err(CodecError::InvalidLength { id: 20, length: 0 })
+        );
+
+        let mut codec = PWPCodec::new();
+        codec.extend(&[0, 0, 0, 3, 7, 0, 0]);
+        assert_eq!(
+            codec.decode(),
+            Err(CodecError::InvalidLength { id: 7, length: 2 })
+        );
+    }
+
+    #[test]
+    fn decode_handshake_and_following_messages() {
+        let reserved = Reserved::client();
+        let mut bytes = PWPCodec::encode(PWPMessage::Handshake(reserved, vec![1; 20], vec![2; 20]));
+        bytes.extend(PWPCodec::encode(PWPMessage::Unchoke));
+        let mut codec = PWPCodec::new();
+
+        codec.extend(&bytes[..30]);
+        assert_eq!(codec.decode_handshake(), Ok(None));
+        codec.extend(&bytes[30..]);
+
+        assert_eq!(
+            codec.decode_handshake(),
+            Ok(Some(PWPMessage::Handshake(
+                reserved,
+                vec![1; 20],
+                vec![2; 20]
+            )))
+        );
+        assert_eq!(codec.decode(), Ok(Some(PWPMessage::Unchoke)));
+
+        let mut codec = PWPCodec::new();
+        codec.extend(b"\x13BitTorrent protocoX");
+        assert_eq!(codec.decode_handshake(), Ok(None));
+        codec.extend(&[0; 48]);
+        assert_eq!(codec.decode_handshake(), Err(CodecError::InvalidHandshake));
+    }
+}
@@ -0,0 +1,29 @@
+use std::fmt;
+
+/// Represents the possible errors found while decoding the messages
+/// of a peer. The connection can't be trusted after any of them.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CodecError {
+    /// The length prefix is larger than the maximum accepted
+    TooLong(u32),
+    /// The payload doesn't have the length required by its message id
+    InvalidLength { id: u8, length: usize },
+    /// The handshake isn't for the BitTorrent protocol
+    InvalidHandshake,
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::TooLong(length) => {
+                write!(f, "The message is too long ({} bytes)", length)
+            }
+            CodecError::InvalidLength { id, length } => write!(
+                f,
+                "A message with id {} can't have a payload of {} bytes",
+                id, length
+            ),
+            CodecError::InvalidHandshake => write!(f, "The handshake is invalid"),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use crate::bencode;
+use crate::pwp::extension_error::ExtensionError;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// # ExtendedHandshake
+/// Dictionary sent as the first message of the extension protocol
+/// (BEP 10). Keys the client doesn't know are ignored.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct ExtendedHandshake {
+    /// Extensions supported, by name, with the id their messages must
+    /// be sent with. The id 0 means the extension is disabled.
+    #[serde(default)]
+    pub m: BTreeMap<String, u8>,
+    /// Port the peer listens at
+    pub p: Option<u16>,
+    /// Name and version of the client
+    pub v: Option<String>,
+    /// Requests the peer keeps queued without dropping them
+    pub reqq: Option<u32>,
+    /// Size of the info dictionary, for `ut_metadata`
+    pub metadata_size: Option<u64>,
+}
+
+impl ExtendedHandshake {
+    pub fn parse(payload: &[u8]) -> Result<Self, ExtensionError> {
+        bencode::from_bytes(payload).map_err(|_| ExtensionError::InvalidHandshake)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, ExtensionError> {
+        bencode::to_bytes(self).map_err(|_| ExtensionError::InvalidHandshake)
+    }
+
+    /// Id the peer assigned to the extension `name`. Returns [`None`]
+    /// if it doesn't support it or disabled it.
+    pub fn id(&self, name: &str) -> Option<u8> {
+        self.m.get(name).copied().filter(|id| *id != 0)
+    }
+}
@@ -0,0 +1,25 @@
+use std::fmt;
+
+/// Represents the possible errors found while handling the messages
+/// of the extension protocol.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExtensionError {
+    /// The extended handshake isn't a valid bencoded dictionary
+    InvalidHandshake,
+    /// The peer used an id the client didn't assign to any extension
+    UnknownId(u8),
+    /// The payload isn't valid for the extension with this name
+    InvalidMessage(&'static str),
+}
+
+impl fmt::Display for ExtensionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtensionError::InvalidHandshake => write!(f, "The extended handshake is invalid"),
+            ExtensionError::UnknownId(id) => write!(f, "There is no extension with id {}", id),
+            ExtensionError::InvalidMessage(name) => {
+                write!(f, "Invalid message for the extension {}", name)
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use crate::pwp::extended_handshake::ExtendedHandshake;
+use crate::pwp::extension_error::ExtensionError;
+
+/// # ExtensionHandler
+/// A feature built on the extension protocol, like the metadata
+/// exchange or the peer exchange. Handlers are registered in an
+/// [`ExtensionRegistry`](crate::pwp::extension_registry::ExtensionRegistry),
+/// which hands them the messages of the peer addressed to them.
+///
+/// The payloads returned are sent to the peer with the id it assigned
+/// to the extension.
+pub trait ExtensionHandler: Send {
+    /// Name of the extension in the `m` dictionary, like `ut_pex`.
+    fn name(&self) -> &'static str;
+
+    /// Adds the keys of the extension to the handshake of the client.
+    fn extend_handshake(&self, _handshake: &mut ExtendedHandshake) {}
+
+    /// Called once the handshake of the peer arrives.
+    fn on_handshake(&mut self, _handshake: &ExtendedHandshake) -> Vec<Vec<u8>> {
+        Vec::new()
+    }
+
+    /// Handles a message of the extension sent by the peer.
+    fn on_message(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, ExtensionError>;
+
+    /// Called before every read once the peer sent its handshake, for
+    /// the messages the extension sends on its own, like the peer
+    /// exchange. Only called if the peer supports the extension.
+    fn poll(&mut self) -> Vec<Vec<u8>> {
+        Vec::new()
+    }
+}
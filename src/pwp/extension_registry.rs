@@ -0,0 +1,193 @@
+use crate::peer::peer_id::client_version;
+use crate::pwp::extended_handshake::ExtendedHandshake;
+use crate::pwp::extension_error::ExtensionError;
+use crate::pwp::extension_handler::ExtensionHandler;
+use crate::pwp::message::PWPMessage;
+use std::fmt;
+
+/// Id of the extended handshake.
+pub const HANDSHAKE_ID: u8 = 0;
+/// Requests of a peer queued by the server.
+pub const REQQ: u32 = 250;
+
+/// # ExtensionRegistry
+/// The extensions of the client on a connection. Each handler gets
+/// an id from the order it was registered in, starting at 1, which is
+/// the one the peer must use to send it messages.
+pub struct ExtensionRegistry {
+    /// Port the client listens at
+    port: u16,
+    handlers: Vec<Box<dyn ExtensionHandler>>,
+    /// Handshake of the peer, once it arrives
+    peer: Option<ExtendedHandshake>,
+}
+
+impl fmt::Debug for ExtensionRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtensionRegistry")
+            .field("port", &self.port)
+            .field(
+                "handlers",
+                &self.handlers.iter().map(|h| h.name()).collect::<Vec<_>>(),
+            )
+            .field("peer", &self.peer)
+            .finish()
+    }
+}
+
+impl ExtensionRegistry {
+    pub fn new(port: u16) -> Self {
+        Self {
+            port,
+            handlers: Vec::new(),
+            peer: None,
+        }
+    }
+
+    pub fn register(&mut self, handler: Box<dyn ExtensionHandler>) -> &'_ mut Self {
+        self.handlers.push(handler);
+        self
+    }
+
+    /// Handshake of the client, with the extensions registered, the
+    /// listen port, the client version and `reqq`.
+    pub fn handshake(&self) -> ExtendedHandshake {
+        let mut handshake = ExtendedHandshake {
+            m: self
+                .handlers
+                .iter()
+                .zip(1..)
+                .map(|(h, id)| (h.name().to_string(), id))
+                .collect(),
+            p: Some(self.port),
+            v: Some(client_version()),
+            reqq: Some(REQQ),
+            metadata_size: None,
+        };
+        for handler in &self.handlers {
+            handler.extend_handshake(&mut handshake);
+        }
+        handshake
+    }
+
+    /// Handshake of the client as a message.
+    pub fn handshake_message(&self) -> Result<PWPMessage, ExtensionError> {
+        Ok(PWPMessage::Extended(
+            HANDSHAKE_ID,
+            self.handshake().encode()?,
+        ))
+    }
+
+    /// Handshake of the peer, [`None`] until it arrives.
+    pub fn peer_handshake(&self) -> Option<&ExtendedHandshake> {
+        self.peer.as_ref()
+    }
+
+    /// Handles an extended message of the peer, returning the messages
+    /// to send back.
+    pub fn handle(&mut self, id: u8, payload: &[u8]) -> Result<Vec<PWPMessage>, ExtensionError> {
+        if id == HANDSHAKE_ID {
+            let handshake = ExtendedHandshake::parse(payload)?;
+            let messages = self
+                .handlers
+                .iter_mut()
+                .flat_map(|h| reply(&handshake, h.name(), h.on_handshake(&handshake)))
+                .collect();
+            self.peer = Some(handshake);
+            return Ok(messages);
+        }
+
+        let handler = self
+            .handlers
+            .get_mut(id as usize - 1)
+            .ok_or(ExtensionError::UnknownId(id))?;
+        let payloads = handler.on_message(payload)?;
+        Ok(match &self.peer {
+            Some(peer) => reply(peer, handler.name(), payloads),
+            None => Vec::new(),
+        })
+    }
+
+    /// Messages the extensions send on their own, for the ones the
+    /// peer supports.
+    pub fn poll(&mut self) -> Vec<PWPMessage> {
+        let peer = match &self.peer {
+            Some(peer) => peer,
+            None => return Vec::new(),
+        };
+        self.handlers
+            .iter_mut()
+            .filter(|h| peer.id(h.name()).is_some())
+            .flat_map(|h| reply(peer, h.name(), h.poll()))
+            .collect()
+    }
+}
+
+/// Addresses the payloads to the extension `name` of the peer. They
+/// are dropped if the peer doesn't support it.
+fn reply(peer: &ExtendedHandshake, name: &str, payloads: Vec<Vec<u8>>) -> Vec<PWPMessage> {
+    match peer.id(name) {
+        Some(id) => payloads
+            .into_iter()
+            .map(|payload| PWPMessage::Extended(id, payload))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every message with the same payload.
+    struct Echo;
+
+    impl ExtensionHandler for Echo {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn on_message(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, ExtensionError> {
+            Ok(vec![payload.to_vec()])
+        }
+    }
+
+    #[test]
+    fn handshake_advertises_extensions_port_version_and_reqq() {
+        let mut registry = ExtensionRegistry::new(6881);
+        registry.register(Box::new(Echo));
+
+        let payload = match registry.handshake_message().unwrap() {
+            PWPMessage::Extended(HANDSHAKE_ID, payload) => payload,
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        let handshake = ExtendedHandshake::parse(&payload).unwrap();
+
+        assert_eq!(handshake.id("echo"), Some(1));
+        assert_eq!(handshake.p, Some(6881));
+        assert_eq!(handshake.v, Some(client_version()));
+        assert_eq!(handshake.reqq, Some(REQQ));
+    }
+
+    #[test]
+    fn messages_are_routed_by_id_and_answered_with_the_peer_id() {
+        let mut registry = ExtensionRegistry::new(6881);
+        registry.register(Box::new(Echo));
+
+        let replies = registry
+            .handle(HANDSHAKE_ID, b"d1:md4:echoi3e6:ut_pexi0ee1:v4:peere")
+            .unwrap();
+        assert!(replies.is_empty());
+        assert_eq!(registry.peer_handshake().unwrap().id("ut_pex"), None);
+
+        assert_eq!(
+            registry.handle(1, b"hi"),
+            Ok(vec![PWPMessage::Extended(3, b"hi".to_vec())])
+        );
+        assert_eq!(registry.handle(2, b"hi"), Err(ExtensionError::UnknownId(2)));
+        assert_eq!(
+            registry.handle(HANDSHAKE_ID, b"i1e"),
+            Err(ExtensionError::InvalidHandshake)
+        );
+    }
+}
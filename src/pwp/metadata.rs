@@ -0,0 +1,173 @@
+use crate::utils::hash_info;
+
+/// Size of the pieces the metadata is exchanged in.
+pub const METADATA_PIECE_SIZE: usize = 16 * 1024;
+/// Largest info dictionary accepted from a peer.
+pub const MAX_METADATA_SIZE: usize = 8 * 1024 * 1024;
+
+/// # Metadata
+/// The bencoded info dictionary of a torrent, as exchanged with
+/// `ut_metadata` (BEP 9). While it's being fetched it keeps the
+/// pieces received, and it's only complete once the whole dictionary
+/// matches the info hash.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Metadata {
+    info_hash: [u8; 20],
+    /// Size announced by the peers, while the info is missing
+    size: Option<usize>,
+    pieces: Vec<Option<Vec<u8>>>,
+    info: Option<Vec<u8>>,
+    /// Times the pieces didn't match the info hash
+    discarded: u32,
+}
+
+impl Metadata {
+    /// Metadata to be fetched from the peers.
+    pub fn new(info_hash: [u8; 20]) -> Self {
+        Self {
+            info_hash,
+            size: None,
+            pieces: Vec::new(),
+            info: None,
+            discarded: 0,
+        }
+    }
+
+    /// Metadata of a torrent the client already has. Returns [`None`]
+    /// if the info doesn't match the info hash.
+    pub fn complete(info_hash: [u8; 20], info: Vec<u8>) -> Option<Self> {
+        (hash_info(&info) == info_hash).then_some(Self {
+            info_hash,
+            size: None,
+            pieces: Vec::new(),
+            info: Some(info),
+            discarded: 0,
+        })
+    }
+
+    pub fn info_hash(&self) -> [u8; 20] {
+        self.info_hash
+    }
+
+    /// The verified info dictionary, [`None`] until every piece
+    /// arrives.
+    pub fn info(&self) -> Option<&[u8]> {
+        self.info.as_deref()
+    }
+
+    pub fn size(&self) -> Option<usize> {
+        self.info.as_ref().map(Vec::len).or(self.size)
+    }
+
+    /// Sets the size announced by a peer, if it wasn't known yet.
+    /// Returns false if it's known to be a different one or it is too
+    /// large.
+    pub fn set_size(&mut self, size: usize) -> bool {
+        if let Some(known) = self.size() {
+            return known == size;
+        }
+        if size == 0 || size > MAX_METADATA_SIZE {
+            return false;
+        }
+        self.size = Some(size);
+        self.pieces = vec![None; size.div_ceil(METADATA_PIECE_SIZE)];
+        true
+    }
+
+    /// Times the pieces were discarded, for the handlers to know when
+    /// they have to be requested again.
+    pub fn discarded(&self) -> u32 {
+        self.discarded
+    }
+
+    /// Pieces that haven't arrived yet.
+    pub fn missing(&self) -> Vec<u32> {
+        if self.info.is_some() {
+            return Vec::new();
+        }
+        (0..self.pieces.len() as u32)
+            .filter(|i| self.pieces[*i as usize].is_none())
+            .collect()
+    }
+
+    /// Returns a piece of the complete info dictionary.
+    pub fn piece(&self, index: u32) -> Option<&[u8]> {
+        let start = index as usize * METADATA_PIECE_SIZE;
+        let info = self.info.as_ref()?;
+        info.get(start..info.len().min(start + METADATA_PIECE_SIZE))
+            .filter(|p| !p.is_empty())
+    }
+
+    /// Stores a piece sent by a peer. Once every piece arrived they
+    /// are joined and checked against the info hash, and if they
+    /// don't match they are discarded along with the size, as it may
+    /// be wrong too. The pieces that arrive while the size isn't known
+    /// are dropped. Returns false if the piece doesn't have the
+    /// expected length.
+    pub fn store(&mut self, index: u32, data: &[u8]) -> bool {
+        let size = match (self.size, &self.info) {
+            (Some(size), None) => size,
+            (None, None) => return true,
+            _ => return false,
+        };
+        let start = index as usize * METADATA_PIECE_SIZE;
+        let expected = size.saturating_sub(start).min(METADATA_PIECE_SIZE);
+        match self.pieces.get_mut(index as usize) {
+            Some(piece) if expected == data.len() => *piece = Some(data.to_vec()),
+            _ => return false,
+        }
+
+        if self.pieces.iter().all(Option::is_some) {
+            let info: Vec<u8> = self.pieces.iter().flatten().flatten().copied().collect();
+            if hash_info(&info) == self.info_hash {
+                self.info = Some(info);
+            } else {
+                self.size = None;
+                self.discarded += 1;
+            }
+            self.pieces.clear();
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pieces_are_joined_and_verified() {
+        let info: Vec<u8> = (0..METADATA_PIECE_SIZE + 10).map(|i| i as u8).collect();
+        let mut metadata = Metadata::new(hash_info(&info));
+
+        assert!(metadata.set_size(info.len()));
+        assert!(!metadata.set_size(info.len() + 1));
+        assert_eq!(metadata.missing(), vec![0, 1]);
+        assert!(!metadata.store(0, &info[..10]));
+        assert!(metadata.store(1, &info[METADATA_PIECE_SIZE..]));
+        assert_eq!(metadata.missing(), vec![0]);
+        assert!(metadata.store(0, &info[..METADATA_PIECE_SIZE]));
+
+        assert_eq!(metadata.info(), Some(&info[..]));
+        assert!(metadata.missing().is_empty());
+        assert_eq!(metadata.piece(1), Some(&info[METADATA_PIECE_SIZE..]));
+        assert_eq!(metadata.piece(2), None);
+    }
+
+    #[test]
+    fn pieces_that_dont_match_the_hash_are_discarded() {
+        let mut metadata = Metadata::new([0; 20]);
+
+        assert!(metadata.set_size(3));
+        assert!(metadata.store(0, b"abc"));
+
+        assert_eq!(metadata.info(), None);
+        assert_eq!(metadata.size(), None);
+        assert_eq!(metadata.discarded(), 1);
+        assert!(metadata.missing().is_empty());
+        assert!(metadata.store(0, b"abc"));
+        assert!(metadata.set_size(4));
+        assert_eq!(metadata.missing(), vec![0]);
+        assert!(Metadata::complete([0; 20], b"abc".to_vec()).is_none());
+    }
+}
@@ -1,3 +1,15 @@
+pub mod allowed_fast;
+pub mod codec;
+pub mod codec_error;
+pub mod extended_handshake;
+pub mod extension_error;
+pub mod extension_handler;
+pub mod extension_registry;
 pub mod message;
+pub mod metadata;
+pub mod pex_swarm;
 pub mod protocol;
 pub mod protocol_error;
+pub mod reserved;
+pub mod ut_metadata;
+pub mod ut_pex;
@@ -0,0 +1,167 @@
+use crate::peer::peer_handler::Peer;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Flag of a peer the client connected to, so it accepts incoming
+/// connections.
+pub const FLAG_REACHABLE: u8 = 0x10;
+/// Flag of a peer that only uploads, like a seeder.
+pub const FLAG_SEED: u8 = 0x02;
+/// Most peers found kept until the download takes them, the rest are
+/// dropped.
+pub const MAX_FOUND_PEERS: usize = 200;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+struct SwarmPeers {
+    /// Peers the client is connected to, with their flags
+    connected: HashMap<SocketAddr, u8>,
+    /// Peers sent by other peers, until the download takes them
+    found: Vec<Peer>,
+}
+
+/// # PexSwarm
+/// The peers of a torrent exchanged with `ut_pex` (BEP 11). Clones
+/// share the same peers, so every connection of the torrent sees the
+/// ones the others connect to or find.
+#[derive(Clone, Default)]
+pub struct PexSwarm {
+    peers: Arc<Mutex<SwarmPeers>>,
+}
+
+impl fmt::Debug for PexSwarm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PexSwarm").field(&*self.lock()).finish()
+    }
+}
+
+/// Two swarms are equal if they have the same peers.
+impl PartialEq for PexSwarm {
+    fn eq(&self, other: &Self) -> bool {
+        if Arc::ptr_eq(&self.peers, &other.peers) {
+            return true;
+        }
+        // Only one of the swarms is locked at a time
+        let peers = self.lock().clone();
+        peers == *other.lock()
+    }
+}
+
+impl Eq for PexSwarm {}
+
+impl PexSwarm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a peer the client connected to. It's removed once the
+    /// returned [`SwarmMember`] is dropped.
+    pub fn join(&self, addr: SocketAddr, flags: u8) -> SwarmMember {
+        self.lock().connected.insert(addr, flags);
+        SwarmMember {
+            swarm: self.clone(),
+            addr,
+        }
+    }
+
+    /// Peers the client is connected to, with their flags.
+    pub fn connected(&self) -> HashMap<SocketAddr, u8> {
+        self.lock().connected.clone()
+    }
+
+    /// Adds the peers sent by another peer, unless they were already
+    /// found or there are [`MAX_FOUND_PEERS`] waiting for the download.
+    pub fn found(&self, peers: Vec<Peer>) {
+        let mut swarm = self.lock();
+        for peer in peers {
+            if swarm.found.len() >= MAX_FOUND_PEERS {
+                break;
+            }
+            if !swarm.found.iter().any(|p| p.addr() == peer.addr()) {
+                swarm.found.push(peer);
+            }
+        }
+    }
+
+    /// Takes the peers found since the last call.
+    pub fn take_found(&self) -> Vec<Peer> {
+        std::mem::take(&mut self.lock().found)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SwarmPeers> {
+        match self.peers.lock() {
+            Ok(peers) => peers,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// A connection to a peer of a [`PexSwarm`], which leaves the swarm
+/// when dropped.
+#[derive(Debug)]
+pub struct SwarmMember {
+    swarm: PexSwarm,
+    addr: SocketAddr,
+}
+
+impl SwarmMember {
+    /// Changes the flags sent along with the peer, like once it's
+    /// known to be a seeder.
+    pub fn set_flags(&self, flags: u8) {
+        if let Some(f) = self.swarm.lock().connected.get_mut(&self.addr) {
+            *f = flags;
+        }
+    }
+}
+
+impl Drop for SwarmMember {
+    fn drop(&mut self) {
+        self.swarm.lock().connected.remove(&self.addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::peer_source::PeerSource;
+
+    fn peer(port: u16) -> Peer {
+        Peer {
+            peer_id: None,
+            ip: Some("10.0.0.1".parse().unwrap()),
+            port,
+            source: PeerSource::Pex,
+        }
+    }
+
+    #[test]
+    fn found_peers_are_capped_and_not_repeated() {
+        let swarm = PexSwarm::new();
+
+        swarm.found(vec![peer(1), peer(1), peer(2)]);
+        swarm.found((1..=MAX_FOUND_PEERS as u16 * 2).map(peer).collect());
+
+        let found = swarm.take_found();
+        assert_eq!(found.len(), MAX_FOUND_PEERS);
+        assert_eq!(found[..2], [peer(1), peer(2)]);
+        assert_eq!(found[2], peer(3));
+    }
+
+    #[test]
+    fn members_leave_the_swarm_when_dropped() {
+        let swarm = PexSwarm::new();
+        let addr: SocketAddr = "10.0.0.1:6881".parse().unwrap();
+
+        let member = swarm.join(addr, FLAG_REACHABLE);
+        member.set_flags(FLAG_REACHABLE | FLAG_SEED);
+        assert_eq!(
+            swarm.connected().get(&addr),
+            Some(&(FLAG_REACHABLE | FLAG_SEED))
+        );
+
+        drop(member);
+        assert!(swarm.connected().is_empty());
+        assert_eq!(swarm, PexSwarm::new());
+    }
+}
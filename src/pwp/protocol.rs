@@ -1,13 +1,24 @@
+use crate::peer::peer_id::session_peer_id;
+use crate::proxy::connector::connect_peer;
+use crate::pwp::codec::PWPCodec;
+use crate::pwp::codec_error::CodecError;
+use crate::pwp::extension_error::ExtensionError;
+use crate::pwp::extension_registry::ExtensionRegistry;
 use crate::pwp::message::PWPMessage;
+use crate::pwp::reserved::{Capability, Reserved};
 use crate::utils;
 use crate::{peer::peer_handler::Peer, pwp::protocol_error::ProtocolError};
-use std::net::SocketAddr;
-use std::{io::Read, io::Write, net::TcpStream};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+/// Longest wait for a connection to a peer.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Bytes read from the socket at a time.
+const READ_CHUNK: usize = 16 * 1024;
 
 #[derive(Debug)]
 pub enum PWPError {
-    ReadError, // TODO: Remove, redundant
-    WrongSizeRead,
     MissingPeerID, // Should be an error?
     Connection,
     Handshake,
@@ -15,23 +26,34 @@ pub enum PWPError {
     Read,
     EmptyBytes,
     MappingError,
+    /// The peer sent bytes that aren't a valid message
+    Decode(CodecError),
+    /// The peer sent an invalid message of the extension protocol
+    Extension(ExtensionError),
 }
 
 #[derive(Debug)]
-pub struct PWPStream(pub TcpStream);
+pub struct PWPStream {
+    stream: TcpStream,
+    codec: PWPCodec,
+    /// Extensions of the client on this connection
+    extensions: ExtensionRegistry,
+    /// Capabilities the peer sent in its handshake
+    peer_reserved: Reserved,
+}
 
 impl PWPStream {
-    pub fn connect(peer: &Peer, info_hash: Vec<u8>) -> Result<Self, ProtocolError> {
-        let ip = match peer.ip {
-            Some(it) => it,
-            None => return Err(ProtocolError::Connection),
-        };
-        let socket = SocketAddr::new(ip, peer.port);
+    pub fn connect(
+        peer: &Peer,
+        info_hash: Vec<u8>,
+        extensions: ExtensionRegistry,
+    ) -> Result<Self, ProtocolError> {
+        let socket = peer.addr().ok_or(ProtocolError::Connection)?;
         // println!("{}", socket);
-        let mut stream = TcpStream::connect(socket).map_err(|_| ProtocolError::Connection)?;
+        let mut stream =
+            connect_peer(socket, CONNECT_TIMEOUT).map_err(|_| ProtocolError::Connection)?;
         // println!("{:?}", stream);
-        let peer_id = peer.peer_id.ok_or(ProtocolError::MissingPeerID)?;
-        let msg = handshake_msg(info_hash, &peer_id[..]);
+        let msg = handshake_msg(Reserved::client(), info_hash, &session_peer_id());
         //let mut stream = stream;
         stream
             .write_all(&msg)
@@ -39,135 +61,120 @@ impl PWPStream {
 
         // println!("Handshake sent");
 
-        Ok(PWPStream(stream))
+        Ok(PWPStream::new(stream, extensions))
     }
 
-    /// msg format <length prefix><message ID><payload>
-    ///
-    /// We have decided to keep the fully match arm without any modularization because it's much
-    /// less complex to understand what is going on with each message.
-    ///
+    /// Sends the message to the peer.
     pub fn send(&mut self, msg: PWPMessage) -> Result<(), PWPError> {
-        let bytes = match msg {
-            PWPMessage::KeepAlive => 0u32.to_be_bytes().into(),
-            PWPMessage::Choke => {
-                let mut b: Vec<u8> = vec![0u8, 0u8, 0u8, 1u8];
-                b.push(0);
-                b
-            }
-            PWPMessage::Unchoke => {
-                let mut b: Vec<u8> = vec![0u8, 0u8, 0u8, 1u8];
-                b.push(1);
-                b
-            }
-            PWPMessage::Interested => {
-                let mut b: Vec<u8> = vec![0u8, 0u8, 0u8, 1u8];
-                b.push(2);
-                b
-            }
-            PWPMessage::NotInterested => {
-                let mut b: Vec<u8> = vec![0u8, 0u8, 0u8, 1u8];
-                b.push(3);
-                b
-            }
-            PWPMessage::Have(piece_index) => {
-                let mut b = vec![0u8, 0u8, 0u8, 5u8, 4u8];
-                b.append(&mut piece_index.to_be_bytes().into());
-                b
-            }
-            PWPMessage::Bitfield(mut bitfield) => {
-                let len = 1u32 + bitfield.len() as u32;
-                let mut b: Vec<u8> = len.to_be_bytes().into();
-                b.push(5u8);
-                b.append(&mut bitfield);
-                b
-            }
-            PWPMessage::Request(index, begin, length) => {
-                let mut b: Vec<u8> = 13u32.to_be_bytes().into();
-                b.push(6);
-                utils::append!(
-                    b,
-                    index.to_be_bytes().to_vec(),
-                    begin.to_be_bytes().to_vec(),
-                    length.to_be_bytes().to_vec()
-                )
-            }
-            PWPMessage::Piece(index, begin, mut block) => {
-                let mut b: Vec<u8> = (9u32 + block.len() as u32).to_be_bytes().into();
-                b.push(7);
-                b.append(&mut index.to_be_bytes().into());
-                b.append(&mut begin.to_be_bytes().into());
-                b.append(&mut block);
-                b
+        self.stream
+            .write_all(&PWPCodec::encode(msg))
+            .map_err(|_| PWPError::PeerConnection)
+    }
+
+    /// Reads from the peer until `decode` returns a message. Partial
+    /// messages are kept in the codec for the next call.
+    fn read_with<F>(&mut self, mut decode: F) -> Result<PWPMessage, PWPError>
+    where
+        F: FnMut(&mut PWPCodec) -> Result<Option<PWPMessage>, CodecError>,
+    {
+        let mut buf = [0u8; READ_CHUNK];
+        loop {
+            if let Some(msg) = decode(&mut self.codec).map_err(PWPError::Decode)? {
+                return Ok(msg);
             }
-            PWPMessage::Cancel(index, begin, length) => {
-                let mut b: Vec<u8> = 13u32.to_be_bytes().into();
-                b.push(8);
-                b.append(&mut index.to_be_bytes().into());
-                b.append(&mut begin.to_be_bytes().into());
-                b.append(&mut length.to_be_bytes().into());
-                b
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(PWPError::EmptyBytes),
+                Ok(n) => self.codec.extend(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(_) => return Err(PWPError::Read),
             }
-            PWPMessage::Handshake(info_hash, peer_id) => handshake_msg(info_hash, &peer_id),
-        };
-        self.0
-            .write_all(&bytes)
-            .map_err(|_| PWPError::PeerConnection)
+        }
     }
 
-    fn read_bytes(&self, bytes_to_read: u32) -> Result<Vec<u8>, PWPError> {
-        let mut buf = vec![];
-        let stream = &self.0;
-        let mut take = stream.take(bytes_to_read as u64);
-        let bytes_read = take.read_to_end(&mut buf);
-        match bytes_read {
-            Ok(n) => {
-                if (n as u32) == bytes_to_read {
-                    Ok(buf)
-                } else {
-                    Err(PWPError::WrongSizeRead)
+    /// Interpretates the stream of bytes recieved from the peer. The
+    /// messages of the extension protocol are handled by the
+    /// extensions, and never returned. The messages the extensions
+    /// send on their own go out before every read.
+    pub fn read(&mut self) -> Result<PWPMessage, PWPError> {
+        loop {
+            for msg in self.extensions.poll() {
+                self.send(msg)?;
+            }
+            match self.read_with(PWPCodec::decode)? {
+                PWPMessage::Extended(id, payload) => {
+                    let replies = self
+                        .extensions
+                        .handle(id, &payload)
+                        .map_err(PWPError::Extension)?;
+                    for reply in replies {
+                        self.send(reply)?;
+                    }
                 }
+                msg => return Ok(msg),
             }
-            Err(_) => Err(PWPError::ReadError),
         }
     }
 
-    /// Interpretates the stream of bytes recieved from the peer.
-    pub fn read(&mut self) -> Result<PWPMessage, PWPError> {
-        let array = self.read_bytes(4)?;
-        if array[3] == 0 {
-            return PWPMessage::new(&b'K', &mut &array[..]).ok_or(PWPError::MappingError);
+    /// Reads a handshake message and returns it.
+    pub fn read_handshake(&mut self) -> Result<PWPMessage, PWPError> {
+        let handshake = self.read_with(PWPCodec::decode_handshake)?;
+        if let PWPMessage::Handshake(reserved, _, _) = &handshake {
+            self.peer_reserved = *reserved;
         }
-        let msg_len = utils::from_u32_be(&mut &array[..]).ok_or(PWPError::WrongSizeRead)?;
+        Ok(handshake)
+    }
 
-        if msg_len > 0 {
-            let msg = self.read_bytes(msg_len)?;
-            PWPMessage::new(&msg[0], &mut &msg[1..]).ok_or(PWPError::MappingError)
-        } else {
-            PWPMessage::new(&b'K', &mut &array[..]).ok_or(PWPError::MappingError)
+    /// Sends the extended handshake if the peer supports the extension
+    /// protocol. It must be sent after the handshake.
+    pub fn send_extended_handshake(&mut self) -> Result<(), PWPError> {
+        if !self.peer_supports(Capability::Extension) {
+            return Ok(());
         }
+        let msg = self
+            .extensions
+            .handshake_message()
+            .map_err(PWPError::Extension)?;
+        self.send(msg)
     }
 
-    /// Reads a handshake message and returns it.
-    pub fn read_handshake(&mut self) -> Result<PWPMessage, PWPError> {
-        let msg_len = 68; // 49 + 19 --> 49 + len(pstr)
-        let msg = match self.read_bytes(msg_len) {
-            Ok(it) => it,
-            Err(_) => return Err(PWPError::WrongSizeRead),
-        };
+    /// Whether the peer announced `capability` in its handshake.
+    pub fn peer_supports(&self, capability: Capability) -> bool {
+        self.peer_reserved.supports(capability)
+    }
 
-        PWPMessage::new(&msg[4], &mut &msg[0..]).ok_or(PWPError::MappingError)
+    pub fn extensions(&self) -> &ExtensionRegistry {
+        &self.extensions
     }
 
-    pub fn new(stream: TcpStream) -> Self {
-        Self(stream)
+    pub fn extensions_mut(&mut self) -> &mut ExtensionRegistry {
+        &mut self.extensions
+    }
+
+    /// Longest wait for the peer in each read, [`None`] waits forever.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<(), PWPError> {
+        self.stream
+            .set_read_timeout(timeout)
+            .map_err(|_| PWPError::Connection)
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr, PWPError> {
+        self.stream.peer_addr().map_err(|_| PWPError::Connection)
+    }
+
+    pub fn new(stream: TcpStream, extensions: ExtensionRegistry) -> Self {
+        Self {
+            stream,
+            codec: PWPCodec::new(),
+            extensions,
+            peer_reserved: Reserved::default(),
+        }
     }
 }
 
-fn handshake_msg(info_hash: Vec<u8>, peer_id: &[u8]) -> Vec<u8> {
+pub(crate) fn handshake_msg(reserved: Reserved, info_hash: Vec<u8>, peer_id: &[u8]) -> Vec<u8> {
     let mut pstr = b"BitTorrent protocol".to_vec();
     let mut pstrlen = vec![pstr.len() as u8];
-    let mut reserved = vec![0u8; 8];
+    let mut reserved = reserved.bytes().to_vec();
     let mut hash = info_hash;
     utils::append!(pstrlen, pstr, reserved, hash, peer_id.to_vec())
 }
@@ -178,7 +185,7 @@ mod tests {
 
     #[test]
     fn create_handshake_msg_correctly() {
-        let got = handshake_msg(vec![0u8; 20], &[0u8; 20]);
+        let got = handshake_msg(Reserved::default(), vec![0u8; 20], &[0u8; 20]);
         let want = vec![
             19, b'B', b'i', b't', b'T', b'o', b'r', b'r', b'e', b'n', b't', b' ', b'p', b'r', b'o',
             b't', b'o', b'c', b'o', b'l', 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
@@ -0,0 +1,75 @@
+/// Capabilities that peers announce in the reserved bytes of the
+/// handshake.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Capability {
+    /// Extension protocol, BEP 10
+    Extension,
+    /// Fast extension, BEP 6
+    Fast,
+    /// Distributed hash table, BEP 5
+    Dht,
+}
+
+impl Capability {
+    /// Byte of the reserved bytes and bit of that byte used by the
+    /// capability.
+    fn position(self) -> (usize, u8) {
+        match self {
+            Capability::Extension => (5, 0x10),
+            Capability::Fast => (7, 0x04),
+            Capability::Dht => (7, 0x01),
+        }
+    }
+}
+
+/// # Reserved
+/// The eight reserved bytes of the handshake, read as the flags of
+/// the capabilities of a peer. Bits that aren't known are kept as
+/// they were received.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Reserved([u8; 8]);
+
+impl Reserved {
+    pub fn new(bytes: [u8; 8]) -> Self {
+        Self(bytes)
+    }
+
+    /// Capabilities supported by the client.
+    pub fn client() -> Self {
+        Self::default()
+            .with(Capability::Extension)
+            .with(Capability::Fast)
+    }
+
+    /// Returns the flags with `capability` set.
+    pub fn with(mut self, capability: Capability) -> Self {
+        let (byte, bit) = capability.position();
+        self.0[byte] |= bit;
+        self
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        let (byte, bit) = capability.position();
+        self.0[byte] & bit != 0
+    }
+
+    pub fn bytes(&self) -> [u8; 8] {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capabilities_are_read_from_their_bits() {
+        let reserved = Reserved::new([0, 0, 0, 0, 0, 0x10, 0, 0x05]);
+
+        assert!(reserved.supports(Capability::Extension));
+        assert!(reserved.supports(Capability::Fast));
+        assert!(reserved.supports(Capability::Dht));
+        assert!(!Reserved::default().supports(Capability::Extension));
+        assert_eq!(Reserved::client().bytes(), [0, 0, 0, 0, 0, 0x10, 0, 0x04]);
+    }
+}
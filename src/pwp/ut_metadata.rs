@@ -0,0 +1,225 @@
+use crate::bencode::{self, parser::Parser};
+use crate::pwp::extended_handshake::ExtendedHandshake;
+use crate::pwp::extension_error::ExtensionError;
+use crate::pwp::extension_handler::ExtensionHandler;
+use crate::pwp::metadata::Metadata;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Name of the extension in the extended handshake.
+pub const NAME: &str = "ut_metadata";
+
+const REQUEST: u8 = 0;
+const DATA: u8 = 1;
+const REJECT: u8 = 2;
+
+/// Dictionary at the start of every message. The `data` messages are
+/// followed by the piece.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct MetadataMessage {
+    msg_type: u8,
+    piece: u32,
+    total_size: Option<u64>,
+}
+
+/// # UtMetadata
+/// Exchanges the info dictionary of a torrent with the peers (BEP 9).
+/// If the client has it, it's sent to the peers that ask for it;
+/// otherwise every piece is requested as soon as the peer tells its
+/// size, and again if the pieces are discarded for not matching the
+/// info hash. The metadata is shared by every connection of the
+/// torrent.
+pub struct UtMetadata {
+    metadata: Arc<Mutex<Metadata>>,
+    /// Size announced by the peer
+    peer_size: Option<usize>,
+    /// Discarded count of the metadata when the pieces were requested
+    requested: Option<u32>,
+}
+
+impl UtMetadata {
+    pub fn new(metadata: Arc<Mutex<Metadata>>) -> Self {
+        Self {
+            metadata,
+            peer_size: None,
+            requested: None,
+        }
+    }
+
+    /// Requests the missing pieces to the peer, unless they were
+    /// already requested since the last time they were discarded.
+    fn request(&mut self) -> Vec<Vec<u8>> {
+        let size = match self.peer_size {
+            Some(size) => size,
+            None => return Vec::new(),
+        };
+        let mut metadata = self.lock();
+        let discarded = Some(metadata.discarded());
+        if metadata.info().is_some() || self.requested == discarded || !metadata.set_size(size) {
+            return Vec::new();
+        }
+        let requests = metadata
+            .missing()
+            .into_iter()
+            .map(|piece| encode(REQUEST, piece, None, &[]))
+            .collect();
+        drop(metadata);
+        self.requested = discarded;
+        requests
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Metadata> {
+        match self.metadata.lock() {
+            Ok(metadata) => metadata,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl ExtensionHandler for UtMetadata {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn extend_handshake(&self, handshake: &mut ExtendedHandshake) {
+        handshake.metadata_size = self.lock().info().map(|info| info.len() as u64);
+    }
+
+    fn on_handshake(&mut self, handshake: &ExtendedHandshake) -> Vec<Vec<u8>> {
+        self.peer_size = handshake.metadata_size.map(|size| size as usize);
+        self.request()
+    }
+
+    fn on_message(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, ExtensionError> {
+        let (msg, data) = parse(payload)?;
+        let mut metadata = self.lock();
+        match msg.msg_type {
+            REQUEST => {
+                let reply = match metadata.piece(msg.piece) {
+                    Some(piece) => {
+                        let size = metadata.size().map(|s| s as u64);
+                        encode(DATA, msg.piece, size, piece)
+                    }
+                    None => encode(REJECT, msg.piece, None, &[]),
+                };
+                Ok(vec![reply])
+            }
+            // Pieces that arrive once the info is complete are dropped
+            DATA if metadata.info().is_none() && !metadata.store(msg.piece, data) => {
+                Err(ExtensionError::InvalidMessage(NAME))
+            }
+            // Rejects and unknown types are ignored
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn poll(&mut self) -> Vec<Vec<u8>> {
+        self.request()
+    }
+}
+
+/// Splits the message in its dictionary and the data that follows.
+fn parse(payload: &[u8]) -> Result<(MetadataMessage, &[u8]), ExtensionError> {
+    let invalid = || ExtensionError::InvalidMessage(NAME);
+    let dict = Parser::new(payload).bencoded_ref().map_err(|_| invalid())?;
+    let end = dict.span().end;
+    let msg = bencode::from_value(dict.to_value()).map_err(|_| invalid())?;
+    Ok((msg, &payload[end..]))
+}
+
+fn encode(msg_type: u8, piece: u32, total_size: Option<u64>, data: &[u8]) -> Vec<u8> {
+    let msg = MetadataMessage {
+        msg_type,
+        piece,
+        total_size,
+    };
+    let mut bytes = bencode::to_bytes(&msg).unwrap_or_default();
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::hash_info;
+
+    fn handshake(metadata_size: Option<u64>) -> ExtendedHandshake {
+        ExtendedHandshake {
+            metadata_size,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn fetch_metadata_from_a_peer() {
+        let info = b"d4:name1:ae".to_vec();
+        let metadata = Arc::new(Mutex::new(Metadata::new(hash_info(&info))));
+        let mut handler = UtMetadata::new(Arc::clone(&metadata));
+
+        let requests = handler.on_handshake(&handshake(Some(info.len() as u64)));
+        assert_eq!(requests, vec![b"d8:msg_typei0e5:piecei0ee".to_vec()]);
+
+        let mut data = b"d8:msg_typei1e5:piecei0e10:total_sizei11ee".to_vec();
+        data.extend_from_slice(&info);
+        assert_eq!(handler.on_message(&data), Ok(Vec::new()));
+        assert_eq!(metadata.lock().unwrap().info(), Some(&info[..]));
+    }
+
+    #[test]
+    fn request_again_after_a_corrupt_peer() {
+        let info = b"d4:name1:ae".to_vec();
+        let metadata = Arc::new(Mutex::new(Metadata::new(hash_info(&info))));
+        let mut corrupt = UtMetadata::new(Arc::clone(&metadata));
+        let mut good = UtMetadata::new(Arc::clone(&metadata));
+        let request = vec![b"d8:msg_typei0e5:piecei0ee".to_vec()];
+
+        assert_eq!(corrupt.on_handshake(&handshake(Some(11))), request);
+        assert_eq!(good.on_handshake(&handshake(Some(11))), request);
+        assert!(good.poll().is_empty());
+
+        let data = |info: &[u8]| {
+            let mut data = b"d8:msg_typei1e5:piecei0e10:total_sizei11ee".to_vec();
+            data.extend_from_slice(info);
+            data
+        };
+        assert_eq!(corrupt.on_message(&data(b"d4:name1:be")), Ok(Vec::new()));
+        assert_eq!(metadata.lock().unwrap().info(), None);
+
+        // The answer to the first request arrives late and is dropped
+        assert_eq!(good.on_message(&data(&info)), Ok(Vec::new()));
+        assert_eq!(metadata.lock().unwrap().info(), None);
+
+        assert_eq!(good.poll(), request);
+        assert!(good.poll().is_empty());
+        assert_eq!(good.on_message(&data(&info)), Ok(Vec::new()));
+        assert_eq!(metadata.lock().unwrap().info(), Some(&info[..]));
+        assert!(corrupt.poll().is_empty());
+    }
+
+    #[test]
+    fn serve_metadata_to_a_peer() {
+        let info = b"d4:name1:ae".to_vec();
+        let metadata = Metadata::complete(hash_info(&info), info.clone()).unwrap();
+        let mut handler = UtMetadata::new(Arc::new(Mutex::new(metadata)));
+
+        let mut ours = ExtendedHandshake::default();
+        handler.extend_handshake(&mut ours);
+        assert_eq!(ours.metadata_size, Some(11));
+        assert!(handler.on_handshake(&handshake(Some(99))).is_empty());
+
+        let mut want = b"d8:msg_typei1e5:piecei0e10:total_sizei11ee".to_vec();
+        want.extend_from_slice(&info);
+        assert_eq!(
+            handler.on_message(b"d8:msg_typei0e5:piecei0ee"),
+            Ok(vec![want])
+        );
+        assert_eq!(
+            handler.on_message(b"d8:msg_typei0e5:piecei1ee"),
+            Ok(vec![b"d8:msg_typei2e5:piecei1ee".to_vec()])
+        );
+        assert_eq!(
+            handler.on_message(b"i0e"),
+            Err(ExtensionError::InvalidMessage(NAME))
+        );
+    }
+}
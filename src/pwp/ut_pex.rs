@@ -0,0 +1,246 @@
+use crate::bencode;
+use crate::peer::peer_handler::Peer;
+use crate::peer::peer_source::PeerSource;
+use crate::pwp::extension_error::ExtensionError;
+use crate::pwp::extension_handler::ExtensionHandler;
+use crate::pwp::pex_swarm::PexSwarm;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Name of the extension in the extended handshake.
+pub const NAME: &str = "ut_pex";
+/// Least time between two messages sent to a peer.
+pub const PEX_INTERVAL: Duration = Duration::from_secs(60);
+/// Most peers added, and dropped, in a message.
+pub const MAX_PEX_PEERS: usize = 50;
+
+/// Peers added and dropped since the last message, in the compact
+/// format. Every added peer has a byte of flags.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct PexMessage {
+    #[serde(default, with = "crate::bencode::bytes")]
+    added: Vec<u8>,
+    #[serde(default, rename = "added.f", with = "crate::bencode::bytes")]
+    added_flags: Vec<u8>,
+    #[serde(default, with = "crate::bencode::bytes")]
+    added6: Vec<u8>,
+    #[serde(default, rename = "added6.f", with = "crate::bencode::bytes")]
+    added6_flags: Vec<u8>,
+    #[serde(default, with = "crate::bencode::bytes")]
+    dropped: Vec<u8>,
+    #[serde(default, with = "crate::bencode::bytes")]
+    dropped6: Vec<u8>,
+}
+
+/// # UtPex
+/// Exchanges the peers of a torrent with a peer (BEP 11). The peers
+/// the client is connected to are sent at most once every
+/// [`PEX_INTERVAL`], as the changes since the last message; the ones
+/// the peer sends are added to the swarm for the download to try. The
+/// messages the peer sends sooner than that are ignored.
+pub struct UtPex {
+    swarm: PexSwarm,
+    /// Address of the peer, which isn't sent back to it
+    peer: SocketAddr,
+    /// Peers the peer was told about
+    sent: HashSet<SocketAddr>,
+    last_sent: Option<Instant>,
+    last_received: Option<Instant>,
+}
+
+impl UtPex {
+    pub fn new(swarm: PexSwarm, peer: SocketAddr) -> Self {
+        Self {
+            swarm,
+            peer,
+            sent: HashSet::new(),
+            last_sent: None,
+            last_received: None,
+        }
+    }
+
+    fn receive_at(&mut self, payload: &[u8], now: Instant) -> Result<(), ExtensionError> {
+        let msg: PexMessage =
+            bencode::from_bytes(payload).map_err(|_| ExtensionError::InvalidMessage(NAME))?;
+        if self
+            .last_received
+            .is_some_and(|last| now.duration_since(last) < PEX_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_received = Some(now);
+
+        // Dropped peers are left for the download to find out about
+        let peers = Peer::new_compact_list(&msg.added, false)
+            .into_iter()
+            .take(MAX_PEX_PEERS)
+            .chain(
+                Peer::new_compact_list(&msg.added6, true)
+                    .into_iter()
+                    .take(MAX_PEX_PEERS),
+            )
+            .map(|peer| Peer {
+                source: PeerSource::Pex,
+                ..peer
+            })
+            .filter(|peer| peer.addr() != Some(self.peer))
+            .collect();
+        self.swarm.found(peers);
+        Ok(())
+    }
+
+    fn poll_at(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        if self
+            .last_sent
+            .is_some_and(|last| now.duration_since(last) < PEX_INTERVAL)
+        {
+            return Vec::new();
+        }
+
+        let connected = self.swarm.connected();
+        let added: Vec<(SocketAddr, u8)> = connected
+            .iter()
+            .filter(|(addr, _)| **addr != self.peer && !self.sent.contains(addr))
+            .map(|(addr, flags)| (*addr, *flags))
+            .take(MAX_PEX_PEERS)
+            .collect();
+        let dropped: Vec<SocketAddr> = self
+            .sent
+            .iter()
+            .filter(|addr| !connected.contains_key(addr))
+            .copied()
+            .take(MAX_PEX_PEERS)
+            .collect();
+        if added.is_empty() && dropped.is_empty() {
+            return Vec::new();
+        }
+
+        let mut msg = PexMessage::default();
+        for (addr, flags) in &added {
+            self.sent.insert(*addr);
+            match addr {
+                SocketAddr::V4(_) => {
+                    msg.added.extend(compact(addr));
+                    msg.added_flags.push(*flags);
+                }
+                SocketAddr::V6(_) => {
+                    msg.added6.extend(compact(addr));
+                    msg.added6_flags.push(*flags);
+                }
+            }
+        }
+        for addr in &dropped {
+            self.sent.remove(addr);
+            match addr {
+                SocketAddr::V4(_) => msg.dropped.extend(compact(addr)),
+                SocketAddr::V6(_) => msg.dropped6.extend(compact(addr)),
+            }
+        }
+        self.last_sent = Some(now);
+        bencode::to_bytes(&msg).map(|m| vec![m]).unwrap_or_default()
+    }
+}
+
+impl ExtensionHandler for UtPex {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn on_message(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, ExtensionError> {
+        self.receive_at(payload, Instant::now())?;
+        Ok(Vec::new())
+    }
+
+    fn poll(&mut self) -> Vec<Vec<u8>> {
+        self.poll_at(Instant::now())
+    }
+}
+
+/// Address and port of the peer, in network byte order.
+fn compact(addr: &SocketAddr) -> Vec<u8> {
+    let mut bytes = match addr {
+        SocketAddr::V4(a) => a.ip().octets().to_vec(),
+        SocketAddr::V6(a) => a.ip().octets().to_vec(),
+    };
+    bytes.extend_from_slice(&addr.port().to_be_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pwp::pex_swarm::FLAG_REACHABLE;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn send_the_changes_of_the_swarm_once_per_interval() {
+        let swarm = PexSwarm::new();
+        let mut handler = UtPex::new(swarm.clone(), addr("10.0.0.9:6881"));
+        let _own = swarm.join(addr("10.0.0.9:6881"), FLAG_REACHABLE);
+        let member = swarm.join(addr("10.0.0.1:6881"), FLAG_REACHABLE);
+        let start = Instant::now();
+
+        assert_eq!(
+            handler.poll_at(start),
+            vec![b"d5:added6:\x0a\x00\x00\x01\x1a\xe17:added.f1:\x106:added60:8:added6.f0:7:dropped0:8:dropped60:e".to_vec()]
+        );
+
+        drop(member);
+        assert!(handler.poll_at(start + PEX_INTERVAL / 2).is_empty());
+        assert_eq!(
+            handler.poll_at(start + PEX_INTERVAL),
+            vec![b"d5:added0:7:added.f0:6:added60:8:added6.f0:7:dropped6:\x0a\x00\x00\x01\x1a\xe18:dropped60:e".to_vec()]
+        );
+        assert!(handler.poll_at(start + PEX_INTERVAL * 3).is_empty());
+    }
+
+    #[test]
+    fn receive_peers_into_the_swarm() {
+        let swarm = PexSwarm::new();
+        let mut handler = UtPex::new(swarm.clone(), addr("10.0.0.9:6881"));
+
+        let mut msg = b"d5:added12:\x0a\x00\x00\x01\x1a\xe1\x0a\x00\x00\x09\x1a\xe17:added.f2:\x10\x006:added618:".to_vec();
+        msg.extend_from_slice(&std::net::Ipv6Addr::LOCALHOST.octets());
+        msg.extend_from_slice(b"\x00\x50e");
+        assert_eq!(handler.on_message(&msg), Ok(Vec::new()));
+
+        let found = swarm.take_found();
+        let addrs: Vec<_> = found.iter().flat_map(Peer::addr).collect();
+        assert_eq!(addrs, vec![addr("10.0.0.1:6881"), addr("[::1]:80")]);
+        assert!(found.iter().all(|p| p.source == PeerSource::Pex));
+        assert!(swarm.take_found().is_empty());
+
+        assert_eq!(
+            handler.on_message(b"le"),
+            Err(ExtensionError::InvalidMessage(NAME))
+        );
+    }
+
+    #[test]
+    fn messages_sooner_than_the_interval_are_ignored() {
+        let swarm = PexSwarm::new();
+        let mut handler = UtPex::new(swarm.clone(), addr("10.0.0.9:6881"));
+        let msg = |port: u8| {
+            let mut msg = b"d5:added6:\x0a\x00\x00\x01\x1a".to_vec();
+            msg.extend_from_slice(&[port, b'e']);
+            msg
+        };
+        let start = Instant::now();
+
+        assert_eq!(handler.receive_at(&msg(1), start), Ok(()));
+        assert_eq!(
+            handler.receive_at(&msg(2), start + PEX_INTERVAL / 2),
+            Ok(())
+        );
+        assert_eq!(handler.receive_at(&msg(3), start + PEX_INTERVAL), Ok(()));
+
+        let found = swarm.take_found();
+        let addrs: Vec<_> = found.iter().flat_map(Peer::addr).collect();
+        assert_eq!(addrs, vec![addr("10.0.0.1:6657"), addr("10.0.0.1:6659")]);
+    }
+}
@@ -9,4 +9,6 @@ pub enum ServerError {
     PieceError,
     NotExpectedMessageError,
     HandshakeError,
+    /// None of the configured ports is free
+    NoFreePort,
 }
@@ -1,9 +1,10 @@
-use crate::{client::bitfield::BitField, utils};
+use crate::client::bitfield::BitField;
 use log::{error, info};
 use std::{
     fs,
     io::{Read, Seek, SeekFrom},
     net::{SocketAddr, TcpListener},
+    ops::RangeInclusive,
     sync::{Arc, Mutex},
     thread,
 };
@@ -11,7 +12,18 @@ use std::{
 use crate::{
     client::torrent_file::TorrentFile,
     log::logger::LogHandle,
-    pwp::{message::PWPMessage, protocol::PWPStream},
+    peer::peer_id::session_peer_id,
+    pwp::{
+        allowed_fast::{allowed_fast_set, ALLOWED_FAST_COUNT},
+        extension_registry::ExtensionRegistry,
+        message::PWPMessage,
+        metadata::Metadata,
+        pex_swarm::PexSwarm,
+        protocol::PWPStream,
+        reserved::{Capability, Reserved},
+        ut_metadata::UtMetadata,
+        ut_pex::UtPex,
+    },
 };
 
 use super::server_error::ServerError;
@@ -38,15 +50,18 @@ impl Server {
     }
 
     /// Starts the server process, the sever will be listening for
-    /// requests at the port specified in the config file
+    /// requests at the first free port of `ports`. Returns that port.
     pub fn run(
         &mut self,
-        port: u16,
+        ports: RangeInclusive<u16>,
         download: String,
         mut logger: LogHandle,
-    ) -> Result<(), ServerError> {
-        let listener = TcpListener::bind(format!("{}:{}", LISTENER_IP, port))
-            .map_err(|_| ServerError::StreamError)?; // el puerto lo tengo que configurar
+    ) -> Result<u16, ServerError> {
+        let listener = bind(LISTENER_IP, ports)?;
+        let port = listener
+            .local_addr()
+            .map_err(|_| ServerError::StreamError)?
+            .port();
         info!("Listening at: {}:{}", LISTENER_IP, port);
         logger.info(&format!("Listening at: {}:{}", LISTENER_IP, port));
 
@@ -61,6 +76,7 @@ impl Server {
                         logger.clone(),
                         torrents.clone(),
                         download.clone(),
+                        port,
                     );
                 }
                 Err(_) => continue, //log
@@ -68,10 +84,18 @@ impl Server {
         }));
         self.thread = thread;
 
-        Ok(())
+        Ok(port)
     }
 }
 
+/// Binds the first free port of `ports`.
+fn bind(ip: &str, ports: RangeInclusive<u16>) -> Result<TcpListener, ServerError> {
+    ports
+        .into_iter()
+        .find_map(|port| TcpListener::bind((ip, port)).ok())
+        .ok_or(ServerError::NoFreePort)
+}
+
 // impl Drop for Server {
 //     fn drop(&mut self) {
 //         if let Some(sender) = self.sender.take() {
@@ -86,12 +110,18 @@ impl Server {
 /// Establishes the connection with the peer that sent a
 /// handshake. Returns `None` if the connection is cut off or if the
 /// handshake read is in someway invalid
-fn init_connection(stream: std::net::TcpStream) -> Option<(PWPStream, PWPMessage, Vec<u8>)> {
-    let mut pwp_stream = PWPStream(stream);
+fn init_connection(
+    stream: std::net::TcpStream,
+    port: u16,
+) -> Option<(PWPStream, PWPMessage, Vec<u8>)> {
+    let mut pwp_stream = PWPStream::new(stream, ExtensionRegistry::new(port));
     let handshake = pwp_stream.read_handshake().ok()?;
 
     let handshake_msg = match handshake {
-        PWPMessage::Handshake(h, p) => (PWPMessage::Handshake(h.clone(), p), h),
+        PWPMessage::Handshake(_, h, _) => (
+            PWPMessage::Handshake(Reserved::client(), h.clone(), session_peer_id().to_vec()),
+            h,
+        ),
         _ => return None,
     };
 
@@ -105,14 +135,39 @@ fn handle_connection(
     mut logger: LogHandle,
     torrents: Arc<Mutex<Vec<TorrentFile>>>,
     download: String,
+    port: u16,
 ) -> Option<thread::JoinHandle<()>> {
-    if let Some((mut pwp_stream, handshake_msg, info_hash)) = init_connection(stream) {
+    if let Some((mut pwp_stream, handshake_msg, info_hash)) = init_connection(stream, port) {
+        if let Some(metadata) = torrent_metadata(&torrents, &info_hash) {
+            pwp_stream
+                .extensions_mut()
+                .register(Box::new(UtMetadata::new(Arc::new(Mutex::new(metadata)))));
+        }
+        if let Some(swarm) = torrent_pex(&torrents, &info_hash) {
+            pwp_stream
+                .extensions_mut()
+                .register(Box::new(UtPex::new(swarm, addr)));
+        }
         establish_connection(&mut pwp_stream, addr, handshake_msg, logger.clone());
         let bitfield = match generate_bitfield(&torrents, &info_hash, &mut logger) {
             Some(b) => b,
             None => return None,
         };
+        let fast = pwp_stream.peer_supports(Capability::Fast);
+        let allowed_fast = if fast {
+            allowed_fast_set(
+                addr.ip(),
+                &info_hash,
+                bitfield.pieces() as u32,
+                ALLOWED_FAST_COUNT,
+            )
+        } else {
+            Vec::new()
+        };
         send_bitfield(bitfield, &mut pwp_stream, addr, &mut logger);
+        for index in &allowed_fast {
+            pwp_stream.send(PWPMessage::AllowedFast(*index)).ok()?;
+        }
 
         let child = thread::spawn(move || {
             let mut peer_interested = false;
@@ -132,21 +187,33 @@ fn handle_connection(
                                 peer_interested = false;
                             }
                             PWPMessage::Request(index, begin, length) => {
-                                make_request(
-                                    (am_choking, peer_interested),
-                                    torrents.clone(),
-                                    info_hash.clone().clone(),
-                                    download.clone(),
-                                    logger.clone(),
-                                    (index, begin, length),
-                                    &mut pwp_stream,
-                                );
+                                let allowed = (!am_choking && peer_interested)
+                                    || allowed_fast.contains(&index);
+                                let sent = allowed
+                                    && make_request(
+                                        torrents.clone(),
+                                        info_hash.clone().clone(),
+                                        download.clone(),
+                                        logger.clone(),
+                                        (index, begin, length),
+                                        &mut pwp_stream,
+                                    )
+                                    .is_some();
+                                // With the fast extension the requests
+                                // that won't be answered are rejected
+                                if !sent
+                                    && fast
+                                    && pwp_stream
+                                        .send(PWPMessage::Reject(index, begin, length))
+                                        .is_err()
+                                {
+                                    break;
+                                }
                             }
                             _ => continue,
                         }
                     }
 
-                    Err(crate::pwp::protocol::PWPError::WrongSizeRead) => continue,
                     Err(_) => {
                         error!("Couldn't read from stream");
                         logger.error("Couldn't read from stream");
@@ -169,14 +236,18 @@ fn handle_connection(
     }
 }
 
-/// Sends handshake to connected peer.
+/// Sends handshake to connected peer, followed by the extended
+/// handshake if the peer supports it.
 fn establish_connection(
     stream: &mut PWPStream,
     addr: SocketAddr,
     handshake: PWPMessage,
     mut logger: LogHandle,
 ) -> Option<()> {
-    match stream.send(handshake) {
+    match stream
+        .send(handshake)
+        .and_then(|_| stream.send_extended_handshake())
+    {
         Ok(_) => {
             info!("Connection established with: {}", addr);
             logger.info(&format!("Connection established with: {}", addr));
@@ -190,6 +261,24 @@ fn establish_connection(
     }
 }
 
+/// Info dictionary of the torrent, to send with `ut_metadata`
+fn torrent_metadata(torrents: &Arc<Mutex<Vec<TorrentFile>>>, info_hash: &[u8]) -> Option<Metadata> {
+    torrents
+        .lock()
+        .ok()?
+        .iter()
+        .find(|t| t.get_info_hash() == *info_hash)?
+        .metadata()
+}
+
+/// Peers of the torrent to exchange with `ut_pex`. Returns [`None`]
+/// for private torrents.
+fn torrent_pex(torrents: &Arc<Mutex<Vec<TorrentFile>>>, info_hash: &[u8]) -> Option<PexSwarm> {
+    let torrents = torrents.lock().ok()?;
+    let torrent = torrents.iter().find(|t| t.get_info_hash() == *info_hash)?;
+    (!torrent.is_private()).then(|| torrent.pex.clone())
+}
+
 /// Generates the bitfield, according to the pieces the client haves
 #[cfg(not(feature = "server-demo"))]
 fn generate_bitfield(
@@ -240,14 +329,25 @@ fn generate_bitfield(
     Some(bitfield)
 }
 
-/// Sends bitfield to the connected peer
+/// Sends bitfield to the connected peer. If the peer supports the
+/// fast extension `HaveAll` or `HaveNone` are sent instead when they
+/// apply.
 fn send_bitfield(
     bitfield: BitField,
     stream: &mut PWPStream,
     addr: SocketAddr,
     logger: &mut LogHandle,
 ) -> Option<()> {
-    match stream.send(PWPMessage::Bitfield(bitfield.bits())) {
+    let msg = if !stream.peer_supports(Capability::Fast) {
+        PWPMessage::Bitfield(bitfield.bits())
+    } else if bitfield.has_all_pieces() {
+        PWPMessage::HaveAll
+    } else if !bitfield.has_any_piece() {
+        PWPMessage::HaveNone
+    } else {
+        PWPMessage::Bitfield(bitfield.bits())
+    };
+    match stream.send(msg) {
         Ok(_) => {
             info!("Bitfield sent: to {}", addr);
             logger.info(&format!("Bitfield sent to: {}", addr));
@@ -261,9 +361,9 @@ fn send_bitfield(
     }
 }
 
-/// Sends the requested block to the peer
+/// Sends the requested block to the peer. Returns `None` if it
+/// couldn't be sent.
 fn make_request(
-    connection_state: (bool, bool),
     torrents: Arc<Mutex<Vec<TorrentFile>>>,
     info_hash: Vec<u8>,
     download: String,
@@ -272,25 +372,25 @@ fn make_request(
     stream: &mut PWPStream,
 ) -> Option<()> {
     let (index, begin, length) = params;
-    let (am_choking, peer_interested) = connection_state;
-    if !am_choking && peer_interested {
-        let buf = block(
-            &torrents,
-            &info_hash,
-            &mut logger,
-            &download,
-            index,
-            begin,
-            length,
-        )?;
-        stream
-            .send(PWPMessage::Piece(index, begin, buf))
-            .map_err(|_| {
-                error!("Couldn't send block");
-                logger.error("Couldn't send block");
-            })
-            .ok()?;
-        let filename = filename(&torrents, &info_hash, &mut logger)?;
+    let buf = block(
+        &torrents,
+        &info_hash,
+        &mut logger,
+        &download,
+        index,
+        begin,
+        length,
+    )?;
+    let sent = buf.len() as u64;
+    stream
+        .send(PWPMessage::Piece(index, begin, buf))
+        .map_err(|_| {
+            error!("Couldn't send block");
+            logger.error("Couldn't send block");
+        })
+        .ok()?;
+    add_uploaded(&torrents, &info_hash, sent);
+    if let Some(filename) = filename(&torrents, &info_hash, &mut logger) {
         // info!(
         //     "Block {} of Piece {} from {} sent",
         //     begin / length,
@@ -307,6 +407,15 @@ fn make_request(
     Some(())
 }
 
+/// Adds the bytes sent to the upload statistics of the torrent
+fn add_uploaded(torrents: &Arc<Mutex<Vec<TorrentFile>>>, info_hash: &[u8], sent: u64) {
+    if let Ok(mut t) = torrents.lock() {
+        if let Some(t) = t.iter_mut().find(|t| t.get_info_hash() == *info_hash) {
+            t.uploaded += sent;
+        }
+    }
+}
+
 /// Obtains the torrent filename
 fn filename(
     torrents: &Arc<Mutex<Vec<TorrentFile>>>,
@@ -317,7 +426,7 @@ fn filename(
         Ok(t) => t
             .iter()
             .find(|t| t.get_info_hash() == *info_hash)
-            .map(|t| utils::get_info_from_torrentfile(t.metainfo.info.clone()).name),
+            .map(|t| t.metainfo.info().name()),
         Err(_) => {
             error!("Poisoned Mutex");
             logger.error("Poisoned Mutex");
@@ -326,7 +435,8 @@ fn filename(
     }
 }
 
-/// Obtains the requested block
+/// Obtains the requested block. Returns `None` if it's empty or if it
+/// goes past the end of the piece.
 fn block(
     torrents: &Arc<Mutex<Vec<TorrentFile>>>,
     info_hash: &[u8],
@@ -336,6 +446,9 @@ fn block(
     begin: u32,
     length: u32,
 ) -> Option<Vec<u8>> {
+    if length == 0 {
+        return None;
+    }
     let filename = filename(torrents, info_hash, logger);
     let mut fi = fs::File::open(format!("{}piece{}-{}", download, index, filename?))
         .map_err(|e| {
@@ -351,15 +464,78 @@ fn block(
         })
         .ok()?;
 
-    let mut buf = Vec::with_capacity(length as usize);
+    // The block is only as large as what's left of the piece, so a
+    // large length doesn't allocate more than that
+    let mut buf = Vec::new();
     let mut handle = fi.take(length as u64);
     handle
-        .read(&mut buf)
+        .read_to_end(&mut buf)
         .map_err(|e| {
             error!("{} occurred while writing block: {}", e, index);
             logger.error(&format!("{} occurred while writing block: {}", e, index));
         })
         .ok()?;
 
-    Some(buf)
+    (buf.len() == length as usize).then_some(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bind_falls_back_to_the_next_free_port() {
+        let taken = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        let listener = bind("127.0.0.1", port..=port.saturating_add(20)).unwrap();
+        let bound = listener.local_addr().unwrap().port();
+
+        assert_ne!(bound, port);
+        assert!(bound > port);
+        assert_eq!(
+            bind("127.0.0.1", port..=port).unwrap_err(),
+            ServerError::NoFreePort
+        );
+    }
+
+    #[test]
+    fn serve_a_block_and_count_it_as_uploaded() {
+        let download = std::env::temp_dir().join(format!("server_block_{}", std::process::id()));
+        fs::create_dir_all(&download).unwrap();
+        fs::write(download.join("piece0-a"), b"abcdefgh").unwrap();
+        let download = format!("{}/", download.display());
+        let torrent = b"d8:announce17:http://a/announce4:infod6:lengthi8e4:name1:a12:piece lengthi8e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = crate::torrent::metainfo::read_torrent(&torrent[..]).unwrap();
+        let torrent = TorrentFile::from_metainfo("a.torrent".to_string(), metainfo).unwrap();
+        let info_hash = torrent.get_info_hash();
+        let torrents = Arc::new(Mutex::new(vec![torrent]));
+        let logger = crate::log::logger::Logger::new(std::io::sink());
+
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let peer = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut stream = PWPStream::new(listener.accept().unwrap().0, ExtensionRegistry::new(0));
+        let mut peer = PWPStream::new(peer, ExtensionRegistry::new(0));
+        let mut request = |begin, length| {
+            make_request(
+                torrents.clone(),
+                info_hash.clone(),
+                download.clone(),
+                logger.new_handler(),
+                (0, begin, length),
+                &mut stream,
+            )
+        };
+
+        assert_eq!(request(2, 4), Some(()));
+        assert_eq!(request(0, 0), None);
+        assert_eq!(request(6, 4), None);
+
+        assert_eq!(
+            peer.read().unwrap(),
+            PWPMessage::Piece(0, 2, b"cdef".to_vec())
+        );
+        assert_eq!(torrents.lock().unwrap()[0].uploaded, 4);
+        fs::remove_dir_all(download).unwrap();
+    }
 }
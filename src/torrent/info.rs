@@ -1,8 +1,11 @@
 use crate::bencode::bencoded_value::BencodedValue;
 use crate::torrent::info_builder::InfoBuilder;
 use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 
-static FILEINFO_REQUIRED_KEYS: [&[u8]; 4] = [b"piece length", b"pieces", b"name", b"length"];
+static INFO_REQUIRED_KEYS: [&[u8]; 3] = [b"piece length", b"pieces", b"name"];
+
+static FILE_REQUIRED_KEYS: [&[u8]; 2] = [b"length", b"path"];
 
 /// This enum represents all the possible variants of the info
 /// dictionary. The empty variant has no use outside initialization.
@@ -12,7 +15,8 @@ pub enum InfoMode {
     Empty,
     /// Single file mode (The torrent doesn't have a directory structure)
     SingleFile(SingleFileData),
-    // TODO: Missing MultipleFile variant
+    /// Multiple file mode (The torrent describes a directory)
+    MultipleFile(MultipleFileData),
 }
 
 /// Wrapper over the the [`InfoMode`] enum.
@@ -43,6 +47,47 @@ pub struct SingleFileData {
     /// obtain peers from other peers (One could interpret private as
     /// "no external peer source")
     pub private: Option<bool>,
+    /// Keys that are not part of the specification, kept so the
+    /// dictionary can be written back without changing the info hash
+    pub extra: Vec<(Vec<u8>, BencodedValue)>,
+}
+
+/// Container for the data in the dictionary associated with the info
+/// key when in Multiple File Mode. The fields: `files`, `name`,
+/// `piece_length` and `pieces` must be always present
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MultipleFileData {
+    /// One entry for each file in the torrent, in the order in which
+    /// they are concatenated to form the pieces
+    pub files: Vec<FileData>,
+    /// The name of the directory in which to store all the files
+    /// (advisory)
+    pub name: String,
+    /// Number of bytes in each piece
+    pub piece_length: i64,
+    /// String consisting of the concatenation of all 20-byte SHA1
+    /// hash values (one per piece)
+    pub pieces: Vec<u8>,
+    /// Same meaning as in [`SingleFileData`]
+    pub private: Option<bool>,
+    /// Same meaning as in [`SingleFileData`]
+    pub extra: Vec<(Vec<u8>, BencodedValue)>,
+}
+
+/// Each of the dictionaries in the `files` list of the multiple file
+/// mode. The fields `length` and `path` must be always present
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FileData {
+    /// Length of the file in bytes
+    pub length: i64,
+    /// 32 character hexadecimal string corresponding to the MD5 sum
+    /// of the file
+    pub md5sum: Option<Vec<u8>>,
+    /// Path of the file, one element per directory and the last one
+    /// being the filename
+    pub path: Vec<String>,
+    /// Keys that are not part of the specification
+    pub extra: Vec<(Vec<u8>, BencodedValue)>,
 }
 
 impl Info {
@@ -54,11 +99,15 @@ impl Info {
             .into_iter()
             .collect::<HashMap<BencodedValue, BencodedValue>>();
 
-        let mut required = FILEINFO_REQUIRED_KEYS
+        let mut required = INFO_REQUIRED_KEYS
             .iter()
             .map(|v| BencodedValue::ByteString(v.to_vec()));
 
-        let has_required = required.all(|k| dict.contains_key(&k));
+        let has_length = dict.contains_key(&BencodedValue::ByteString(b"length".to_vec()));
+        let has_files = dict.contains_key(&BencodedValue::ByteString(b"files".to_vec()));
+        // Exactly one of length (single file) or files (multiple
+        // file) must be present
+        let has_required = required.all(|k| dict.contains_key(&k)) && (has_length != has_files);
         if has_required {
             let mut info_build = InfoBuilder::new();
             for (k, v) in dict {
@@ -72,50 +121,256 @@ impl Info {
             None
         }
     }
-    /// Bencodes the contents of the struct returning a byte string
+    /// Bencodes the contents of the struct returning a byte string.
+    /// The dictionary is rebuilt in canonical form, to write back the
+    /// one of a torrent file use [`Metainfo::info_bytes`] instead.
+    ///
+    /// [`Metainfo::info_bytes`]: crate::torrent::metainfo::Metainfo::info_bytes
     pub fn bencode(&self) -> Vec<u8> {
+        self.bencoded_value()
+            .map(|v| v.encode())
+            .unwrap_or_default()
+    }
+
+    /// Returns the info dictionary as a [`BencodedValue`], or [`None`]
+    /// if the mode is [`InfoMode::Empty`]
+    pub fn bencoded_value(&self) -> Option<BencodedValue> {
         match self.0 {
-            InfoMode::Empty => Vec::new(),
-            InfoMode::SingleFile(ref s) => bencode_single_file(s),
+            InfoMode::Empty => None,
+            InfoMode::SingleFile(ref s) => Some(single_file_value(s)),
+            InfoMode::MultipleFile(ref m) => Some(multiple_file_value(m)),
+        }
+    }
+
+    /// Returns the advisory name of the torrent: the filename in
+    /// single file mode, or the directory name in multiple file mode
+    pub fn name(&self) -> String {
+        match &self.0 {
+            InfoMode::Empty => String::new(),
+            InfoMode::SingleFile(s) => s.name.clone(),
+            InfoMode::MultipleFile(m) => m.name.clone(),
+        }
+    }
+
+    /// Returns the total length in bytes of the torrent, in multiple
+    /// file mode it's the sum of the length of every file
+    pub fn length(&self) -> i64 {
+        match &self.0 {
+            InfoMode::Empty => 0,
+            InfoMode::SingleFile(s) => s.length,
+            InfoMode::MultipleFile(m) => m.files.iter().map(|f| f.length).sum(),
+        }
+    }
+
+    /// Returns the number of bytes in each piece
+    pub fn piece_length(&self) -> i64 {
+        match &self.0 {
+            InfoMode::Empty => 0,
+            InfoMode::SingleFile(s) => s.piece_length,
+            InfoMode::MultipleFile(m) => m.piece_length,
         }
     }
 
-    pub fn info(&self) -> Option<SingleFileData> {
+    /// Returns the concatenation of the SHA1 hashes of every piece
+    pub fn pieces(&self) -> &[u8] {
+        match &self.0 {
+            InfoMode::Empty => &[],
+            InfoMode::SingleFile(s) => &s.pieces,
+            InfoMode::MultipleFile(m) => &m.pieces,
+        }
+    }
+
+    /// Returns the value of the private flag
+    pub fn private(&self) -> Option<bool> {
         match &self.0 {
             InfoMode::Empty => None,
-            InfoMode::SingleFile(s) => Some(s.clone()),
+            InfoMode::SingleFile(s) => s.private,
+            InfoMode::MultipleFile(m) => m.private,
+        }
+    }
+
+    /// Returns the relative path and the length of every file in the
+    /// torrent, in the order in which they appear in the pieces. In
+    /// multiple file mode every path is prefixed by the directory
+    /// name.
+    pub fn files(&self) -> Vec<(PathBuf, i64)> {
+        match &self.0 {
+            InfoMode::Empty => Vec::new(),
+            InfoMode::SingleFile(s) => vec![(PathBuf::from(&s.name), s.length)],
+            InfoMode::MultipleFile(m) => m
+                .files
+                .iter()
+                .map(|f| {
+                    let path = f.path.iter().fold(PathBuf::from(&m.name), |p, e| p.join(e));
+                    (path, f.length)
+                })
+                .collect(),
         }
     }
 }
 
 /// Helper function for bencoding the [`Info`] struct when the mode is
 /// single file
-fn bencode_single_file(data: &SingleFileData) -> Vec<u8> {
-    let mut str = format!(
-        "d6:lengthi{}e4:name{}:{}12:piece lengthi{}e",
-        data.length,
-        data.name.len(),
-        data.name,
-        data.piece_length
-    );
+fn single_file_value(data: &SingleFileData) -> BencodedValue {
+    let mut dict = vec![
+        (
+            BencodedValue::ByteString(b"length".to_vec()),
+            BencodedValue::Integer(data.length),
+        ),
+        (
+            BencodedValue::ByteString(b"name".to_vec()),
+            BencodedValue::ByteString(data.name.as_bytes().to_vec()),
+        ),
+        (
+            BencodedValue::ByteString(b"piece length".to_vec()),
+            BencodedValue::Integer(data.piece_length),
+        ),
+        (
+            BencodedValue::ByteString(b"pieces".to_vec()),
+            BencodedValue::ByteString(data.pieces.clone()),
+        ),
+    ];
+    if let Some(ref sum) = data.md5sum {
+        dict.push((
+            BencodedValue::ByteString(b"md5sum".to_vec()),
+            BencodedValue::ByteString(sum.clone()),
+        ));
+    }
     if let Some(b) = data.private {
-        str = format!("{}7:privatei{}e", str, b as u8)
+        dict.push((
+            BencodedValue::ByteString(b"private".to_vec()),
+            BencodedValue::Integer(b as i64),
+        ));
     }
+    dict.extend(extra_entries(&data.extra));
 
-    // TODO: REFACTOR
-    let mut byte_string = str.as_bytes().to_vec();
-    if let Some(ref sum) = data.md5sum {
-        byte_string.append(&mut b"6:md5sum".to_vec());
-        byte_string.append(&mut sum.len().to_string().into_bytes());
-        byte_string.push(b':'); // ':' in ascii
-        byte_string.append(&mut sum.clone());
+    BencodedValue::Dictionary(dict)
+}
+
+/// Helper function for bencoding the [`Info`] struct when the mode is
+/// multiple file
+fn multiple_file_value(data: &MultipleFileData) -> BencodedValue {
+    let files = data
+        .files
+        .iter()
+        .map(|f| {
+            let mut dict = vec![(
+                BencodedValue::ByteString(b"length".to_vec()),
+                BencodedValue::Integer(f.length),
+            )];
+            if let Some(ref sum) = f.md5sum {
+                dict.push((
+                    BencodedValue::ByteString(b"md5sum".to_vec()),
+                    BencodedValue::ByteString(sum.clone()),
+                ));
+            }
+            dict.push((
+                BencodedValue::ByteString(b"path".to_vec()),
+                BencodedValue::List(
+                    f.path
+                        .iter()
+                        .map(|e| BencodedValue::ByteString(e.as_bytes().to_vec()))
+                        .collect(),
+                ),
+            ));
+            dict.extend(extra_entries(&f.extra));
+            BencodedValue::Dictionary(dict)
+        })
+        .collect();
+
+    let mut dict = vec![
+        (
+            BencodedValue::ByteString(b"files".to_vec()),
+            BencodedValue::List(files),
+        ),
+        (
+            BencodedValue::ByteString(b"name".to_vec()),
+            BencodedValue::ByteString(data.name.as_bytes().to_vec()),
+        ),
+        (
+            BencodedValue::ByteString(b"piece length".to_vec()),
+            BencodedValue::Integer(data.piece_length),
+        ),
+        (
+            BencodedValue::ByteString(b"pieces".to_vec()),
+            BencodedValue::ByteString(data.pieces.clone()),
+        ),
+    ];
+    if let Some(b) = data.private {
+        dict.push((
+            BencodedValue::ByteString(b"private".to_vec()),
+            BencodedValue::Integer(b as i64),
+        ));
+    }
+    dict.extend(extra_entries(&data.extra));
+
+    BencodedValue::Dictionary(dict)
+}
+
+/// Converts the keys that are not part of the specification back to
+/// dictionary entries
+fn extra_entries(
+    extra: &[(Vec<u8>, BencodedValue)],
+) -> impl Iterator<Item = (BencodedValue, BencodedValue)> + '_ {
+    extra
+        .iter()
+        .map(|(k, v)| (BencodedValue::ByteString(k.clone()), v.clone()))
+}
+
+/// Whether the element can be joined to the download directory
+/// without leaving it: a single file or directory name, not empty,
+/// `.`, `..`, an absolute path nor containing a separator.
+fn is_plain_path_element(element: &str) -> bool {
+    let mut components = Path::new(element).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) && !element.contains(['/', '\\'])
+}
+
+/// Creates a [`FileData`] from one of the dictionaries of the `files`
+/// list. Returns [`None`] if a required field is missing or has the
+/// wrong type, if the length is negative or if an element of the path
+/// could make the file be written outside the download directory.
+fn build_file_data(file: BencodedValue) -> Option<FileData> {
+    let dict = file
+        .dictionary()?
+        .into_iter()
+        .collect::<HashMap<BencodedValue, BencodedValue>>();
+
+    let has_required = FILE_REQUIRED_KEYS
+        .iter()
+        .map(|v| BencodedValue::ByteString(v.to_vec()))
+        .all(|k| dict.contains_key(&k));
+    if !has_required {
+        return None;
+    }
+
+    let mut data = FileData {
+        length: 0,
+        md5sum: None,
+        path: Vec::new(),
+        extra: Vec::new(),
+    };
+    for (k, v) in dict {
+        match &k.byte_string()?[..] {
+            b"length" => data.length = Some(v.integer()?).filter(|l| *l >= 0)?,
+            b"md5sum" => data.md5sum = Some(v.byte_string()?),
+            b"path" => {
+                data.path = v
+                    .list()?
+                    .into_iter()
+                    .map(|e| String::from_utf8(e.byte_string()?).ok())
+                    .collect::<Option<Vec<String>>>()?;
+                if data.path.is_empty() || !data.path.iter().all(|e| is_plain_path_element(e)) {
+                    return None;
+                }
+            }
+            key => data.extra.push((key.to_vec(), v)),
+        }
     }
-    byte_string.append(&mut b"6:pieces".to_vec());
-    byte_string.append(&mut data.pieces.len().to_string().into_bytes());
-    byte_string.push(b':');
-    byte_string.append(&mut data.pieces.clone());
-    byte_string.push(b'e');
-    byte_string
+    data.extra.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Some(data)
 }
 
 /// Helper function for building the [`Info Struct`]. Returns [`None`]
@@ -134,12 +389,20 @@ fn build_info_fields<'a>(
             let bytes = value.byte_string()?;
             info.pieces(bytes);
         }
+        // The name is the file, or the directory, created in the
+        // download directory
         b"name" => {
-            let bytes = value.byte_string()?;
-            info.name(String::from_utf8(bytes).ok()?);
+            let name = String::from_utf8(value.byte_string()?).ok()?;
+            if !is_plain_path_element(&name) {
+                return None;
+            }
+            info.name(name);
         }
         b"length" => {
             let l = value.integer()?;
+            if l < 0 {
+                return None;
+            }
             info.length(l);
         }
         b"private" => {
@@ -155,7 +418,19 @@ fn build_info_fields<'a>(
             let bytes = value.byte_string()?;
             info.md5sum(Some(bytes));
         }
-        _ => return None,
+        b"files" => {
+            let files = value
+                .list()?
+                .into_iter()
+                .map(build_file_data)
+                .collect::<Option<Vec<FileData>>>()?;
+            info.files(files);
+        }
+        // Unknown keys don't prevent the torrent from being used,
+        // they are kept to write the dictionary back
+        _ => {
+            info.extra(field.to_vec(), value);
+        }
     }
     Some(())
 }
@@ -163,6 +438,7 @@ fn build_info_fields<'a>(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bencode::parser;
     #[test]
     fn new_info_in_single_file_mode() {
         let dict = vec![
@@ -198,6 +474,7 @@ mod tests {
             match mode {
                 InfoMode::Empty => false,
                 InfoMode::SingleFile(_) => true,
+                InfoMode::MultipleFile(_) => false,
             }
         });
 
@@ -264,10 +541,147 @@ mod tests {
         let info = Info::new(dict);
         let got = info.map(|i| i.bencode());
         let want = Some(
-            b"d6:lengthi0e4:name4:file12:piece lengthi0e7:privatei1e6:md5sum4:aaaa6:pieces5:aaaaae"
+            b"d6:lengthi0e6:md5sum4:aaaa4:name4:file12:piece lengthi0e6:pieces5:aaaaa7:privatei1ee"
                 .to_vec(),
         );
 
         assert_eq!(got, want);
     }
+
+    #[test]
+    fn new_info_in_multiple_file_mode() {
+        let info = parser::parse(
+            b"d5:filesld6:lengthi3e4:pathl1:a5:b.txteed6:lengthi4e6:md5sum4:aaaa4:pathl1:ceee\
+              4:name3:dir12:piece lengthi2e6:pieces5:aaaaae"
+                .to_vec(),
+        )
+        .unwrap()
+        .dictionary()
+        .unwrap();
+
+        let got = Info::new(info).unwrap();
+        let want = Info(InfoMode::MultipleFile(MultipleFileData {
+            files: vec![
+                FileData {
+                    length: 3,
+                    md5sum: None,
+                    path: vec!["a".into(), "b.txt".into()],
+                    extra: Vec::new(),
+                },
+                FileData {
+                    length: 4,
+                    md5sum: Some(b"aaaa".to_vec()),
+                    path: vec!["c".into()],
+                    extra: Vec::new(),
+                },
+            ],
+            name: "dir".into(),
+            piece_length: 2,
+            pieces: b"aaaaa".to_vec(),
+            private: None,
+            extra: Vec::new(),
+        }));
+
+        assert_eq!(got, want);
+        assert_eq!(got.length(), 7);
+        assert_eq!(
+            got.files(),
+            vec![
+                (PathBuf::from("dir").join("a").join("b.txt"), 3),
+                (PathBuf::from("dir").join("c"), 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn new_returns_none_if_both_length_and_files_are_present() {
+        let info = parser::parse(
+            b"d5:filesld6:lengthi3e4:pathl1:aeee6:lengthi3e4:name3:dir\
+              12:piece lengthi2e6:pieces5:aaaaae"
+                .to_vec(),
+        )
+        .unwrap()
+        .dictionary()
+        .unwrap();
+
+        assert_eq!(Info::new(info), None);
+    }
+
+    #[test]
+    fn bencode_multiple_file_info_data() {
+        let want = b"d5:filesld6:lengthi3e4:pathl1:a5:b.txteed6:lengthi4e6:md5sum4:aaaa\
+                     4:pathl1:ceee4:name3:dir12:piece lengthi2e6:pieces5:aaaaa7:privatei0ee";
+        let info = parser::parse(want.to_vec()).unwrap().dictionary().unwrap();
+        let got = Info::new(info).unwrap().bencode();
+
+        assert_eq!(got, want.to_vec());
+    }
+
+    fn multiple_file_info(file: &[u8]) -> Option<Info> {
+        let mut s = b"d5:filesl".to_vec();
+        s.extend_from_slice(file);
+        s.extend_from_slice(b"e4:name3:dir12:piece lengthi2e6:pieces5:aaaaae");
+        Info::new(parser::parse(s).unwrap().dictionary().unwrap())
+    }
+
+    #[test]
+    fn new_accepts_plain_path_elements() {
+        let info = multiple_file_info(b"d6:lengthi3e4:pathl3:sub5:a.txtee").unwrap();
+
+        assert_eq!(
+            info.files(),
+            vec![(PathBuf::from("dir").join("sub").join("a.txt"), 3)]
+        );
+    }
+
+    #[test]
+    fn new_rejects_a_parent_directory_in_the_path() {
+        assert_eq!(
+            multiple_file_info(b"d6:lengthi3e4:pathl2:..5:a.txtee"),
+            None
+        );
+        assert_eq!(multiple_file_info(b"d6:lengthi3e4:pathl1:.ee"), None);
+    }
+
+    #[test]
+    fn new_rejects_an_absolute_path() {
+        assert_eq!(
+            multiple_file_info(b"d6:lengthi3e4:pathl4:/etc6:passwdee"),
+            None
+        );
+    }
+
+    #[test]
+    fn new_rejects_an_empty_path_element() {
+        assert_eq!(multiple_file_info(b"d6:lengthi3e4:pathl0:5:a.txtee"), None);
+    }
+
+    #[test]
+    fn new_rejects_a_separator_in_a_path_element() {
+        assert_eq!(multiple_file_info(b"d6:lengthi3e4:pathl3:a/bee"), None);
+        assert_eq!(multiple_file_info(b"d6:lengthi3e4:pathl3:a\\bee"), None);
+    }
+
+    #[test]
+    fn new_rejects_a_negative_length() {
+        assert_eq!(multiple_file_info(b"d6:lengthi-3e4:pathl1:aee"), None);
+
+        let single = b"d6:lengthi-1e4:name1:a12:piece lengthi2e6:pieces5:aaaaae";
+        let info = parser::parse(single.to_vec())
+            .unwrap()
+            .dictionary()
+            .unwrap();
+        assert_eq!(Info::new(info), None);
+    }
+
+    #[test]
+    fn new_rejects_a_name_that_is_not_a_plain_element() {
+        for name in [&b"2:.."[..], b"4:/tmp", b"0:", b"3:a/b"] {
+            let mut s = b"d6:lengthi1e4:name".to_vec();
+            s.extend_from_slice(name);
+            s.extend_from_slice(b"12:piece lengthi2e6:pieces5:aaaaae");
+            let info = parser::parse(s).unwrap().dictionary().unwrap();
+            assert_eq!(Info::new(info), None);
+        }
+    }
 }
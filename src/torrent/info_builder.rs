@@ -1,6 +1,9 @@
-use super::info::{Info, InfoMode, SingleFileData};
+use super::info::{FileData, Info, InfoMode, MultipleFileData, SingleFileData};
+use crate::bencode::bencoded_value::BencodedValue;
 
 pub struct InfoBuilder {
+    extra: Vec<(Vec<u8>, BencodedValue)>,
+    files: Option<Vec<FileData>>,
     length: i64,
     md5sum: Option<Vec<u8>>,
     name: String,
@@ -12,6 +15,8 @@ pub struct InfoBuilder {
 impl InfoBuilder {
     pub fn new() -> Self {
         Self {
+            extra: Vec::new(),
+            files: None,
             length: 0,
             md5sum: None,
             name: String::new(),
@@ -21,6 +26,17 @@ impl InfoBuilder {
         }
     }
 
+    /// Adds a key that is not part of the specification
+    pub fn extra(&'_ mut self, key: Vec<u8>, value: BencodedValue) -> &'_ mut Self {
+        self.extra.push((key, value));
+        self
+    }
+
+    pub fn files(&'_ mut self, f: Vec<FileData>) -> &'_ mut Self {
+        self.files = Some(f);
+        self
+    }
+
     pub fn length(&'_ mut self, l: i64) -> &'_ mut Self {
         self.length = l;
         self
@@ -59,10 +75,26 @@ impl InfoBuilder {
             piece_length: self.piece_length,
             pieces: self.pieces,
             private: self.private,
+            extra: self.extra,
+        }
+    }
+
+    pub fn multiple_file(self, files: Vec<FileData>) -> MultipleFileData {
+        MultipleFileData {
+            files,
+            name: self.name,
+            piece_length: self.piece_length,
+            pieces: self.pieces,
+            private: self.private,
+            extra: self.extra,
         }
     }
 
-    pub fn build(self) -> Info {
-        Info(InfoMode::SingleFile(self.single_file()))
+    pub fn build(mut self) -> Info {
+        self.extra.sort_by(|(a, _), (b, _)| a.cmp(b));
+        match self.files.take() {
+            Some(files) => Info(InfoMode::MultipleFile(self.multiple_file(files))),
+            None => Info(InfoMode::SingleFile(self.single_file())),
+        }
     }
 }
@@ -0,0 +1,162 @@
+use super::magnet_error::MagnetError;
+use super::metainfo::Metainfo;
+use crate::tracker::url_encoder::encoder::URLEncoded;
+use crate::utils;
+
+const SCHEME: &str = "magnet:?";
+const BTIH: &str = "urn:btih:";
+
+/// # Magnet
+/// A magnet link (BEP 9): the info hash of a torrent, and optionally
+/// its name and trackers. The info dictionary has to be fetched from
+/// the peers.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Magnet {
+    pub info_hash: [u8; 20],
+    /// Name to show until the info dictionary arrives, `dn`
+    pub name: Option<String>,
+    /// Tracker URLs, `tr`
+    pub trackers: Vec<String>,
+}
+
+impl Magnet {
+    /// Parses a `magnet:?xt=urn:btih:...` link. The info hash can be
+    /// hex or base32 encoded. Unknown parameters are ignored.
+    pub fn parse(uri: &str) -> Result<Self, MagnetError> {
+        let query = uri.trim().strip_prefix(SCHEME).ok_or(MagnetError::Scheme)?;
+        let mut info_hash = None;
+        let mut name = None;
+        let mut trackers = Vec::new();
+        for (key, value) in query.split('&').filter_map(|p| p.split_once('=')) {
+            match key {
+                "xt" if info_hash.is_none() => {
+                    if let Some(hash) = value.strip_prefix(BTIH) {
+                        info_hash = Some(parse_hash(hash)?);
+                    }
+                }
+                "dn" => name = decode(value),
+                "tr" => trackers.extend(decode(value)),
+                _ => (),
+            }
+        }
+        Ok(Self {
+            info_hash: info_hash.ok_or(MagnetError::MissingHash)?,
+            name,
+            trackers,
+        })
+    }
+
+    /// Builds the magnet link of a torrent, with its name and every
+    /// tracker.
+    pub fn from_metainfo(metainfo: &Metainfo) -> Self {
+        let mut trackers: Vec<String> = match metainfo.announce_list {
+            Some(ref tiers) => tiers.iter().flatten().cloned().collect(),
+            None => Vec::new(),
+        };
+        if !trackers.contains(&metainfo.announce) {
+            trackers.insert(0, metainfo.announce.clone());
+        }
+        Self {
+            info_hash: metainfo.info_hash(),
+            name: Some(metainfo.info().name()),
+            trackers,
+        }
+    }
+
+    /// Writes the link, with the info hash in hexadecimal.
+    pub fn to_uri(&self) -> String {
+        let mut uri = format!("{}xt={}{}", SCHEME, BTIH, utils::to_hex(&self.info_hash));
+        let encode = |s: &str| URLEncoded::encode(s.as_bytes()).map(|e| e.get_url());
+        if let Some(Ok(name)) = self.name.as_deref().map(encode) {
+            uri.push_str(&format!("&dn={}", name));
+        }
+        for tracker in self.trackers.iter().filter_map(|t| encode(t).ok()) {
+            uri.push_str(&format!("&tr={}", tracker));
+        }
+        uri
+    }
+}
+
+fn parse_hash(hash: &str) -> Result<[u8; 20], MagnetError> {
+    let bytes = match hash.len() {
+        40 => utils::from_hex(hash),
+        32 => utils::from_base32(hash),
+        _ => None,
+    };
+    bytes
+        .and_then(|b| b.try_into().ok())
+        .ok_or(MagnetError::InvalidHash)
+}
+
+/// Decodes a percent encoded parameter, where `+` is a space.
+fn decode(value: &str) -> Option<String> {
+    let bytes = URLEncoded(value.replace('+', " ")).decode()?;
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+
+    #[test]
+    fn parse_hex_magnet_with_name_and_trackers() {
+        let uri = format!(
+            "magnet:?xt=urn:btih:{}&dn=Some+file%20name&tr=http%3A%2F%2Ftracker.example%2Fannounce&tr=udp://other:80&x.pe=1.2.3.4:5",
+            HASH.to_uppercase()
+        );
+
+        assert_eq!(
+            Magnet::parse(&uri),
+            Ok(Magnet {
+                info_hash: utils::from_hex(HASH).unwrap().try_into().unwrap(),
+                name: Some("Some file name".to_string()),
+                trackers: vec![
+                    "http://tracker.example/announce".to_string(),
+                    "udp://other:80".to_string()
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn parse_base32_magnet() {
+        let magnet = Magnet::parse("magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK").unwrap();
+
+        assert_eq!(utils::to_hex(&magnet.info_hash), HASH);
+        assert_eq!(magnet.name, None);
+        assert!(magnet.trackers.is_empty());
+    }
+
+    #[test]
+    fn invalid_magnets() {
+        assert_eq!(Magnet::parse("http://x"), Err(MagnetError::Scheme));
+        assert_eq!(
+            Magnet::parse("magnet:?dn=x&xt=urn:sha1:abc"),
+            Err(MagnetError::MissingHash)
+        );
+        assert_eq!(
+            Magnet::parse("magnet:?xt=urn:btih:abcd"),
+            Err(MagnetError::InvalidHash)
+        );
+    }
+
+    #[test]
+    fn uri_round_trip() {
+        let magnet = Magnet {
+            info_hash: [0xab; 20],
+            name: Some("a b".to_string()),
+            trackers: vec!["http://t/announce?x=1".to_string()],
+        };
+
+        assert_eq!(
+            magnet.to_uri(),
+            format!(
+                "magnet:?xt=urn:btih:{}&dn=a%20b&tr=http%3A%2F%2Ft%2Fannounce%3Fx%3D1",
+                "ab".repeat(20)
+            )
+        );
+        assert_eq!(Magnet::parse(&magnet.to_uri()), Ok(magnet));
+    }
+}
@@ -0,0 +1,22 @@
+use std::fmt;
+
+/// Represents the possible errors found while parsing a magnet link.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MagnetError {
+    /// The link doesn't start with `magnet:?`
+    Scheme,
+    /// There is no `xt` parameter with a BitTorrent info hash
+    MissingHash,
+    /// The info hash isn't 40 hexadecimal or 32 base32 characters
+    InvalidHash,
+}
+
+impl fmt::Display for MagnetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MagnetError::Scheme => write!(f, "The link isn't a magnet link"),
+            MagnetError::MissingHash => write!(f, "The magnet link has no info hash"),
+            MagnetError::InvalidHash => write!(f, "The info hash of the magnet link is invalid"),
+        }
+    }
+}
@@ -1,7 +1,8 @@
-use super::info::{self, Info, SingleFileData};
+use super::info::{self, Info};
 use super::metainfo_builder::MetainfoBuilder;
 use super::torrent_error::TorrentError;
 use crate::bencode::{bencoded_value::BencodedValue, parser};
+use crate::utils;
 
 use std::collections::HashMap;
 use std::io;
@@ -14,8 +15,10 @@ static METAINFO_REQUIERED_KEYS: [&[u8]; 2] = [b"info", b"announce"];
 pub struct Metainfo {
     /// Announce URL of the tracker
     pub announce: String,
-    /// Extension to the oficial specification
-    pub announce_list: Option<Vec<String>>,
+    /// Extension to the oficial specification. Tiers of announce
+    /// URLs, the trackers in a tier are tried before moving on to the
+    /// next one
+    pub announce_list: Option<Vec<Vec<String>>>,
     /// Comments from the author
     pub comment: Option<String>,
     /// Name and version of the program used to create the .torrent
@@ -27,14 +30,43 @@ pub struct Metainfo {
     pub encoding: Option<String>,
     /// Describes the file(s) of the torrent. There are two
     /// possibilities: single file and multifile.
-    pub info: Info,
+    info: Info,
+    /// SHA1 hash of `info_bytes`
+    info_hash: [u8; 20],
+    /// The bencoded info dictionary, exactly as it appears in the
+    /// torrent file. It's written back as is, so the info hash never
+    /// changes
+    info_bytes: Vec<u8>,
+    /// Top level keys that are not part of the specification, kept so
+    /// the file can be written back
+    pub extra: Vec<(Vec<u8>, BencodedValue)>,
 }
 
 impl Metainfo {
-    /// Creates a new Metainfo structure from a bencoded dictionary.
+    /// Creates a new Metainfo with the info dictionary and its bytes,
+    /// or its bencoding if they aren't known, from which the info hash
+    /// is computed. The rest of the fields are empty.
+    pub(super) fn with_info(info: Info, info_bytes: Option<Vec<u8>>) -> Self {
+        let info_bytes = info_bytes.unwrap_or_else(|| info.bencode());
+        Self {
+            announce: String::new(),
+            announce_list: None,
+            comment: None,
+            created_by: None,
+            creation_date: None,
+            encoding: None,
+            info_hash: utils::hash_info(&info_bytes),
+            info_bytes,
+            info,
+            extra: Vec::new(),
+        }
+    }
+
+    /// Creates a new Metainfo structure from a bencoded dictionary,
+    /// along with the original bytes of its info dictionary.
     /// Returns [`Some`] if no errors occur while building the
     /// instance; otherwise returns [`None`].
-    fn new(bencoded_value: BencodedValue) -> Option<Self> {
+    fn new(bencoded_value: BencodedValue, info_bytes: &[u8]) -> Option<Self> {
         let dict = bencoded_value
             .dictionary()?
             .into_iter()
@@ -46,6 +78,7 @@ impl Metainfo {
             .all(|k| dict.contains_key(&k))
             .then(|| {
                 let mut metainfo = MetainfoBuilder::new();
+                metainfo.info_bytes(info_bytes.to_vec());
                 for (k, v) in dict {
                     // This will never be None, a key is always a
                     // BencodedValue::ByteString variant
@@ -58,8 +91,77 @@ impl Metainfo {
             .flatten()
     }
 
-    pub fn info(&self) -> Option<SingleFileData> {
-        self.info.info()
+    /// Describes the file(s) of the torrent.
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
+
+    /// Replaces the info dictionary. It's bencoded again, so the info
+    /// hash changes.
+    pub fn set_info(&mut self, info: Info) {
+        self.info_bytes = info.bencode();
+        self.info_hash = utils::hash_info(&self.info_bytes);
+        self.info = info;
+    }
+
+    /// SHA1 hash of the bencoded info dictionary, exactly as it
+    /// appears in the torrent file.
+    pub fn info_hash(&self) -> [u8; 20] {
+        self.info_hash
+    }
+
+    /// The bencoded info dictionary, exactly as it appears in the
+    /// torrent file.
+    pub fn info_bytes(&self) -> &[u8] {
+        &self.info_bytes
+    }
+
+    /// Bencodes every field of the struct, including the unknown
+    /// keys, returning the contents of a torrent file. The info
+    /// dictionary is written with its original bytes, so the info
+    /// hash is kept even if it wasn't canonical.
+    pub fn bencode(&self) -> Vec<u8> {
+        let string = |s: &str| BencodedValue::ByteString(s.as_bytes().to_vec());
+        let mut dict = vec![(string("announce"), string(&self.announce))];
+        if let Some(ref tiers) = self.announce_list {
+            let tiers = tiers
+                .iter()
+                .map(|t| BencodedValue::List(t.iter().map(|u| string(u)).collect()))
+                .collect();
+            dict.push((string("announce-list"), BencodedValue::List(tiers)));
+        }
+        if let Some(ref comment) = self.comment {
+            dict.push((string("comment"), string(comment)));
+        }
+        if let Some(ref created_by) = self.created_by {
+            dict.push((string("created by"), string(created_by)));
+        }
+        if let Some(date) = self.creation_date {
+            dict.push((string("creation date"), BencodedValue::Integer(date)));
+        }
+        if let Some(ref encoding) = self.encoding {
+            dict.push((string("encoding"), string(encoding)));
+        }
+        dict.extend(
+            self.extra
+                .iter()
+                .map(|(k, v)| (BencodedValue::ByteString(k.clone()), v.clone())),
+        );
+
+        let mut entries: Vec<(Vec<u8>, Vec<u8>)> = dict
+            .into_iter()
+            .flat_map(|(k, v)| Some((k.byte_string()?, v.encode())))
+            .collect();
+        entries.push((b"info".to_vec(), self.info_bytes.clone()));
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut bytes = vec![b'd'];
+        for (k, v) in entries {
+            bytes.extend(BencodedValue::ByteString(k).encode());
+            bytes.extend(v);
+        }
+        bytes.push(b'e');
+        bytes
     }
 }
 
@@ -73,9 +175,16 @@ impl Metainfo {
 pub fn read_torrent<R: io::Read>(mut reader: R) -> Result<Metainfo, TorrentError> {
     let mut buf = Vec::new();
     reader.read_to_end(&mut buf).map_err(TorrentError::File)?;
-    let bencoded_dictionary = parser::parse(buf).map_err(TorrentError::Parse)?;
+    let bencoded_dictionary = parser::parse_ref(&buf).map_err(TorrentError::Parse)?;
+    // The hash must be computed over the original bytes, re-encoding
+    // the info dictionary could change the order of the keys or drop
+    // the ones that aren't known.
+    let info = bencoded_dictionary
+        .get(b"info")
+        .map(|info| &buf[info.span()])
+        .ok_or(TorrentError::InvalidTorrent)?;
 
-    Metainfo::new(bencoded_dictionary).ok_or(TorrentError::InvalidTorrent)
+    Metainfo::new(bencoded_dictionary.to_value(), info).ok_or(TorrentError::InvalidTorrent)
 }
 
 /// Helper function for building the Metainfo struct. Returns [`None`]
@@ -97,19 +206,18 @@ fn build_metainfo_fields(
             metainfo.announce(String::from_utf8(bytes).ok()?);
         }
         b"announce-list" => {
-            let announce_list = v
-                .list()
+            let tiers = v
+                .list()?
                 .into_iter()
-                .flatten()
-                .filter_map(BencodedValue::list)
-                .flatten()
-                .map(|e| {
-                    let bytes = e.byte_string()?;
-                    String::from_utf8(bytes).ok()
+                .map(|tier| {
+                    tier.list()?
+                        .into_iter()
+                        .map(|e| String::from_utf8(e.byte_string()?).ok())
+                        .collect::<Option<Vec<String>>>()
                 })
-                .collect::<Option<Vec<String>>>()?;
+                .collect::<Option<Vec<Vec<String>>>>()?;
 
-            metainfo.announce_list(Some(announce_list));
+            metainfo.announce_list(Some(tiers));
         }
         b"creation date" => {
             let date = v.integer()?;
@@ -127,9 +235,9 @@ fn build_metainfo_fields(
             let bytes = v.byte_string()?;
             metainfo.encoding(Some(String::from_utf8(bytes).ok()?));
         }
-
-        b"httpseeds" => {}
-        _ => return None,
+        _ => {
+            metainfo.extra(field.to_vec(), v);
+        }
     }
     Some(())
 }
@@ -137,6 +245,7 @@ fn build_metainfo_fields(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::torrent::info::InfoMode;
 
     #[test]
     fn read_metainfo_from_torrent_single_file() {
@@ -144,21 +253,27 @@ mod tests {
 			 10:created by2:me13:creation datei0e8:encoding4:utf84:infod6:length\
 			 i0e6:md5sum4:aaaa4:name4:file12:piece lengthi0e6:pieces5:aaaaa7:privatei1eee";
         let got = read_torrent(&metainfo[..]).unwrap();
-        let info = parser::parse(
-            b"d6:lengthi0e6:md5sum4:aaaa4:name4:file12:piece lengthi0e6:pieces5:aaaaa7:privatei1ee"
-                .to_vec(),
-        )
-        .unwrap()
-        .dictionary()
-        .unwrap();
+        let raw_info =
+            b"d6:lengthi0e6:md5sum4:aaaa4:name4:file12:piece lengthi0e6:pieces5:aaaaa7:privatei1ee";
+        let info = parser::parse(raw_info.to_vec())
+            .unwrap()
+            .dictionary()
+            .unwrap();
         let want = Metainfo {
             announce: "url".into(),
-            announce_list: Some(vec!["a".into(), "ab".into(), "abc".into()]),
+            announce_list: Some(vec![
+                vec!["a".into()],
+                vec!["ab".into()],
+                vec!["abc".into()],
+            ]),
             comment: Some("comment".into()),
             created_by: Some("me".into()),
             creation_date: Some(0),
             encoding: Some("utf8".into()),
             info: Info::new(info).unwrap(),
+            info_hash: utils::hash_info(raw_info),
+            info_bytes: raw_info.to_vec(),
+            extra: Vec::new(),
         };
         assert_eq!(got, want);
     }
@@ -168,11 +283,11 @@ mod tests {
         let metainfo = b"d8:announce3:url4:infod6:length\
 			 i0e4:name4:file12:piece lengthi0e6:pieces5:aaaaaee";
         let got = read_torrent(&metainfo[..]).unwrap();
-        let info =
-            parser::parse(b"d6:lengthi0e4:name4:file12:piece lengthi0e6:pieces5:aaaaae".to_vec())
-                .unwrap()
-                .dictionary()
-                .unwrap();
+        let raw_info = b"d6:lengthi0e4:name4:file12:piece lengthi0e6:pieces5:aaaaae";
+        let info = parser::parse(raw_info.to_vec())
+            .unwrap()
+            .dictionary()
+            .unwrap();
         let want = Metainfo {
             announce: "url".into(),
             announce_list: None,
@@ -181,10 +296,25 @@ mod tests {
             creation_date: None,
             encoding: None,
             info: Info::new(info).unwrap(),
+            info_hash: utils::hash_info(raw_info),
+            info_bytes: raw_info.to_vec(),
+            extra: Vec::new(),
         };
         assert_eq!(got, want);
     }
 
+    #[test]
+    fn info_hash_is_computed_over_the_original_bytes() {
+        // Unsorted keys and an unknown key, re-encoding the info
+        // dictionary wouldn't give back the same bytes
+        let raw_info = b"d4:name4:file6:lengthi0e12:piece lengthi0e6:pieces5:aaaaa6:source3:abce";
+        let metainfo = [&b"d8:announce3:url4:info"[..], &raw_info[..], &b"e"[..]].concat();
+        let got = read_torrent(&metainfo[..]).unwrap();
+
+        assert_eq!(got.info_hash, utils::hash_info(raw_info));
+        assert_ne!(got.info.bencode(), raw_info.to_vec());
+    }
+
     #[test]
     fn reading_something_different_from_dictionary_returns_error() {
         let metainfo = b"le";
@@ -208,11 +338,77 @@ mod tests {
     }
 
     #[test]
-    fn reading_torrent_with_extra_field_returns_error() {
+    fn reading_torrent_with_extra_field_keeps_it() {
         let metainfo = b"d5:extrai0e8:announce3:url4:infod6:length\
 			 i0e4:name4:file12:piece lengthi0e6:pieces5:aaaaaee";
-        let got = read_torrent(&metainfo[..]).unwrap_err();
-        assert_eq!(got, TorrentError::InvalidTorrent);
+        let got = read_torrent(&metainfo[..]).unwrap();
+        assert_eq!(
+            got.extra,
+            vec![(b"extra".to_vec(), BencodedValue::Integer(0))]
+        );
+    }
+
+    #[test]
+    fn bencode_round_trips_every_field() {
+        let metainfo = b"d8:announce3:url13:announce-listll1:ael2:ab3:abcee7:comment7:comment\
+			 10:created by2:me13:creation datei0e8:encoding4:utf85:extrali1ee\
+			 4:infod6:lengthi0e6:md5sum4:aaaa4:name4:file12:piece lengthi0e\
+			 6:pieces5:aaaaa7:privatei1e6:source3:abce8:url-list0:e";
+        let got = read_torrent(&metainfo[..]).unwrap();
+
+        assert_eq!(got.bencode(), metainfo.to_vec());
+        assert_eq!(read_torrent(&got.bencode()[..]).unwrap(), got);
+    }
+
+    #[test]
+    fn bencode_writes_back_a_non_canonical_info_dictionary() {
+        // Unsorted keys, rebuilding the info dictionary from its
+        // fields would change the info hash
+        let raw_info = b"d4:name4:file6:lengthi0e12:piece lengthi0e6:pieces5:aaaaae";
+        let metainfo = [&b"d8:announce3:url4:info"[..], &raw_info[..], &b"e"[..]].concat();
+        let got = read_torrent(&metainfo[..]).unwrap();
+
+        assert_eq!(got.bencode(), metainfo);
+        assert_eq!(
+            read_torrent(&got.bencode()[..]).unwrap().info_hash,
+            utils::hash_info(raw_info)
+        );
+    }
+
+    #[test]
+    fn bencode_after_changing_the_announce_keeps_the_info_hash() {
+        let metainfo = b"d8:announce3:url4:infod5:filesld6:lengthi3e4:pathl1:a5:b.txte\
+			 4:sha13:abced6:lengthi4e4:pathl1:ceee4:name3:dir12:piece lengthi2e\
+			 6:pieces5:aaaaaee";
+        let mut got = read_torrent(&metainfo[..]).unwrap();
+        got.announce = "http://new/announce".into();
+        got.comment = Some("moved".into());
+
+        let reread = read_torrent(&got.bencode()[..]).unwrap();
+
+        assert_eq!(reread.announce, "http://new/announce");
+        assert_eq!(reread.comment, Some("moved".into()));
+        assert_eq!(reread.info_hash, got.info_hash);
+    }
+
+    #[test]
+    fn bencode_after_changing_the_info_writes_the_new_one() {
+        let metainfo = b"d8:announce3:url4:infod4:name4:file6:lengthi0e\
+			 12:piece lengthi0e6:pieces5:aaaaaee";
+        let mut got = read_torrent(&metainfo[..]).unwrap();
+        let original_hash = got.info_hash();
+        let mut info = got.info().clone();
+        if let InfoMode::SingleFile(ref mut file) = info.0 {
+            file.name = "renamed".into();
+        }
+
+        got.set_info(info.clone());
+        let reread = read_torrent(&got.bencode()[..]).unwrap();
+
+        assert_eq!(reread.info(), &info);
+        assert_eq!(reread.info_hash(), got.info_hash());
+        assert_eq!(got.info_hash(), utils::hash_info(&info.bencode()));
+        assert_ne!(got.info_hash(), original_hash);
     }
 
     #[test]
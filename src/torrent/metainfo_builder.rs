@@ -1,14 +1,17 @@
 use super::info::{Info, InfoMode};
 use super::metainfo::Metainfo;
+use crate::bencode::bencoded_value::BencodedValue;
 
 pub struct MetainfoBuilder {
     announce: String,
-    announce_list: Option<Vec<String>>,
+    announce_list: Option<Vec<Vec<String>>>,
     comment: Option<String>,
     created_by: Option<String>,
     creation_date: Option<i64>,
     encoding: Option<String>,
+    extra: Vec<(Vec<u8>, BencodedValue)>,
     info: Info,
+    info_bytes: Option<Vec<u8>>,
 }
 
 impl MetainfoBuilder {
@@ -21,6 +24,8 @@ impl MetainfoBuilder {
             comment: None,
             created_by: None,
             encoding: None,
+            extra: Vec::new(),
+            info_bytes: None,
         }
     }
 
@@ -34,7 +39,7 @@ impl MetainfoBuilder {
         self
     }
 
-    pub fn announce_list(&'_ mut self, urls: Option<Vec<String>>) -> &'_ mut Self {
+    pub fn announce_list(&'_ mut self, urls: Option<Vec<Vec<String>>>) -> &'_ mut Self {
         self.announce_list = urls;
         self
     }
@@ -59,15 +64,32 @@ impl MetainfoBuilder {
         self
     }
 
-    pub fn build(self) -> Metainfo {
-        Metainfo {
-            announce: self.announce,
-            announce_list: self.announce_list,
-            comment: self.comment,
-            created_by: self.created_by,
-            creation_date: self.creation_date,
-            encoding: self.encoding,
-            info: self.info,
-        }
+    /// Original bytes of the info dictionary, which must be the ones
+    /// it was parsed from.
+    pub(super) fn info_bytes(&'_ mut self, bytes: Vec<u8>) -> &'_ mut Self {
+        self.info_bytes = Some(bytes);
+        self
+    }
+
+    /// Adds a top level key that is not part of the specification
+    pub fn extra(&'_ mut self, key: Vec<u8>, value: BencodedValue) -> &'_ mut Self {
+        self.extra.push((key, value));
+        self
+    }
+
+    /// Builds the [`Metainfo`]. The info hash is computed over the
+    /// original bytes of the info dictionary, or over its bencoding if
+    /// they weren't set.
+    pub fn build(mut self) -> Metainfo {
+        self.extra.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut metainfo = Metainfo::with_info(self.info, self.info_bytes);
+        metainfo.announce = self.announce;
+        metainfo.announce_list = self.announce_list;
+        metainfo.comment = self.comment;
+        metainfo.created_by = self.created_by;
+        metainfo.creation_date = self.creation_date;
+        metainfo.encoding = self.encoding;
+        metainfo.extra = self.extra;
+        metainfo
     }
 }
@@ -1,5 +1,9 @@
 pub mod info;
 mod info_builder;
+pub mod magnet;
+pub mod magnet_error;
 pub mod metainfo;
 mod metainfo_builder;
+pub mod torrent_creator;
+pub mod torrent_creator_error;
 pub mod torrent_error;
@@ -0,0 +1,349 @@
+use super::info::FileData;
+use super::info_builder::InfoBuilder;
+use super::metainfo_builder::MetainfoBuilder;
+use super::torrent_creator_error::TorrentCreatorError;
+use crate::utils;
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Smallest piece length accepted, 16 KiB
+pub const MIN_PIECE_LENGTH: i64 = 1 << 14;
+/// Largest piece length picked automatically, 16 MiB
+pub const MAX_PIECE_LENGTH: i64 = 1 << 24;
+/// Amount of pieces aimed for when picking the piece length
+const TARGET_PIECES: u64 = 1500;
+
+/// # TorrentCreator
+/// Creates the metainfo file (`.torrent`) of a file or a directory. A
+/// directory is shared in multiple file mode with its files sorted by
+/// path.
+///
+/// ```#s
+/// let bytes = TorrentCreator::new("build/", "http://tracker/announce")
+///     .comment(Some("Nightly build".to_string()))
+///     .private(true)
+///     .create()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct TorrentCreator {
+    path: PathBuf,
+    announce: String,
+    announce_list: Option<Vec<Vec<String>>>,
+    comment: Option<String>,
+    created_by: Option<String>,
+    creation_date: Option<i64>,
+    piece_length: Option<i64>,
+    private: bool,
+}
+
+impl TorrentCreator {
+    pub fn new<P: AsRef<Path>>(path: P, announce: &str) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            announce: announce.to_string(),
+            announce_list: None,
+            comment: None,
+            created_by: None,
+            creation_date: None,
+            piece_length: None,
+            private: false,
+        }
+    }
+
+    /// Tiers of trackers, written as the `announce-list` key
+    pub fn announce_list(&'_ mut self, tiers: Option<Vec<Vec<String>>>) -> &'_ mut Self {
+        self.announce_list = tiers;
+        self
+    }
+
+    pub fn comment(&'_ mut self, c: Option<String>) -> &'_ mut Self {
+        self.comment = c;
+        self
+    }
+
+    pub fn created_by(&'_ mut self, c: Option<String>) -> &'_ mut Self {
+        self.created_by = c;
+        self
+    }
+
+    /// Creation time in standard UNIX epoch format. It's left out by
+    /// default so the output only depends on the files.
+    pub fn creation_date(&'_ mut self, d: Option<i64>) -> &'_ mut Self {
+        self.creation_date = d;
+        self
+    }
+
+    /// Number of bytes in each piece. If [`None`] it's picked
+    /// according to the size of the torrent, see
+    /// [`auto_piece_length`].
+    pub fn piece_length(&'_ mut self, l: Option<i64>) -> &'_ mut Self {
+        self.piece_length = l;
+        self
+    }
+
+    pub fn private(&'_ mut self, is_private: bool) -> &'_ mut Self {
+        self.private = is_private;
+        self
+    }
+
+    /// Hashes the pieces of the files and returns the bencoded
+    /// metainfo file.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the files can't be
+    /// read, if there are no files, if the piece length is invalid or
+    /// if a name is not valid UTF-8.
+    pub fn create(&self) -> Result<Vec<u8>, TorrentCreatorError> {
+        let name = file_name(&self.path)?;
+        let metadata = fs::metadata(&self.path).map_err(TorrentCreatorError::File)?;
+        let files = if metadata.is_dir() {
+            let mut files = Vec::new();
+            collect_files(&self.path, &mut Vec::new(), &mut files)?;
+            files
+        } else {
+            vec![(self.path.clone(), Vec::new(), metadata.len())]
+        };
+        if files.is_empty() {
+            return Err(TorrentCreatorError::EmptyTorrent);
+        }
+
+        let total: u64 = files.iter().map(|(_, _, l)| l).sum();
+        let piece_length = match self.piece_length {
+            Some(l) if l < MIN_PIECE_LENGTH || l.count_ones() != 1 => {
+                return Err(TorrentCreatorError::InvalidPieceLength(l))
+            }
+            Some(l) => l,
+            None => auto_piece_length(total),
+        };
+        let pieces = hash_pieces(files.iter().map(|(p, _, _)| p.as_path()), piece_length)?;
+
+        let mut info = InfoBuilder::new();
+        info.name(name)
+            .piece_length(piece_length)
+            .pieces(pieces)
+            .private(self.private.then_some(true));
+        if metadata.is_dir() {
+            info.files(
+                files
+                    .into_iter()
+                    .map(|(_, path, length)| FileData {
+                        length: length as i64,
+                        md5sum: None,
+                        path,
+                        extra: Vec::new(),
+                    })
+                    .collect(),
+            );
+        } else {
+            info.length(total as i64);
+        }
+
+        let mut metainfo = MetainfoBuilder::new();
+        metainfo
+            .announce(self.announce.clone())
+            .announce_list(self.announce_list.clone())
+            .comment(self.comment.clone())
+            .created_by(self.created_by.clone())
+            .creation_date(self.creation_date)
+            .info(info.build());
+        Ok(metainfo.build().bencode())
+    }
+}
+
+/// Picks a power of two piece length so the torrent has around 1500
+/// pieces, between [`MIN_PIECE_LENGTH`] and [`MAX_PIECE_LENGTH`].
+pub fn auto_piece_length(total_length: u64) -> i64 {
+    let length = (total_length / TARGET_PIECES).next_power_of_two() as i64;
+    length.clamp(MIN_PIECE_LENGTH, MAX_PIECE_LENGTH)
+}
+
+fn file_name(path: &Path) -> Result<String, TorrentCreatorError> {
+    let name = match path.file_name() {
+        Some(n) => n.to_owned(),
+        // Paths like `.` or `dir/..` have no name, use the one of
+        // the directory they point to
+        None => fs::canonicalize(path)
+            .map_err(TorrentCreatorError::File)?
+            .file_name()
+            .ok_or_else(|| TorrentCreatorError::InvalidPath(path.to_path_buf()))?
+            .to_owned(),
+    };
+    name.into_string()
+        .map_err(|_| TorrentCreatorError::InvalidPath(path.to_path_buf()))
+}
+
+/// Adds the files inside `dir` to `files`, recursively and sorted by
+/// name. Each file is stored with its path on disk, its path relative
+/// to the torrent directory and its length.
+fn collect_files(
+    dir: &Path,
+    prefix: &mut Vec<String>,
+    files: &mut Vec<(PathBuf, Vec<String>, u64)>,
+) -> Result<(), TorrentCreatorError> {
+    let mut entries = fs::read_dir(dir)
+        .and_then(|d| d.collect::<Result<Vec<_>, _>>())
+        .map_err(TorrentCreatorError::File)?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        prefix.push(file_name(&path)?);
+        let metadata = fs::metadata(&path).map_err(TorrentCreatorError::File)?;
+        if metadata.is_dir() {
+            collect_files(&path, prefix, files)?;
+        } else {
+            files.push((path, prefix.clone(), metadata.len()));
+        }
+        prefix.pop();
+    }
+    Ok(())
+}
+
+/// Reads the files one after the other, as if they were concatenated,
+/// and returns the SHA1 hashes of every piece.
+fn hash_pieces<'a, I: Iterator<Item = &'a Path>>(
+    files: I,
+    piece_length: i64,
+) -> Result<Vec<u8>, TorrentCreatorError> {
+    let mut pieces = Vec::new();
+    let mut piece = vec![0; piece_length as usize];
+    let mut filled = 0;
+
+    for path in files {
+        let mut file = File::open(path).map_err(TorrentCreatorError::File)?;
+        loop {
+            let read = file
+                .read(&mut piece[filled..])
+                .map_err(TorrentCreatorError::File)?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+            if filled == piece.len() {
+                pieces.extend_from_slice(&utils::hash_info(&piece));
+                filled = 0;
+            }
+        }
+    }
+    if filled > 0 {
+        pieces.extend_from_slice(&utils::hash_info(&piece[..filled]));
+    }
+    Ok(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent::metainfo;
+    use std::env;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("torrent_creator_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn create_single_file_torrent() {
+        let dir = temp_dir("single");
+        let content: Vec<u8> = (0..40000).map(|i| i as u8).collect();
+        fs::write(dir.join("file.bin"), &content).unwrap();
+
+        let bytes = TorrentCreator::new(dir.join("file.bin"), "http://tracker/announce")
+            .piece_length(Some(MIN_PIECE_LENGTH))
+            .comment(Some("test".to_string()))
+            .private(true)
+            .create()
+            .unwrap();
+        let metainfo = metainfo::read_torrent(&bytes[..]).unwrap();
+        let want: Vec<u8> = content
+            .chunks(MIN_PIECE_LENGTH as usize)
+            .flat_map(utils::hash_info)
+            .collect();
+
+        assert_eq!(metainfo.announce, "http://tracker/announce");
+        assert_eq!(metainfo.comment, Some("test".to_string()));
+        assert_eq!(metainfo.info().name(), "file.bin");
+        assert_eq!(metainfo.info().length(), 40000);
+        assert_eq!(metainfo.info().pieces(), &want[..]);
+        assert_eq!(metainfo.info().private(), Some(true));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn create_multiple_file_torrent_with_pieces_spanning_files() {
+        let dir = temp_dir("multiple");
+        fs::create_dir_all(dir.join("shared/sub")).unwrap();
+        fs::write(dir.join("shared/b.txt"), vec![1; 20000]).unwrap();
+        fs::write(dir.join("shared/a.txt"), vec![2; 10000]).unwrap();
+        fs::write(dir.join("shared/sub/c.txt"), vec![3; 5000]).unwrap();
+
+        let bytes = TorrentCreator::new(dir.join("shared"), "http://tracker/announce")
+            .announce_list(Some(vec![
+                vec!["http://tracker/announce".to_string()],
+                vec!["udp://backup:6969".to_string()],
+            ]))
+            .create()
+            .unwrap();
+        let metainfo = metainfo::read_torrent(&bytes[..]).unwrap();
+        let content = [vec![2; 10000], vec![1; 20000], vec![3; 5000]].concat();
+        let want: Vec<u8> = content
+            .chunks(MIN_PIECE_LENGTH as usize)
+            .flat_map(utils::hash_info)
+            .collect();
+
+        assert_eq!(
+            metainfo.announce_list,
+            Some(vec![
+                vec!["http://tracker/announce".to_string()],
+                vec!["udp://backup:6969".to_string()],
+            ])
+        );
+        assert_eq!(metainfo.info().name(), "shared");
+        assert_eq!(metainfo.info().piece_length(), MIN_PIECE_LENGTH);
+        assert_eq!(metainfo.info().pieces(), &want[..]);
+        assert_eq!(
+            metainfo.info().files(),
+            vec![
+                (PathBuf::from("shared/a.txt"), 10000),
+                (PathBuf::from("shared/b.txt"), 20000),
+                (PathBuf::from("shared/sub/c.txt"), 5000),
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn create_with_invalid_piece_length_returns_error() {
+        let dir = temp_dir("invalid");
+        fs::write(dir.join("file"), b"abc").unwrap();
+
+        let got = TorrentCreator::new(dir.join("file"), "http://tracker")
+            .piece_length(Some(20000))
+            .create();
+
+        assert_eq!(got, Err(TorrentCreatorError::InvalidPieceLength(20000)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn create_from_empty_directory_returns_error() {
+        let dir = temp_dir("empty");
+
+        let got = TorrentCreator::new(&dir, "http://tracker").create();
+
+        assert_eq!(got, Err(TorrentCreatorError::EmptyTorrent));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn auto_piece_length_is_a_bounded_power_of_two() {
+        assert_eq!(auto_piece_length(0), MIN_PIECE_LENGTH);
+        assert_eq!(auto_piece_length(1 << 30), 1 << 20);
+        assert_eq!(auto_piece_length(1 << 40), MAX_PIECE_LENGTH);
+    }
+}
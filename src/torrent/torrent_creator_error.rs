@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+use std::{error::Error, fmt, io};
+
+/// Represents the possible errors that can occur while creating a
+/// torrent.
+#[derive(Debug)]
+pub enum TorrentCreatorError {
+    /// An error ocurred while reading the files
+    File(io::Error),
+    /// The path has no files to share
+    EmptyTorrent,
+    /// The piece length is not a power of two of at least 16 KiB
+    InvalidPieceLength(i64),
+    /// The name of the file or directory is not valid UTF-8
+    InvalidPath(PathBuf),
+}
+
+impl Error for TorrentCreatorError {}
+
+impl fmt::Display for TorrentCreatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TorrentCreatorError::File(e) => write!(f, "Could not read the files: {}", e),
+            TorrentCreatorError::EmptyTorrent => write!(f, "There are no files to share"),
+            TorrentCreatorError::InvalidPieceLength(l) => write!(
+                f,
+                "The piece length {} is not a power of two of at least 16 KiB",
+                l
+            ),
+            TorrentCreatorError::InvalidPath(p) => {
+                write!(f, "The path {} is not valid UTF-8", p.display())
+            }
+        }
+    }
+}
+
+impl Eq for TorrentCreatorError {}
+
+impl PartialEq for TorrentCreatorError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TorrentCreatorError::File(e1), TorrentCreatorError::File(e2)) => {
+                e1.kind() == e2.kind()
+            }
+            (TorrentCreatorError::EmptyTorrent, TorrentCreatorError::EmptyTorrent) => true,
+            (
+                TorrentCreatorError::InvalidPieceLength(l1),
+                TorrentCreatorError::InvalidPieceLength(l2),
+            ) => l1 == l2,
+            (TorrentCreatorError::InvalidPath(p1), TorrentCreatorError::InvalidPath(p2)) => {
+                p1 == p2
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::torrent::metainfo::Metainfo;
+use crate::tracker::tracker_status::TrackerStatus;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use std::time::SystemTime;
+
+/// # AnnounceList
+/// Trackers of a torrent grouped in tiers, following BEP 12. The
+/// trackers are tried in order, a tier is only used if every tracker
+/// of the previous ones failed, and a tracker that answers is moved to
+/// the front of its tier.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AnnounceList {
+    tiers: Vec<Vec<TrackerStatus>>,
+}
+
+impl AnnounceList {
+    /// Creates the list from the `announce-list` of the metainfo,
+    /// shuffling the trackers of each tier. If the torrent has no
+    /// `announce-list` its only tracker is the `announce` URL.
+    pub fn new(metainfo: &Metainfo) -> Self {
+        let tiers = match metainfo.announce_list {
+            Some(ref tiers) if tiers.iter().any(|t| !t.is_empty()) => tiers.clone(),
+            _ => vec![vec![metainfo.announce.clone()]],
+        };
+        let mut list = Self::from_tiers(tiers);
+        list.tiers
+            .iter_mut()
+            .for_each(|t| t.shuffle(&mut thread_rng()));
+        list
+    }
+
+    /// Creates the list keeping the order of the trackers. Empty tiers
+    /// are discarded.
+    pub fn from_tiers(tiers: Vec<Vec<String>>) -> Self {
+        let tiers = tiers
+            .into_iter()
+            .filter(|t| !t.is_empty())
+            .enumerate()
+            .map(|(i, t)| {
+                t.into_iter()
+                    .map(|url| TrackerStatus::new(url, i))
+                    .collect()
+            })
+            .collect();
+        Self { tiers }
+    }
+
+    /// Returns the status of every tracker, in the order in which
+    /// they will be tried.
+    pub fn trackers(&self) -> impl Iterator<Item = &TrackerStatus> {
+        self.tiers.iter().flatten()
+    }
+
+    /// Tries the trackers in order until `announce` succeeds. The
+    /// closure receives the status of the tracker, which it can update
+    /// with the data the tracker sends back, and returns the result
+    /// along with the amount of peers it got, or a description of the
+    /// error. The status of every tracker tried is updated and the
+    /// one that succeeds is promoted to the front of its tier.
+    ///
+    /// Returns [`None`] if every tracker failed.
+    pub fn announce<T, F>(&mut self, mut announce: F) -> Option<T>
+    where
+        F: FnMut(&mut TrackerStatus) -> Result<(T, usize), String>,
+    {
+        for tier in self.tiers.iter_mut() {
+            for i in 0..tier.len() {
+                let status = &mut tier[i];
+                status.last_announce = Some(SystemTime::now());
+                match announce(status) {
+                    Ok((result, peers)) => {
+                        status.last_error = None;
+                        status.peers = peers;
+                        let tracker = tier.remove(i);
+                        tier.insert(0, tracker);
+                        return Some(result);
+                    }
+                    Err(e) => status.last_error = Some(e),
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(list: &AnnounceList) -> Vec<&str> {
+        list.trackers().map(|t| t.url.as_str()).collect()
+    }
+
+    fn tiers() -> AnnounceList {
+        AnnounceList::from_tiers(vec![
+            vec!["a".into(), "b".into()],
+            vec![],
+            vec!["c".into(), "d".into()],
+        ])
+    }
+
+    #[test]
+    fn successful_tracker_is_promoted_within_its_tier() {
+        let mut list = tiers();
+
+        let got = list.announce(|tracker| match tracker.url.as_str() {
+            "b" => Ok((tracker.url.clone(), 5)),
+            _ => Err("down".to_string()),
+        });
+
+        assert_eq!(got, Some("b".to_string()));
+        assert_eq!(urls(&list), vec!["b", "a", "c", "d"]);
+        let statuses: Vec<_> = list.trackers().collect();
+        assert_eq!(statuses[0].peers, 5);
+        assert_eq!(statuses[0].last_error, None);
+        assert_eq!(statuses[1].last_error, Some("down".to_string()));
+        assert!(statuses[2].last_announce.is_none());
+    }
+
+    #[test]
+    fn next_tier_is_used_when_a_tier_fails() {
+        let mut list = tiers();
+        let mut tried = Vec::new();
+
+        let got = list.announce(|tracker| {
+            tried.push(tracker.url.clone());
+            match tracker.url.as_str() {
+                "d" => Ok(((), 1)),
+                _ => Err("down".to_string()),
+            }
+        });
+
+        assert_eq!(got, Some(()));
+        assert_eq!(tried, vec!["a", "b", "c", "d"]);
+        assert_eq!(urls(&list), vec!["a", "b", "d", "c"]);
+        assert_eq!(
+            list.trackers().map(|t| t.tier).collect::<Vec<_>>(),
+            vec![0, 0, 1, 1]
+        );
+    }
+
+    #[test]
+    fn announce_returns_none_if_every_tracker_fails() {
+        let mut list = tiers();
+
+        let got: Option<()> = list.announce(|_| Err("down".to_string()));
+
+        assert_eq!(got, None);
+        assert!(list.trackers().all(|t| t.last_error.is_some()));
+    }
+
+    #[test]
+    fn shuffle_keeps_the_trackers_of_each_tier() {
+        let metainfo = crate::torrent::metainfo::read_torrent(
+            &b"d8:announce1:x13:announce-listll1:a1:bel1:cee4:infod6:lengthi0e4:name1:f\
+               12:piece lengthi1e6:pieces0:ee"[..],
+        )
+        .unwrap();
+
+        let list = AnnounceList::new(&metainfo);
+        let mut first: Vec<_> = urls(&list)[..2].to_vec();
+        first.sort();
+
+        assert_eq!(first, vec!["a", "b"]);
+        assert_eq!(urls(&list)[2], "c");
+    }
+
+    #[test]
+    fn announce_is_used_without_announce_list() {
+        let metainfo = crate::torrent::metainfo::read_torrent(
+            &b"d8:announce1:x4:infod6:lengthi0e4:name1:f12:piece lengthi1e6:pieces0:ee"[..],
+        )
+        .unwrap();
+
+        assert_eq!(urls(&AnnounceList::new(&metainfo)), vec!["x"]);
+    }
+}
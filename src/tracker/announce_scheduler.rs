@@ -0,0 +1,223 @@
+use crate::tracker::request::tracker_request_event::TrackerRequestEvent;
+use std::time::{Duration, Instant};
+
+/// Shortest interval accepted from a tracker, to avoid flooding it if
+/// it sends a bogus one.
+const MIN_ALLOWED_INTERVAL: Duration = Duration::from_secs(60);
+/// Time that must pass before re-announcing early if the tracker
+/// doesn't send a `min interval`.
+const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(300);
+/// Wait after the first failed announce, doubled after each failure.
+const RETRY_DELAY: Duration = Duration::from_secs(15);
+/// Longest wait between retries.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30 * 60);
+/// Amount of connected peers below which the client re-announces
+/// without waiting for the interval.
+pub const LOW_PEERS: usize = 5;
+
+/// # AnnounceScheduler
+/// Decides when a torrent has to be announced to its trackers and
+/// which event has to be sent. The first announce is due right away,
+/// the next ones follow the `interval` of the tracker, and failures
+/// are retried with exponential backoff.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AnnounceScheduler {
+    /// Event to send in the next announce
+    event: Option<TrackerRequestEvent>,
+    /// Whether the tracker acknowledged the `started` event
+    started: bool,
+    /// Bytes left to download, as of the last update
+    left: Option<u64>,
+    next_announce: Instant,
+    /// Time of the last successful announce
+    last_announce: Option<Instant>,
+    min_interval: Duration,
+    /// Consecutive failed announces
+    failures: u32,
+}
+
+impl AnnounceScheduler {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            event: Some(TrackerRequestEvent::Started),
+            started: false,
+            left: None,
+            next_announce: now,
+            last_announce: None,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            failures: 0,
+        }
+    }
+
+    /// Event to send in the next announce, [`None`] for a regular one.
+    pub fn event(&self) -> Option<TrackerRequestEvent> {
+        self.event.clone()
+    }
+
+    /// Time at which the next announce is due.
+    pub fn next_announce(&self) -> Instant {
+        self.next_announce
+    }
+
+    /// Returns true if the tracker acknowledged the `started` event
+    /// and hasn't been sent the `stopped` one.
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    /// Returns true if the torrent has to be announced. Besides the
+    /// regular interval, a client with less than [`LOW_PEERS`]
+    /// connected peers re-announces as soon as the `min interval`
+    /// allows it, unless the tracker is failing.
+    pub fn is_due(&self, now: Instant, peers: usize) -> bool {
+        if now >= self.next_announce {
+            return true;
+        }
+        match self.last_announce {
+            Some(last) => {
+                self.failures == 0 && peers < LOW_PEERS && now >= last + self.min_interval
+            }
+            None => false,
+        }
+    }
+
+    /// Updates the amount of bytes left to download. If the download
+    /// finishes after the tracker was told that it started, the
+    /// `completed` event is announced right away.
+    pub fn set_left(&mut self, left: u64, now: Instant) {
+        let was_incomplete = self.left.is_some_and(|l| l > 0);
+        if left == 0 && was_incomplete && self.started {
+            self.event = Some(TrackerRequestEvent::Completed);
+            self.next_announce = now;
+        }
+        self.left = Some(left);
+    }
+
+    /// Marks the announce as successful and schedules the next one
+    /// after the interval, and the minimum interval, given by the
+    /// tracker.
+    pub fn succeeded(&mut self, now: Instant, interval: i64, min_interval: Option<i64>) {
+        self.started = self.event != Some(TrackerRequestEvent::Stopped);
+        self.event = None;
+        self.failures = 0;
+        self.last_announce = Some(now);
+
+        let interval = seconds(interval).max(MIN_ALLOWED_INTERVAL);
+        self.min_interval = min_interval
+            .map(seconds)
+            .unwrap_or(DEFAULT_MIN_INTERVAL)
+            .min(interval);
+        self.next_announce = now + interval;
+    }
+
+    /// Marks the announce as failed. The same event is retried after a
+    /// delay that doubles with every consecutive failure.
+    pub fn failed(&mut self, now: Instant) {
+        let delay = RETRY_DELAY
+            .checked_mul(1 << self.failures.min(16))
+            .map_or(MAX_RETRY_DELAY, |d| d.min(MAX_RETRY_DELAY));
+        self.failures += 1;
+        self.next_announce = now + delay;
+    }
+
+    /// Makes the next announce send the `stopped` event, right away.
+    pub fn stop(&mut self, now: Instant) {
+        self.event = Some(TrackerRequestEvent::Stopped);
+        self.next_announce = now;
+    }
+}
+
+fn seconds(s: i64) -> Duration {
+    Duration::from_secs(s.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_announce_is_started_and_due_right_away() {
+        let now = Instant::now();
+        let mut scheduler = AnnounceScheduler::new(now);
+
+        assert!(scheduler.is_due(now, 0));
+        assert_eq!(scheduler.event(), Some(TrackerRequestEvent::Started));
+
+        scheduler.succeeded(now, 1800, Some(600));
+        assert!(scheduler.is_started());
+        assert_eq!(scheduler.event(), None);
+        assert!(!scheduler.is_due(now + Duration::from_secs(1799), LOW_PEERS));
+        assert!(scheduler.is_due(now + Duration::from_secs(1800), LOW_PEERS));
+    }
+
+    #[test]
+    fn low_on_peers_announces_after_the_min_interval() {
+        let now = Instant::now();
+        let mut scheduler = AnnounceScheduler::new(now);
+        scheduler.succeeded(now, 1800, Some(600));
+
+        assert!(!scheduler.is_due(now + Duration::from_secs(599), 0));
+        assert!(scheduler.is_due(now + Duration::from_secs(600), 0));
+        assert!(!scheduler.is_due(now + Duration::from_secs(600), LOW_PEERS));
+    }
+
+    #[test]
+    fn failures_back_off_exponentially() {
+        let now = Instant::now();
+        let mut scheduler = AnnounceScheduler::new(now);
+
+        scheduler.failed(now);
+        assert!(!scheduler.is_due(now + Duration::from_secs(14), 0));
+        assert!(scheduler.is_due(now + Duration::from_secs(15), 0));
+
+        scheduler.failed(now);
+        scheduler.failed(now);
+        assert!(!scheduler.is_due(now + Duration::from_secs(59), 0));
+        assert!(scheduler.is_due(now + Duration::from_secs(60), 0));
+        assert_eq!(scheduler.event(), Some(TrackerRequestEvent::Started));
+
+        for _ in 0..40 {
+            scheduler.failed(now);
+        }
+        assert!(scheduler.is_due(now + MAX_RETRY_DELAY, 0));
+    }
+
+    #[test]
+    fn completed_is_sent_when_the_download_finishes() {
+        let now = Instant::now();
+        let mut scheduler = AnnounceScheduler::new(now);
+        scheduler.set_left(100, now);
+        scheduler.succeeded(now, 1800, None);
+
+        scheduler.set_left(0, now);
+
+        assert_eq!(scheduler.event(), Some(TrackerRequestEvent::Completed));
+        assert!(scheduler.is_due(now, LOW_PEERS));
+    }
+
+    #[test]
+    fn completed_is_not_sent_when_seeding_from_the_start() {
+        let now = Instant::now();
+        let mut scheduler = AnnounceScheduler::new(now);
+        scheduler.set_left(0, now);
+        scheduler.succeeded(now, 1800, None);
+
+        scheduler.set_left(0, now);
+
+        assert_eq!(scheduler.event(), None);
+    }
+
+    #[test]
+    fn stop_sends_stopped() {
+        let now = Instant::now();
+        let mut scheduler = AnnounceScheduler::new(now);
+        scheduler.succeeded(now, 1800, None);
+
+        scheduler.stop(now);
+        assert_eq!(scheduler.event(), Some(TrackerRequestEvent::Stopped));
+        assert!(scheduler.is_due(now, LOW_PEERS));
+
+        scheduler.succeeded(now, 1800, None);
+        assert!(!scheduler.is_started());
+    }
+}
@@ -0,0 +1,291 @@
+use std::net::Ipv6Addr;
+
+/// Protocols supported for the announce URL.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Scheme {
+    Http,
+    Https,
+    Udp,
+}
+
+impl Scheme {
+    /// Port used when the URL doesn't have one. UDP trackers have no
+    /// default port.
+    fn default_port(&self) -> Option<u16> {
+        match self {
+            Scheme::Http => Some(80),
+            Scheme::Https => Some(443),
+            Scheme::Udp => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Scheme::Http => "http",
+            Scheme::Https => "https",
+            Scheme::Udp => "udp",
+        }
+    }
+}
+
+/// Announce URL of a tracker split in its parts.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AnnounceUrl {
+    pub scheme: Scheme,
+    /// Domain name or IP address. IPv6 addresses are stored without
+    /// the brackets.
+    pub host: String,
+    pub port: u16,
+    /// Path of the announce, `/` if the URL has none
+    pub path: String,
+    /// Query parameters of the URL without the `?`, like a passkey.
+    /// They must be sent along with the announce parameters.
+    pub query: Option<String>,
+}
+
+impl AnnounceUrl {
+    /// Parses an `http`, `https` or `udp` URL. Returns [`None`] if the
+    /// scheme is not supported, if the host is missing or if the port
+    /// is invalid. The fragment is discarded.
+    pub fn parse(url: &str) -> Option<Self> {
+        let (scheme, rest) = url.split_once("://")?;
+        let scheme = match scheme.to_ascii_lowercase().as_str() {
+            "http" => Scheme::Http,
+            "https" => Scheme::Https,
+            "udp" => Scheme::Udp,
+            _ => return None,
+        };
+        let rest = rest.split('#').next().unwrap_or_default();
+        let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+        let (authority, path_and_query) = rest.split_at(authority_end);
+        // Credentials are not supported, but they don't prevent the
+        // connection
+        let authority = authority.rsplit('@').next().unwrap_or_default();
+
+        let (host, port) = if let Some(ipv6) = authority.strip_prefix('[') {
+            let (host, port) = ipv6.split_once(']')?;
+            host.parse::<Ipv6Addr>().ok()?;
+            // Only the port can follow the address
+            let port = match port {
+                "" => None,
+                port => Some(port.strip_prefix(':')?),
+            };
+            (host, port)
+        } else {
+            match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            }
+        };
+        if host.is_empty() {
+            return None;
+        }
+        let port = match port {
+            Some(p) => p.parse().ok().filter(|p| *p != 0)?,
+            None => scheme.default_port()?,
+        };
+
+        let (path, query) = match path_and_query.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (path_and_query, None),
+        };
+        Some(Self {
+            scheme,
+            host: host.to_string(),
+            port,
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+            query: query.filter(|q| !q.is_empty()).map(String::from),
+        })
+    }
+
+    /// Value of the `Host:` header. IPv6 addresses are written between
+    /// brackets and the port is left out if it's the default one.
+    pub fn host_header(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        if self.scheme.default_port() == Some(self.port) {
+            host
+        } else {
+            format!("{}:{}", host, self.port)
+        }
+    }
+
+    /// Resolves the `Location` of a redirect against this URL. The
+    /// location can be an absolute URL, a URL without the scheme or
+    /// a path, absolute or relative to the current one.
+    pub fn join(&self, location: &str) -> Option<Self> {
+        if location.contains("://") {
+            return Self::parse(location);
+        }
+        if location.starts_with("//") {
+            return Self::parse(&format!("{}:{}", self.scheme.name(), location));
+        }
+
+        let location = location.split('#').next().unwrap_or_default();
+        let (path, query) = match location.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (location, None),
+        };
+        let path = if path.starts_with('/') {
+            path.to_string()
+        } else if path.is_empty() {
+            self.path.clone()
+        } else {
+            let directory = self.path.rfind('/').map_or(0, |i| i + 1);
+            format!("{}{}", &self.path[..directory], path)
+        };
+        Some(Self {
+            path,
+            query: query.filter(|q| !q.is_empty()).map(String::from),
+            ..self.clone()
+        })
+    }
+
+    /// Returns the scrape URL of the tracker. By convention it's the
+    /// announce URL with the last `announce` segment of the path
+    /// replaced by `scrape`, so if the path doesn't end in an
+    /// `announce` segment the tracker doesn't support scrapes. UDP
+    /// trackers scrape at the same address.
+    pub fn scrape_url(&self) -> Option<Self> {
+        if self.scheme == Scheme::Udp {
+            return Some(self.clone());
+        }
+        let (directory, last) = self.path.rsplit_once('/')?;
+        let rest = last.strip_prefix("announce")?;
+        Some(Self {
+            path: format!("{}/scrape{}", directory, rest),
+            ..self.clone()
+        })
+    }
+
+    /// Path and query to request, appending `params` to the query of
+    /// the URL.
+    pub fn request_target(&self, params: &str) -> String {
+        let params = params.trim_start_matches('?');
+        match (&self.query, params.is_empty()) {
+            (Some(q), true) => format!("{}?{}", self.path, q),
+            (Some(q), false) => format!("{}?{}&{}", self.path, q, params),
+            (None, true) => self.path.clone(),
+            (None, false) => format!("{}?{}", self.path, params),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_http_url_with_default_port() {
+        let url = AnnounceUrl::parse("http://tracker.example.com/announce").unwrap();
+
+        assert_eq!(
+            url,
+            AnnounceUrl {
+                scheme: Scheme::Http,
+                host: "tracker.example.com".into(),
+                port: 80,
+                path: "/announce".into(),
+                query: None,
+            }
+        );
+        assert_eq!(url.host_header(), "tracker.example.com");
+        assert_eq!(
+            url.request_target("?info_hash=a&port=1"),
+            "/announce?info_hash=a&port=1"
+        );
+    }
+
+    #[test]
+    fn parse_https_url_with_port_path_and_passkey() {
+        let url =
+            AnnounceUrl::parse("HTTPS://tracker:8443/a/b/announce.php?passkey=xyz#frag").unwrap();
+
+        assert_eq!(url.scheme, Scheme::Https);
+        assert_eq!(url.host, "tracker");
+        assert_eq!(url.port, 8443);
+        assert_eq!(url.path, "/a/b/announce.php");
+        assert_eq!(url.query, Some("passkey=xyz".into()));
+        assert_eq!(url.host_header(), "tracker:8443");
+        assert_eq!(
+            url.request_target("?info_hash=a"),
+            "/a/b/announce.php?passkey=xyz&info_hash=a"
+        );
+    }
+
+    #[test]
+    fn parse_ipv6_literal() {
+        let url = AnnounceUrl::parse("http://[2001:db8::1]:6969?x=1").unwrap();
+
+        assert_eq!(url.host, "2001:db8::1");
+        assert_eq!(url.port, 6969);
+        assert_eq!(url.path, "/");
+        assert_eq!(url.host_header(), "[2001:db8::1]:6969");
+        assert_eq!(url.request_target(""), "/?x=1");
+    }
+
+    #[test]
+    fn parse_udp_url() {
+        let url = AnnounceUrl::parse("udp://tracker.example.org:1337/announce").unwrap();
+
+        assert_eq!(url.scheme, Scheme::Udp);
+        assert_eq!(url.port, 1337);
+    }
+
+    #[test]
+    fn join_redirect_locations() {
+        let url = AnnounceUrl::parse("https://tracker:8443/a/announce?passkey=x").unwrap();
+
+        let absolute = url.join("http://other/announce?info_hash=a").unwrap();
+        assert_eq!(absolute.scheme, Scheme::Http);
+        assert_eq!(absolute.host, "other");
+        assert_eq!(absolute.request_target(""), "/announce?info_hash=a");
+
+        let no_scheme = url.join("//mirror:9000/announce").unwrap();
+        assert_eq!(no_scheme.scheme, Scheme::Https);
+        assert_eq!(no_scheme.port, 9000);
+
+        let path = url.join("/b/announce?info_hash=a").unwrap();
+        assert_eq!(path.host_header(), "tracker:8443");
+        assert_eq!(path.request_target(""), "/b/announce?info_hash=a");
+
+        let relative = url.join("announce.php").unwrap();
+        assert_eq!(relative.request_target(""), "/a/announce.php");
+    }
+
+    #[test]
+    fn derive_scrape_url() {
+        let scrape = |url: &str| {
+            AnnounceUrl::parse(url)
+                .unwrap()
+                .scrape_url()
+                .map(|u| u.request_target(""))
+        };
+
+        assert_eq!(scrape("http://t/announce"), Some("/scrape".into()));
+        assert_eq!(
+            scrape("http://t/x/announce?k=1"),
+            Some("/x/scrape?k=1".into())
+        );
+        assert_eq!(scrape("http://t/announce.php"), Some("/scrape.php".into()));
+        assert_eq!(scrape("http://t/x/announce/y"), None);
+        assert_eq!(scrape("http://t/a"), None);
+        assert_eq!(scrape("udp://t:80/announce"), Some("/announce".into()));
+    }
+
+    #[test]
+    fn parse_invalid_urls_returns_none() {
+        assert_eq!(AnnounceUrl::parse("tracker/announce"), None);
+        assert_eq!(AnnounceUrl::parse("ftp://tracker/announce"), None);
+        assert_eq!(AnnounceUrl::parse("http:///announce"), None);
+        assert_eq!(AnnounceUrl::parse("http://tracker:abc/announce"), None);
+        assert_eq!(AnnounceUrl::parse("http://tracker:0/announce"), None);
+        assert_eq!(AnnounceUrl::parse("http://[::1/announce"), None);
+        assert_eq!(AnnounceUrl::parse("http://[::1]garbage/announce"), None);
+        assert_eq!(AnnounceUrl::parse("http://[::1]:/announce"), None);
+        assert_eq!(AnnounceUrl::parse("udp://tracker/announce"), None);
+    }
+}
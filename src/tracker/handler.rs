@@ -1,16 +1,28 @@
 use super::request::tracker_request::TrackerRequest;
 use super::request::tracker_request_error::TrackerRequestError;
-use super::response::tracker_response::TrackerResponse;
+use super::response::http_response::HttpResponse;
+use super::response::tracker_response::{TrackerResponse, TrackerResponseMode};
 use super::response::tracker_response_error::TrackerResponseError;
-use super::tracker_handler_error::TrackerHandlerError;
+use crate::bencode::json::{self, BinaryEncoding};
 use crate::bencode::parser;
+use crate::bencode::parser_options::ParserOptions;
 use crate::log::logger;
+use crate::proxy::connector;
+use crate::tracker::announce_url::{AnnounceUrl, Scheme};
+use crate::tracker::udp_tracker::UdpTracker;
+use crate::tracker::udp_tracker_error::UdpTrackerError;
 
-use log::{error, info};
+use log::{debug, error, info};
 use native_tls::TlsConnector;
-use serde::__private::from_utf8_lossy;
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::io::{BufReader, Read, Write};
+use std::time::Duration;
+
+/// Maximum amount of redirects followed for an announce.
+const MAX_REDIRECTS: usize = 5;
+/// Time to wait for the connection to the tracker.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time to wait for the tracker to answer, or to accept the request.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum HandlerMessage {
@@ -33,65 +45,40 @@ pub struct Handler {
     // request_queue: mpsc::Sender<HandlerMessage>, // Cliente -> Handler
     // response_receiver: mpsc::Receiver<HandlerClientMessage>, // Handler -> Cliente
     pub tracker_response: Option<TrackerResponse>,
+    /// Description of the error if the request failed
+    pub error: Option<String>,
 }
 
 impl Handler {
     pub fn new(logger: logger::LogHandle, tracker_request: &mut TrackerRequest) -> Self {
         let mut handle = logger;
 
-        let response = match make_request(tracker_request) {
-            Ok(s) => {
-                info!("Tracker request sent");
-                handle.info("Tracker request sent");
-                match receive_response(s) {
-                    Ok(response) => match parse_response(response, tracker_request.peer_id) {
-                        Ok(r) => {
-                            info!("Response received");
-                            handle.info("Response received");
-                            Some(r)
-                        }
-                        Err(e) => {
-                            error!("{}", e);
-                            handle.error(&format!("{}", e));
-                            None
-                        }
-                    },
-                    Err(e) => {
-                        error!("{}", e);
-                        handle.error(&format!("{}", e));
-                        None
-                    }
-                }
+        let response = match AnnounceUrl::parse(&tracker_request.announce) {
+            Some(url) if url.scheme == Scheme::Udp => {
+                announce_udp(&url, tracker_request, &mut handle).map_err(|e| e.to_string())
+            }
+            _ => announce(tracker_request, &mut handle)
+                .and_then(|response| parse_response(response).map_err(|e| e.to_string())),
+        };
+        let (response, error) = match response {
+            Ok(r) => {
+                info!("Response received");
+                handle.info("Response received");
+                (Some(r), None)
             }
             Err(e) => {
                 error!("{}", e);
-                handle.error(&format!("{}", e));
-                None
+                handle.error(&e);
+                (None, Some(e))
             }
         };
         Handler {
             tracker_response: response,
+            error,
         }
     }
 }
 
-fn parse_torrent_host(host: &mut String) -> Option<&String> {
-    if host.contains("https://") {
-        *host = host.strip_prefix("https://")?.to_string();
-        *host = host.strip_suffix("/announce")?.to_string();
-        let tls: Vec<&str> = host.split(':').collect();
-        *host = tls[0].to_string();
-        host.push_str(":443");
-    } else if host.contains("http://") {
-        *host = host.strip_prefix("http://")?.to_string();
-        *host = host.strip_suffix("/announce")?.to_string();
-    } else {
-        return None;
-    }
-
-    Some(host)
-}
-
 trait ReadWrite: Read + Write {}
 
 impl<T: Read + Write> ReadWrite for T {}
@@ -99,104 +86,179 @@ pub struct Stream {
     stream: Box<dyn ReadWrite>,
 }
 
-fn make_request(request: &mut TrackerRequest) -> Result<Stream, TrackerRequestError> {
-    let mut announce = request.announce.clone();
-
-    if announce.contains("https://") {
-        let connector = TlsConnector::new().map_err(|_| TrackerHandlerError::InvalidTlsConnector);
-        let host = match parse_torrent_host(&mut announce) {
-            Some(it) => it,
-            None => return Err(TrackerRequestError::Host),
-        };
-        let stream = match TcpStream::connect(host) {
-            Ok(it) => it,
-            Err(_) => return Err(TrackerRequestError::InvalidTcpStream),
-        };
-        let local_addr = stream.local_addr();
-        let ip = match local_addr {
-            Ok(it) => it.ip(),
-            Err(_) => return Err(TrackerRequestError::InvalidAdress),
-        };
-        request.set_addr(ip);
-        let tls: Vec<&str> = host.split(':').collect();
-        let mut stream = connector.unwrap().connect(tls[0], stream).unwrap();
-        let path = format!(
-            "/announce{}",
-            request
+/// Sends the announce to an HTTP tracker and reads the response.
+fn announce(
+    request: &mut TrackerRequest,
+    logger: &mut logger::LogHandle,
+) -> Result<HttpResponse, String> {
+    let url = AnnounceUrl::parse(&request.announce)
+        .ok_or_else(|| TrackerRequestError::Host.to_string())?;
+    http_get(
+        url,
+        |url| {
+            let querystring = request
                 .generate_querystring()
-                .map_err(|_| TrackerRequestError::InvalidQuerystring)?
-                .get_querystring()
-        );
-        let header = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, host);
-        match stream.write_all(header.as_bytes()) {
-            Ok(_) => Ok({
-                Stream {
-                    stream: Box::new(stream),
-                }
-            }),
-            Err(_) => Err(TrackerRequestError::WriteStream),
-        }
-    } else {
-        let _host = match parse_torrent_host(&mut announce) {
-            Some(it) => it,
-            None => return Err(TrackerRequestError::Host),
-        };
-        let host = "127.0.0.1:7878";
-        let mut stream = match TcpStream::connect(host) {
-            Ok(it) => it,
-            Err(_) => return Err(TrackerRequestError::InvalidTcpStream),
+                .map_err(|_| TrackerRequestError::InvalidQuerystring)?;
+            Ok(url.request_target(&querystring.get_querystring()))
+        },
+        logger,
+    )
+}
+
+/// Sends a GET request to an HTTP tracker and reads the response,
+/// following up to [`MAX_REDIRECTS`] redirects. `target` returns the
+/// path and query of the first request, given its URL. The location
+/// of a redirect is requested as it comes, since it already has the
+/// parameters. Redirects from HTTPS to HTTP are refused, as they would
+/// send the parameters in cleartext.
+pub fn http_get<F>(
+    mut url: AnnounceUrl,
+    mut target: F,
+    logger: &mut logger::LogHandle,
+) -> Result<HttpResponse, String>
+where
+    F: FnMut(&AnnounceUrl) -> Result<String, TrackerRequestError>,
+{
+    let mut redirects = 0;
+    loop {
+        let stream = connect(&url).map_err(|e| e.to_string())?;
+        let path = if redirects == 0 {
+            target(&url).map_err(|e| e.to_string())?
+        } else {
+            url.request_target("")
         };
-        let local_addr = stream.local_addr();
-        let ip = match local_addr {
-            Ok(it) => it.ip(),
-            Err(_) => return Err(TrackerRequestError::InvalidAdress),
+        let stream = make_request(stream, &url, &path).map_err(|e| e.to_string())?;
+        info!("Tracker request sent");
+        logger.info("Tracker request sent");
+        let response = receive_response(stream).map_err(|e| e.to_string())?;
+
+        let location = match response.redirect_location() {
+            Some(location) => location,
+            None => return Ok(response),
         };
-        request.set_addr(ip);
-        let path = format!(
-            "/announce{}",
-            request
-                .generate_querystring()
-                .map_err(|_| TrackerRequestError::InvalidQuerystring)?
-                .get_querystring()
-        );
-        let header = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, host);
-        println!("{}", header);
-        match stream.write_all(header.as_bytes()) {
-            Ok(_) => Ok({
-                Stream {
-                    stream: Box::new(stream),
-                }
-            }),
-            Err(_) => Err(TrackerRequestError::WriteStream),
+        if redirects == MAX_REDIRECTS {
+            return Err(TrackerResponseError::TooManyRedirects.to_string());
         }
+        debug!("Tracker redirected to {}", location);
+        url = redirect(&url, location)?;
+        redirects += 1;
     }
 }
 
-fn receive_response(mut stream: Stream) -> Result<Vec<u8>, TrackerResponseError> {
-    let mut res = vec![];
+/// URL to follow a redirect to, as long as it doesn't downgrade an
+/// HTTPS request.
+fn redirect(url: &AnnounceUrl, location: &str) -> Result<AnnounceUrl, String> {
+    let next = url
+        .join(location)
+        .ok_or_else(|| TrackerRequestError::Host.to_string())?;
+    if url.scheme == Scheme::Https && next.scheme != Scheme::Https {
+        return Err(TrackerResponseError::InsecureRedirect.to_string());
+    }
+    Ok(next)
+}
+
+/// Announces to a tracker that uses the UDP protocol.
+fn announce_udp(
+    url: &AnnounceUrl,
+    request: &TrackerRequest,
+    logger: &mut logger::LogHandle,
+) -> Result<TrackerResponse, UdpTrackerError> {
+    let tracker = UdpTracker::new(&url.host, url.port)?;
+    info!("Tracker request sent");
+    logger.info("Tracker request sent");
+    tracker.announce(request)
+}
 
-    match stream.stream.read_to_end(&mut res) {
-        Ok(_) => {
-            let index = find_payload_index(&res).unwrap();
-            Ok(res[index + 4..].to_vec())
+/// Connects to an HTTP or HTTPS tracker.
+fn connect(url: &AnnounceUrl) -> Result<Box<dyn ReadWrite>, TrackerRequestError> {
+    let stream = connector::connect_tracker(&url.host, url.port, CONNECT_TIMEOUT)
+        .map_err(|e| TrackerRequestError::Connection(e.to_string()))?;
+    stream
+        .set_read_timeout(Some(READ_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(READ_TIMEOUT)))
+        .map_err(|_| TrackerRequestError::InvalidTcpStream)?;
+    let stream: Box<dyn ReadWrite> = match url.scheme {
+        Scheme::Http => Box::new(stream),
+        Scheme::Https => {
+            let connector = TlsConnector::new().map_err(|_| TrackerRequestError::Tls)?;
+            Box::new(
+                connector
+                    .connect(&url.host, stream)
+                    .map_err(|_| TrackerRequestError::Tls)?,
+            )
         }
-        Err(_) => Err(TrackerResponseError::ReadStream),
-    }
+        Scheme::Udp => return Err(TrackerRequestError::Host),
+    };
+    Ok(stream)
+}
+
+/// Sends the GET request for `target` through the connection.
+fn make_request(
+    mut stream: Box<dyn ReadWrite>,
+    url: &AnnounceUrl,
+    target: &str,
+) -> Result<Stream, TrackerRequestError> {
+    let header = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        target,
+        url.host_header()
+    );
+    debug!("{}", header);
+    stream
+        .write_all(header.as_bytes())
+        .map_err(|_| TrackerRequestError::WriteStream)?;
+
+    Ok(Stream { stream })
 }
 
-fn parse_response(
-    response: Vec<u8>,
-    peer_id: [u8; 20],
-) -> Result<TrackerResponse, TrackerResponseError> {
-    let bencoded_dictionary = parser::parse(response.clone()).unwrap();
-    println!("{:?}", from_utf8_lossy(&response));
-    TrackerResponse::new(bencoded_dictionary, peer_id).map_err(|_| TrackerResponseError::Parse)
+fn receive_response(stream: Stream) -> Result<HttpResponse, TrackerResponseError> {
+    HttpResponse::read(&mut BufReader::new(stream.stream))
 }
 
-fn find_payload_index(buffer: &[u8]) -> Option<usize> {
-    buffer
-        .windows(4)
-        .enumerate()
-        .find(|(_, w)| matches!(*w, b"\r\n\r\n"))
-        .map(|(i, _)| i)
+/// Parses the body of the response. A response with an error status is
+/// only accepted if the tracker explains the failure in the body.
+fn parse_response(response: HttpResponse) -> Result<TrackerResponse, TrackerResponseError> {
+    let bencoded_dictionary =
+        parser::parse_with_options(&response.body, ParserOptions::untrusted());
+    if !response.is_success() {
+        return match bencoded_dictionary.map(TrackerResponse::new) {
+            Ok(Ok(failure @ TrackerResponse(TrackerResponseMode::Failure(_)))) => Ok(failure),
+            _ => Err(TrackerResponseError::Status(response.status)),
+        };
+    }
+
+    let bencoded_dictionary = bencoded_dictionary.map_err(|_| TrackerResponseError::Parse)?;
+    debug!(
+        "Tracker response: {}",
+        json::to_json(&bencoded_dictionary, BinaryEncoding::Hex)
+    );
+    TrackerResponse::new(bencoded_dictionary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redirects_from_https_to_http_are_refused() {
+        let https = AnnounceUrl::parse("https://tracker/announce?passkey=x").unwrap();
+        let http = AnnounceUrl::parse("http://tracker/announce").unwrap();
+
+        assert_eq!(
+            redirect(&https, "http://tracker/announce?passkey=x"),
+            Err(TrackerResponseError::InsecureRedirect.to_string())
+        );
+        assert_eq!(
+            redirect(&https, "https://mirror/announce").map(|u| u.host),
+            Ok("mirror".to_string())
+        );
+        assert_eq!(
+            redirect(&https, "/b/announce").map(|u| u.scheme),
+            Ok(Scheme::Https)
+        );
+        assert_eq!(
+            redirect(&http, "https://mirror/announce").map(|u| u.scheme),
+            Ok(Scheme::Https)
+        );
+    }
 }
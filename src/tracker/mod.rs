@@ -1,5 +1,13 @@
+pub mod announce_list;
+pub mod announce_scheduler;
+pub mod announce_url;
 pub mod handler;
 pub mod request;
 pub mod response;
+pub mod scrape;
+pub mod scrape_error;
 pub mod tracker_handler_error;
+pub mod tracker_status;
+pub mod udp_tracker;
+pub mod udp_tracker_error;
 pub mod url_encoder;
@@ -1,14 +1,18 @@
+use crate::peer::peer_id::session_peer_id;
 use crate::tracker::request::querystring::Querystring;
 use crate::tracker::request::tracker_request_error::TrackerRequestError;
 use crate::tracker::request::tracker_request_event::TrackerRequestEvent;
+use crate::tracker::udp_tracker::ACTION_ANNOUNCE;
 use crate::tracker::url_encoder::encoder::URLEncoded;
-use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use std::fmt::Write;
-use std::net::{IpAddr, TcpStream};
+use std::net::IpAddr;
+
+/// Amount of peers asked to the tracker in every announce.
+pub const DEFAULT_NUMWANT: u32 = 50;
 
 /// This type is scoped on the handling of the request of the tracker.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct TrackerRequest {
     /// Describes the file(s) of the torrent. There are two.
     /// This value is going to be hashed with sha1.
@@ -17,7 +21,7 @@ pub struct TrackerRequest {
     pub announce: String,
     /// Peers's unique identificator.
     pub peer_id: [u8; 20],
-    /// Client's port.
+    /// Port the client is listening at.
     port: u16,
     /// The total amount uploaded since the client sent the 'started' event to the tracker
     uploaded: u64,
@@ -27,10 +31,19 @@ pub struct TrackerRequest {
     left: u64,
     /// Ip of the client
     ip: Option<IpAddr>,
-    /// Possible status of the request (started, stopped, completed)
-    event: TrackerRequestEvent,
+    /// Possible status of the request (started, stopped, completed).
+    /// [`None`] for the announces sent at regular intervals.
+    event: Option<TrackerRequestEvent>,
     /// Indicates if the client accepts a compact response
     compact: u8,
+    /// Amount of peers the client wants, [`None`] to let the tracker
+    /// decide.
+    numwant: Option<u32>,
+    /// Random value that lets the tracker identify the client if its
+    /// ip changes. It should be the same in every announce of a torrent.
+    key: u32,
+    /// Identifier sent by the tracker in a previous announce.
+    tracker_id: Option<Vec<u8>>,
 }
 
 impl TrackerRequest {
@@ -38,47 +51,88 @@ impl TrackerRequest {
     pub fn new(info: [u8; 20], announce: String, port: u16) -> Self {
         Self {
             info_hash: info,
-            peer_id: {
-                let random_chars: Vec<u8> = (&mut thread_rng())
-                    .sample_iter(Alphanumeric)
-                    .take(12)
-                    .collect();
-                ["-AZ2060-".as_bytes(), &random_chars[..]].concat()[0..20]
-                    .try_into()
-                    .unwrap()
-            },
+            peer_id: session_peer_id(),
             port,
             ip: None,
             uploaded: 0,
             downloaded: 0,
             left: 0,
-            event: TrackerRequestEvent::Started,
+            event: Some(TrackerRequestEvent::Started),
             announce,
-            compact: 0,
+            compact: 1,
+            numwant: Some(DEFAULT_NUMWANT),
+            key: thread_rng().gen(),
+            tracker_id: None,
         }
     }
 
-    /// Establish a TCP conection with the first port in the range [6881, 6889]
-    /// and sets its port number with that port.
-    fn set_port(&mut self, ip: IpAddr) {
-        let mut current_port: u16 = 6881;
-        loop {
-            if TcpStream::connect((ip, current_port)).is_ok() {
-                self.port = current_port;
-            }
+    /// Sets the ip address announced to the tracker. With [`None`]
+    /// the tracker uses the address the request comes from.
+    pub fn set_ip(&mut self, ip: Option<IpAddr>) {
+        self.ip = ip;
+    }
 
-            if current_port == 6889 {
-                break;
-            }
+    /// Sets the transfer statistics of the torrent, in bytes.
+    pub fn set_stats(&mut self, uploaded: u64, downloaded: u64, left: u64) {
+        self.uploaded = uploaded;
+        self.downloaded = downloaded;
+        self.left = left;
+    }
 
-            current_port += 1;
-        }
+    /// Sets the event of the announce, [`None`] for a regular one.
+    pub fn set_event(&mut self, event: Option<TrackerRequestEvent>) {
+        self.event = event;
+    }
+
+    /// Sets the amount of peers asked to the tracker, [`None`] to let
+    /// the tracker decide.
+    pub fn set_numwant(&mut self, numwant: Option<u32>) {
+        self.numwant = numwant;
     }
 
-    /// Sets the ip address direction of the client.    
-    pub fn set_addr(&mut self, ip: IpAddr) {
-        self.set_port(ip);
-        self.ip = Some(ip);
+    /// Sets the key sent to the tracker.
+    pub fn set_key(&mut self, key: u32) {
+        self.key = key;
+    }
+
+    /// Sets the `tracker id` received in a previous announce to the
+    /// same tracker.
+    pub fn set_tracker_id(&mut self, tracker_id: Option<Vec<u8>>) {
+        self.tracker_id = tracker_id;
+    }
+
+    /// Generates the announce packet of the UDP tracker protocol
+    /// (BEP 15), for the given connection and transaction.
+    pub fn generate_udp_announce(&self, connection_id: u64, transaction_id: u32) -> Vec<u8> {
+        let event: u32 = match self.event {
+            None => 0,
+            Some(TrackerRequestEvent::Completed) => 1,
+            Some(TrackerRequestEvent::Started) => 2,
+            Some(TrackerRequestEvent::Stopped) => 3,
+        };
+        let mut packet = Vec::with_capacity(98);
+        packet.extend_from_slice(&connection_id.to_be_bytes());
+        packet.extend_from_slice(&ACTION_ANNOUNCE.to_be_bytes());
+        packet.extend_from_slice(&transaction_id.to_be_bytes());
+        packet.extend_from_slice(&self.info_hash);
+        packet.extend_from_slice(&self.peer_id);
+        packet.extend_from_slice(&self.downloaded.to_be_bytes());
+        packet.extend_from_slice(&self.left.to_be_bytes());
+        packet.extend_from_slice(&self.uploaded.to_be_bytes());
+        packet.extend_from_slice(&event.to_be_bytes());
+        // With 0 the tracker uses the address the packet comes from. Only
+        // IPv4 addresses can be sent.
+        let ip = match self.ip {
+            Some(IpAddr::V4(ip)) => u32::from(ip),
+            _ => 0,
+        };
+        packet.extend_from_slice(&ip.to_be_bytes());
+        packet.extend_from_slice(&self.key.to_be_bytes());
+        // -1 lets the tracker decide the amount of peers
+        let numwant = self.numwant.map_or(-1, |n| n.min(i32::MAX as u32) as i32);
+        packet.extend_from_slice(&numwant.to_be_bytes());
+        packet.extend_from_slice(&self.port.to_be_bytes());
+        packet
     }
 
     /// Generates the querystring needed to do the request to the tracker.
@@ -114,17 +168,70 @@ impl TrackerRequest {
         write!(querystring, "&uploaded={}", self.uploaded)
             .map_err(|_| TrackerRequestError::WriteError)?;
         write!(querystring, "&left={}", self.left).map_err(|_| TrackerRequestError::WriteError)?;
-        write!(
-            querystring,
-            "&event={}",
-            match self.event {
-                TrackerRequestEvent::Started => "started",
-                TrackerRequestEvent::Completed => "completed",
-                TrackerRequestEvent::Stopped => "stopped",
-            }
-        )
-        .map_err(|_| TrackerRequestError::WriteError)?;
+        if let Some(event) = &self.event {
+            write!(
+                querystring,
+                "&event={}",
+                match event {
+                    TrackerRequestEvent::Started => "started",
+                    TrackerRequestEvent::Completed => "completed",
+                    TrackerRequestEvent::Stopped => "stopped",
+                }
+            )
+            .map_err(|_| TrackerRequestError::WriteError)?;
+        }
+        write!(querystring, "&compact={}", self.compact)
+            .map_err(|_| TrackerRequestError::WriteError)?;
+        if let Some(numwant) = self.numwant {
+            write!(querystring, "&numwant={}", numwant)
+                .map_err(|_| TrackerRequestError::WriteError)?;
+        }
+        write!(querystring, "&key={:08x}", self.key)
+            .map_err(|_| TrackerRequestError::WriteError)?;
+        if let Some(tracker_id) = &self.tracker_id {
+            let tracker_id = URLEncoded::encode(tracker_id)
+                .map_err(|_| TrackerRequestError::EncoderError)?
+                .get_url();
+            write!(querystring, "&trackerid={}", tracker_id)
+                .map_err(|_| TrackerRequestError::WriteError)?;
+        }
 
         Ok(Querystring(querystring))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn querystring_asks_for_compact_peers() {
+        let mut request = TrackerRequest::new([b'a'; 20], "http://t/announce".into(), 6881);
+        request.set_key(0xbeef);
+        request.set_event(None);
+        request.set_tracker_id(Some(b"id 1".to_vec()));
+
+        let querystring = request.generate_querystring().unwrap().get_querystring();
+
+        assert!(querystring.ends_with(&format!(
+            "&port=6881&downloaded=0&uploaded=0&left=0&compact=1&numwant={}&key=0000beef&trackerid=id%201",
+            DEFAULT_NUMWANT
+        )));
+    }
+
+    #[test]
+    fn udp_announce_has_key_and_numwant() {
+        let mut request = TrackerRequest::new([b'a'; 20], "udp://t".into(), 6881);
+        request.set_key(7);
+        request.set_numwant(None);
+        request.set_ip(Some(IpAddr::from([203, 0, 113, 7])));
+
+        let packet = request.generate_udp_announce(1, 2);
+
+        assert_eq!(packet.len(), 98);
+        assert_eq!(packet[84..88], [203, 0, 113, 7]);
+        assert_eq!(packet[88..92], 7u32.to_be_bytes());
+        assert_eq!(packet[92..96], (-1i32).to_be_bytes());
+        assert_eq!(packet[96..], 6881u16.to_be_bytes());
+    }
+}
@@ -12,6 +12,10 @@ pub enum TrackerRequestError {
     InvalidAdress,
     InvalidQuerystring,
     WriteStream,
+    Tls,
+    /// The connection couldn't be established, directly or through
+    /// the proxy
+    Connection(String),
 }
 
 impl fmt::Display for TrackerRequestError {
@@ -31,6 +35,8 @@ impl fmt::Display for TrackerRequestError {
                 write!(f, "An error ocurred generating the query")
             }
             TrackerRequestError::WriteStream => write!(f, "Couldn't write to stream'"),
+            TrackerRequestError::Tls => write!(f, "Couldn't establish a TLS connection"),
+            TrackerRequestError::Connection(e) => write!(f, "{}", e),
         }
     }
 }
@@ -0,0 +1,278 @@
+use crate::tracker::response::tracker_response_error::TrackerResponseError;
+use std::io::{self, BufRead, ErrorKind, Read};
+
+/// Longest status line, header or chunk size line accepted.
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+/// Maximum amount of headers, or trailers, accepted in a response.
+const MAX_HEADERS: usize = 100;
+/// Largest body accepted. Tracker responses are small even when they
+/// carry a lot of peers.
+pub const MAX_BODY_LENGTH: usize = 4 * 1024 * 1024;
+
+/// # HttpResponse
+/// HTTP/1.x response of a tracker, with the body already read
+/// according to its `Content-Length` or chunked encoding.
+#[derive(Debug, PartialEq, Eq)]
+pub struct HttpResponse {
+    /// Status code
+    pub status: u16,
+    /// Reason phrase of the status line
+    pub reason: String,
+    /// Headers in the order in which they were received. The names
+    /// are lowercase.
+    pub headers: Vec<(String, String)>,
+    /// Body of the response, without the transfer encoding
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Reads a whole response from `reader`. Interim `1xx` responses
+    /// are skipped. If the response has neither `Content-Length` nor
+    /// chunked encoding the body is read until the connection is closed.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self, TrackerResponseError> {
+        let mut response = Self::read_head(reader)?;
+        while (100..200).contains(&response.status) {
+            response = Self::read_head(reader)?;
+        }
+        response.body = response.read_body(reader)?;
+        Ok(response)
+    }
+
+    /// Returns the value of the first header called `name`, ignoring
+    /// case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns true if the status code is `2xx`.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Returns the `Location` to follow if the response is a
+    /// redirect, else returns [`None`].
+    pub fn redirect_location(&self) -> Option<&str> {
+        match self.status {
+            301 | 302 | 303 | 307 | 308 => self.header("location"),
+            _ => None,
+        }
+    }
+
+    /// Reads the status line and the headers.
+    fn read_head<R: BufRead>(reader: &mut R) -> Result<Self, TrackerResponseError> {
+        let status_line = read_line(reader)?;
+        let mut parts = status_line.splitn(3, ' ');
+        if !parts.next().unwrap_or_default().starts_with("HTTP/1.") {
+            return Err(TrackerResponseError::InvalidHttp);
+        }
+        let status = parts
+            .next()
+            .and_then(|s| s.parse().ok())
+            .filter(|s| (100..600).contains(s))
+            .ok_or(TrackerResponseError::InvalidHttp)?;
+        let reason = parts.next().unwrap_or_default().to_string();
+
+        let headers = read_headers(reader)?
+            .into_iter()
+            .map(|(n, v)| (n.to_ascii_lowercase(), v))
+            .collect();
+        Ok(Self {
+            status,
+            reason,
+            headers,
+            body: Vec::new(),
+        })
+    }
+
+    fn read_body<R: BufRead>(&self, reader: &mut R) -> Result<Vec<u8>, TrackerResponseError> {
+        if self.status == 204 || self.status == 304 {
+            return Ok(Vec::new());
+        }
+        let chunked = self
+            .header("transfer-encoding")
+            .map(|te| te.to_ascii_lowercase().contains("chunked"))
+            .unwrap_or(false);
+        if chunked {
+            return read_chunked(reader);
+        }
+
+        match self.header("content-length") {
+            Some(length) => {
+                let length: usize = length
+                    .parse()
+                    .map_err(|_| TrackerResponseError::InvalidHttp)?;
+                if length > MAX_BODY_LENGTH {
+                    return Err(TrackerResponseError::TooLarge);
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).map_err(read_error)?;
+                Ok(body)
+            }
+            None => {
+                let mut body = Vec::new();
+                reader
+                    .by_ref()
+                    .take(MAX_BODY_LENGTH as u64 + 1)
+                    .read_to_end(&mut body)
+                    .map_err(read_error)?;
+                if body.len() > MAX_BODY_LENGTH {
+                    return Err(TrackerResponseError::TooLarge);
+                }
+                Ok(body)
+            }
+        }
+    }
+}
+
+/// Reads a body sent with chunked transfer encoding, discarding the
+/// chunk extensions and the trailers.
+fn read_chunked<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, TrackerResponseError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size =
+            usize::from_str_radix(size, 16).map_err(|_| TrackerResponseError::InvalidHttp)?;
+        if size == 0 {
+            break;
+        }
+        if body.len().saturating_add(size) > MAX_BODY_LENGTH {
+            return Err(TrackerResponseError::TooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).map_err(read_error)?;
+        if !read_line(reader)?.is_empty() {
+            return Err(TrackerResponseError::InvalidHttp);
+        }
+    }
+    read_headers(reader)?;
+    Ok(body)
+}
+
+/// Reads `name: value` lines until an empty one.
+fn read_headers<R: BufRead>(reader: &mut R) -> Result<Vec<(String, String)>, TrackerResponseError> {
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(TrackerResponseError::InvalidHttp);
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(TrackerResponseError::InvalidHttp)?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+}
+
+/// Reads a line ended by `\n`, returning it without the line break.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<String, TrackerResponseError> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_LENGTH)
+        .read_until(b'\n', &mut line)
+        .map_err(read_error)?;
+    if line.last() != Some(&b'\n') {
+        return Err(if line.len() as u64 == MAX_LINE_LENGTH {
+            TrackerResponseError::InvalidHttp
+        } else {
+            TrackerResponseError::ReadStream
+        });
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+fn read_error(e: io::Error) -> TrackerResponseError {
+    match e.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => TrackerResponseError::Timeout,
+        _ => TrackerResponseError::ReadStream,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(response: &[u8]) -> Result<HttpResponse, TrackerResponseError> {
+        HttpResponse::read(&mut &response[..])
+    }
+
+    #[test]
+    fn read_response_with_content_length() {
+        let response = read(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: 5\r\n\r\nhelloextra",
+        )
+        .unwrap();
+
+        assert_eq!(response.status, 200);
+        assert_eq!(response.reason, "OK");
+        assert_eq!(response.header("CONTENT-TYPE"), Some("text/plain"));
+        assert_eq!(response.body, b"hello");
+        assert!(response.is_success());
+    }
+
+    #[test]
+    fn read_chunked_response() {
+        let response = read(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+              4;ext=1\r\nd8:i\r\nB\r\nntervali1ee\r\n0\r\nX-Trailer: a\r\n\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(response.body, b"d8:intervali1ee"[..]);
+    }
+
+    #[test]
+    fn read_until_close_and_skip_interim_responses() {
+        let response =
+            read(b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.0 404 Not Found\n\nnope").unwrap();
+
+        assert_eq!(response.status, 404);
+        assert_eq!(response.body, b"nope");
+        assert!(!response.is_success());
+    }
+
+    #[test]
+    fn redirect_location() {
+        let response =
+            read(b"HTTP/1.1 302 Found\r\nLocation: /new\r\nContent-Length: 0\r\n\r\n").unwrap();
+
+        assert_eq!(response.redirect_location(), Some("/new"));
+    }
+
+    #[test]
+    fn read_invalid_responses_returns_error() {
+        assert_eq!(
+            read(b"d8:intervali1ee").unwrap_err(),
+            TrackerResponseError::ReadStream
+        );
+        assert_eq!(
+            read(b"SPDY 200 OK\r\n\r\n").unwrap_err(),
+            TrackerResponseError::InvalidHttp
+        );
+        assert_eq!(
+            read(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort").unwrap_err(),
+            TrackerResponseError::ReadStream
+        );
+        assert_eq!(
+            read(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n").unwrap_err(),
+            TrackerResponseError::InvalidHttp
+        );
+        assert_eq!(
+            read(b"HTTP/1.1 200 OK\r\nContent-Length: 99999999\r\n\r\n").unwrap_err(),
+            TrackerResponseError::TooLarge
+        );
+    }
+}
@@ -1,3 +1,5 @@
+pub mod http_response;
+pub mod scrape_data;
 pub mod tracker_response;
 pub mod tracker_response_builder;
 pub mod tracker_response_error;
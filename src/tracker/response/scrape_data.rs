@@ -0,0 +1,10 @@
+/// Statistics of a torrent returned by a scrape of its tracker.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct ScrapeData {
+    /// Number of peers with the entire file.
+    pub complete: i64,
+    /// Number of times the download was completed.
+    pub downloaded: i64,
+    /// Number of non-seeder peers.
+    pub incomplete: i64,
+}
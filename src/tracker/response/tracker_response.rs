@@ -5,13 +5,15 @@ use crate::{bencode::bencoded_value::BencodedValue, peer::peer_handler::Peer};
 use std::collections::HashMap;
 
 // static RESPONSE_REQUIRED_KEYS: [&[u8]; 4] = [b"interval", b"complete", b"incomplete", b"peers"];
-static RESPONSE_REQUIRED_KEYS: [&[u8]; 2] = [b"interval", b"peers"];
+static RESPONSE_REQUIRED_KEYS: [&[u8]; 1] = [b"interval"];
+/// A response must have at least one of these.
+static RESPONSE_PEERS_KEYS: [&[u8]; 2] = [b"peers", b"peers6"];
 
 /// Represents the possible variants of the response of the tracker.
 #[derive(Debug, PartialEq, Eq)]
 pub enum TrackerResponseMode {
-    /// Placeholder.
-    Failure,
+    /// The tracker rejected the request, with the reason it gave.
+    Failure(String),
     /// Response data mode.
     Response(ResponseData),
 }
@@ -34,30 +36,48 @@ pub struct ResponseData {
     pub peers: Vec<Peer>,
     /// Minimum announce interval.
     pub min_interval: Option<i64>,
+    /// Warning sent by the tracker along with a successful response.
+    pub warning_message: Option<String>,
+    /// Identifier that must be sent back to the tracker in the next
+    /// announces.
+    pub tracker_id: Option<Vec<u8>>,
 }
 
 impl TrackerResponse {
     /// Creates a new TrackerResponse structure from a bencoded dictionary.
-    /// Returns [`Some`] if no errors occur while building the instance; otherwise returns [`None`].
-    pub fn new(
-        bencoded_value: BencodedValue,
-        peer_id: [u8; 20],
-    ) -> Result<Self, TrackerResponseError> {
+    /// If the dictionary has a `failure reason` the response is a
+    /// [`TrackerResponseMode::Failure`] and the rest of the keys are ignored.
+    /// Returns an error if the dictionary is missing some required key,
+    /// has neither `peers` nor `peers6`, or if a key has a value of the
+    /// wrong type. Keys the client doesn't know are ignored.
+    pub fn new(bencoded_value: BencodedValue) -> Result<Self, TrackerResponseError> {
         let dict = bencoded_value
             .dictionary()
             .ok_or(TrackerResponseError::InvalidResponse)?
             .into_iter()
             .collect::<HashMap<BencodedValue, BencodedValue>>();
+        if let Some(reason) = dict.get(&BencodedValue::ByteString(b"failure reason".to_vec())) {
+            let reason = reason
+                .as_byte_string()
+                .ok_or(TrackerResponseError::InvalidResponse)?;
+            return Ok(TrackerResponse(TrackerResponseMode::Failure(
+                String::from_utf8_lossy(reason).into_owned(),
+            )));
+        }
         let mut required = RESPONSE_REQUIRED_KEYS
             .iter()
             .map(|v| BencodedValue::ByteString(v.to_vec()));
-        let has_requiered = required.all(|k| dict.contains_key(&k));
+        let has_requiered = required.all(|k| dict.contains_key(&k))
+            && RESPONSE_PEERS_KEYS
+                .iter()
+                .any(|k| dict.contains_key(&BencodedValue::ByteString(k.to_vec())));
 
         if has_requiered {
             let mut tracker_response = TrackerResponseBuilder::new();
             for (k, v) in dict {
                 if let BencodedValue::ByteString(s) = k {
-                    build_response_fields(&mut tracker_response, &s[..], v, peer_id);
+                    build_response_fields(&mut tracker_response, &s[..], v)
+                        .ok_or(TrackerResponseError::InvalidResponse)?;
                 } else {
                     return Err(TrackerResponseError::InvalidResponse);
                 }
@@ -75,7 +95,6 @@ fn build_response_fields(
     tracker_response: &mut TrackerResponseBuilder,
     field: &[u8],
     value: BencodedValue,
-    peer_id: [u8; 20],
 ) -> Option<()> {
     match field {
         b"interval" => {
@@ -98,35 +117,32 @@ fn build_response_fields(
                 let mut peers = Vec::new();
                 for p in list {
                     let dict = p.dictionary()?;
-                    let peer = Peer::new_dict(dict, peer_id)?;
-                    peers.push(peer);
-                }
-                tracker_response.peers(peers);
-            } else if let BencodedValue::ByteString(b) = value {
-                let mut peers: Vec<Peer> = Vec::new();
-                let peer_ammount = b.len() / 6;
-                let mut i = 0;
-                loop {
-                    if i >= peer_ammount * 6 {
-                        break;
-                    }
-
-                    let ip = &b[i..i + 4];
-                    let port = &b[i + 4..i + 6];
-
-                    i += 6;
-
-                    let peer = Peer::new_byte_string(ip, port, peer_id)?;
+                    let peer = Peer::new_dict(dict)?;
                     peers.push(peer);
                 }
-                tracker_response.peers(peers);
+                tracker_response.add_peers(peers);
+            } else {
+                let b = value.byte_string()?;
+                tracker_response.add_peers(Peer::new_compact_list(&b, false));
             }
         }
+        b"peers6" => {
+            let b = value.byte_string()?;
+            tracker_response.add_peers(Peer::new_compact_list(&b, true));
+        }
         b"min interval" => {
             let min_interval = value.integer()?;
             tracker_response.min_interval(min_interval);
         }
-        _ => return None,
+        b"warning message" => {
+            let warning = value.byte_string()?;
+            tracker_response.warning_message(String::from_utf8_lossy(&warning).into_owned());
+        }
+        b"tracker id" => {
+            let tracker_id = value.byte_string()?;
+            tracker_response.tracker_id(tracker_id);
+        }
+        _ => (),
     };
     Some(())
 }
@@ -134,26 +150,24 @@ fn build_response_fields(
 #[cfg(test)]
 mod tests {
 
-    use std::net::{IpAddr, Ipv4Addr};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 
     use super::*;
     use crate::bencode::parser;
+    use crate::peer::peer_source::PeerSource;
 
     #[test]
     fn normal_response_with_dictionary_mode() {
         let response = "d8:intervali1800e8:completei1900e10:incompletei1700e5:peersld2:ip13:192.168.189.14:porti20111eeee".into();
         let bencoded_dictionary = parser::parse(response).unwrap();
-        let tracker_response = TrackerResponse::new(
-            bencoded_dictionary,
-            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-        )
-        .unwrap();
+        let tracker_response = TrackerResponse::new(bencoded_dictionary).unwrap();
 
         let mut vec_peers = Vec::new();
         let peer = Peer {
-            peer_id: Some([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            peer_id: None,
             ip: Some(IpAddr::V4(Ipv4Addr::new(192, 168, 189, 1))),
             port: 20111,
+            source: PeerSource::Tracker,
         };
         vec_peers.push(peer);
         let response = TrackerResponse(TrackerResponseMode::Response(ResponseData {
@@ -162,17 +176,102 @@ mod tests {
             incomplete: 1700,
             peers: vec_peers,
             min_interval: None,
+            warning_message: None,
+            tracker_id: None,
         }));
         assert_eq!(tracker_response, response);
     }
 
+    #[test]
+    fn response_with_warning_and_tracker_id() {
+        let response =
+            "d8:intervali900e5:peers0:15:warning message7:go slow10:tracker id3:abce".into();
+        let bencoded_dictionary = parser::parse(response).unwrap();
+
+        let TrackerResponse(TrackerResponseMode::Response(data)) =
+            TrackerResponse::new(bencoded_dictionary).unwrap()
+        else {
+            panic!("expected a response");
+        };
+        assert_eq!(data.warning_message, Some("go slow".to_string()));
+        assert_eq!(data.tracker_id, Some(b"abc".to_vec()));
+    }
+
+    #[test]
+    fn response_with_compact_peers_and_peers6() {
+        let mut response = b"d8:intervali900e5:peers6:".to_vec();
+        response.extend_from_slice(&[10, 0, 0, 1, 0x1a, 0xe1]);
+        response.extend_from_slice(b"6:peers618:");
+        response.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        response.extend_from_slice(&[0x1a, 0xe2, b'e']);
+        let bencoded_dictionary = parser::parse(response).unwrap();
+
+        let TrackerResponse(TrackerResponseMode::Response(data)) =
+            TrackerResponse::new(bencoded_dictionary).unwrap()
+        else {
+            panic!("expected a response");
+        };
+        let mut addrs: Vec<_> = data.peers.iter().flat_map(Peer::addr).collect();
+        addrs.sort();
+        assert_eq!(
+            addrs,
+            vec![
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 6881),
+                SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 6882),
+            ]
+        );
+    }
+
+    #[test]
+    fn failure_reason_is_returned_without_required_keys() {
+        let response = "d14:failure reason17:torrent not founde".into();
+        let bencoded_dictionary = parser::parse(response).unwrap();
+
+        assert_eq!(
+            TrackerResponse::new(bencoded_dictionary).unwrap(),
+            TrackerResponse(TrackerResponseMode::Failure(
+                "torrent not found".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn response_with_mistyped_fields_is_invalid() {
+        for response in [
+            "d8:interval3:abc5:peers0:e",
+            "d8:intervali900e5:peersi3ee",
+            "d8:intervali900e5:peers0:8:completelee",
+            "d8:intervali900e5:peersli1eee",
+        ] {
+            let bencoded_dictionary = parser::parse(response.into()).unwrap();
+
+            assert_eq!(
+                TrackerResponse::new(bencoded_dictionary).unwrap_err(),
+                TrackerResponseError::InvalidResponse
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_ignored() {
+        let response = "d11:external ip4:\x7f\x00\x00\x018:intervali900e5:peers0:e".into();
+        let bencoded_dictionary = parser::parse(response).unwrap();
+
+        let TrackerResponse(TrackerResponseMode::Response(data)) =
+            TrackerResponse::new(bencoded_dictionary).unwrap()
+        else {
+            panic!("expected a response");
+        };
+        assert_eq!(data.interval, 900);
+    }
+
     #[test]
     fn response_with_few_keys() {
         let response = "d5:peersld2:ip13:192.168.189.14:porti20111eeee".into();
         let bencoded_dictionary = parser::parse(response).unwrap();
 
         assert_eq!(
-            TrackerResponse::new(bencoded_dictionary, [0u8; 20]).unwrap_err(),
+            TrackerResponse::new(bencoded_dictionary).unwrap_err(),
             TrackerResponseError::InvalidResponse
         );
     }
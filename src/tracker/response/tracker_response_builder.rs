@@ -10,6 +10,8 @@ pub struct TrackerResponseBuilder {
     incomplete: i64,
     peers: Vec<Peer>,
     min_interval: Option<i64>,
+    warning_message: Option<String>,
+    tracker_id: Option<Vec<u8>>,
 }
 
 impl Default for TrackerResponseBuilder {
@@ -26,6 +28,8 @@ impl TrackerResponseBuilder {
             incomplete: 0,
             peers: Vec::new(),
             min_interval: None,
+            warning_message: None,
+            tracker_id: None,
         }
     }
 
@@ -49,11 +53,28 @@ impl TrackerResponseBuilder {
         self
     }
 
+    /// Adds peers to the ones already set, for responses that split
+    /// them between `peers` and `peers6`.
+    pub fn add_peers(&'_ mut self, p: Vec<Peer>) -> &'_ mut Self {
+        self.peers.extend(p);
+        self
+    }
+
     pub fn min_interval(&'_ mut self, mi: i64) -> &'_ mut Self {
         self.min_interval = Some(mi);
         self
     }
 
+    pub fn warning_message(&'_ mut self, w: String) -> &'_ mut Self {
+        self.warning_message = Some(w);
+        self
+    }
+
+    pub fn tracker_id(&'_ mut self, id: Vec<u8>) -> &'_ mut Self {
+        self.tracker_id = Some(id);
+        self
+    }
+
     pub fn response_data(self) -> ResponseData {
         ResponseData {
             interval: self.interval,
@@ -61,6 +82,8 @@ impl TrackerResponseBuilder {
             incomplete: self.incomplete,
             peers: self.peers,
             min_interval: self.min_interval,
+            warning_message: self.warning_message,
+            tracker_id: self.tracker_id,
         }
     }
 
@@ -7,6 +7,18 @@ pub enum TrackerResponseError {
     InvalidResponse,
     ReadStream,
     Parse,
+    /// The response is not valid HTTP
+    InvalidHttp,
+    /// The response is larger than the maximum accepted
+    TooLarge,
+    /// The tracker took too long to answer
+    Timeout,
+    /// The tracker answered with an error status code
+    Status(u16),
+    /// The tracker redirected the request too many times
+    TooManyRedirects,
+    /// An HTTPS tracker redirected the request to an HTTP URL
+    InsecureRedirect,
 }
 
 impl fmt::Display for TrackerResponseError {
@@ -19,6 +31,22 @@ impl fmt::Display for TrackerResponseError {
                 write!(f, "An error ocurred while reading from the stream")
             }
             TrackerResponseError::Parse => write!(f, "An error ocurred while parsing the response"),
+            TrackerResponseError::InvalidHttp => {
+                write!(f, "The tracker sent an invalid HTTP response")
+            }
+            TrackerResponseError::TooLarge => {
+                write!(f, "The response from the tracker is too large")
+            }
+            TrackerResponseError::Timeout => write!(f, "The tracker didn't answer in time"),
+            TrackerResponseError::Status(status) => {
+                write!(f, "The tracker answered with status {}", status)
+            }
+            TrackerResponseError::TooManyRedirects => {
+                write!(f, "The tracker redirected the request too many times")
+            }
+            TrackerResponseError::InsecureRedirect => {
+                write!(f, "The tracker redirected the request to an insecure URL")
+            }
         }
     }
 }
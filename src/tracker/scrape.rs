@@ -0,0 +1,172 @@
+use crate::bencode::bencoded_value::BencodedValue;
+use crate::bencode::parser;
+use crate::bencode::parser_options::ParserOptions;
+use crate::log::logger::LogHandle;
+use crate::tracker::announce_url::{AnnounceUrl, Scheme};
+use crate::tracker::handler::http_get;
+use crate::tracker::request::tracker_request_error::TrackerRequestError;
+use crate::tracker::response::http_response::HttpResponse;
+use crate::tracker::response::scrape_data::ScrapeData;
+use crate::tracker::response::tracker_response_error::TrackerResponseError;
+use crate::tracker::scrape_error::ScrapeError;
+use crate::tracker::udp_tracker::UdpTracker;
+use crate::tracker::url_encoder::encoder::URLEncoded;
+
+use std::collections::HashMap;
+
+/// Maximum amount of info hashes in an HTTP scrape, to keep the URL
+/// short.
+const MAX_HTTP_SCRAPE_HASHES: usize = 40;
+
+/// Asks the tracker of the announce URL for the statistics of the
+/// torrents, in as few requests as possible. Returns the statistics of
+/// the torrents the tracker knows about.
+pub fn scrape(
+    announce: &str,
+    info_hashes: &[[u8; 20]],
+    logger: &mut LogHandle,
+) -> Result<HashMap<[u8; 20], ScrapeData>, ScrapeError> {
+    let url = AnnounceUrl::parse(announce)
+        .and_then(|url| url.scrape_url())
+        .ok_or(ScrapeError::Unsupported)?;
+
+    if url.scheme == Scheme::Udp {
+        let stats = UdpTracker::new(&url.host, url.port)?.scrape(info_hashes)?;
+        return Ok(info_hashes.iter().copied().zip(stats).collect());
+    }
+    let mut stats = HashMap::new();
+    for hashes in info_hashes.chunks(MAX_HTTP_SCRAPE_HASHES) {
+        let query = scrape_query(hashes)?;
+        let response = http_get(url.clone(), |url| Ok(url.request_target(&query)), logger)
+            .map_err(ScrapeError::Request)?;
+        stats.extend(parse_scrape(&response)?);
+    }
+    Ok(stats)
+}
+
+/// Query with one `info_hash` parameter per torrent.
+fn scrape_query(info_hashes: &[[u8; 20]]) -> Result<String, ScrapeError> {
+    info_hashes
+        .iter()
+        .map(|h| URLEncoded::encode(h).map(|e| format!("info_hash={}", e.get_url())))
+        .collect::<Result<Vec<_>, _>>()
+        .map(|params| params.join("&"))
+        .map_err(|_| ScrapeError::Request(TrackerRequestError::EncoderError.to_string()))
+}
+
+/// Parses the `files` dictionary of the response, whose keys are the
+/// info hashes. Missing statistics are taken as zero.
+fn parse_scrape(response: &HttpResponse) -> Result<HashMap<[u8; 20], ScrapeData>, ScrapeError> {
+    let value = parser::parse_with_options(&response.body, ParserOptions::untrusted());
+    if let Some(reason) = value
+        .as_ref()
+        .ok()
+        .and_then(|v| v.get(b"failure reason"))
+        .and_then(BencodedValue::as_byte_string)
+    {
+        return Err(ScrapeError::Failure(
+            String::from_utf8_lossy(reason).into_owned(),
+        ));
+    }
+    if !response.is_success() {
+        return Err(ScrapeError::Request(
+            TrackerResponseError::Status(response.status).to_string(),
+        ));
+    }
+
+    let value = value.map_err(|_| ScrapeError::InvalidResponse)?;
+    let files = value
+        .get(b"files")
+        .and_then(BencodedValue::as_dictionary)
+        .ok_or(ScrapeError::InvalidResponse)?;
+    files
+        .iter()
+        .map(|(hash, stats)| {
+            let hash = hash
+                .as_byte_string()
+                .and_then(|h| h.try_into().ok())
+                .ok_or(ScrapeError::InvalidResponse)?;
+            let field = |k: &[u8]| {
+                stats
+                    .get(k)
+                    .and_then(BencodedValue::as_integer)
+                    .unwrap_or_default()
+            };
+            let stats = ScrapeData {
+                complete: field(b"complete"),
+                downloaded: field(b"downloaded"),
+                incomplete: field(b"incomplete"),
+            };
+            Ok((hash, stats))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, body: &[u8]) -> HttpResponse {
+        HttpResponse {
+            status,
+            reason: String::new(),
+            headers: Vec::new(),
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn parse_scrape_of_several_torrents() {
+        let mut body = b"d5:filesd20:".to_vec();
+        body.extend_from_slice(&[1; 20]);
+        body.extend_from_slice(b"d8:completei5e10:downloadedi50e10:incompletei10ee20:");
+        body.extend_from_slice(&[2; 20]);
+        body.extend_from_slice(b"d8:completei1eee");
+        body.push(b'e');
+
+        let got = parse_scrape(&response(200, &body)).unwrap();
+
+        assert_eq!(got.len(), 2);
+        assert_eq!(
+            got[&[1; 20]],
+            ScrapeData {
+                complete: 5,
+                downloaded: 50,
+                incomplete: 10
+            }
+        );
+        assert_eq!(
+            got[&[2; 20]],
+            ScrapeData {
+                complete: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn parse_scrape_failures() {
+        assert_eq!(
+            parse_scrape(&response(400, b"d14:failure reason4:nopee")),
+            Err(ScrapeError::Failure("nope".into()))
+        );
+        assert_eq!(
+            parse_scrape(&response(404, b"not found")),
+            Err(ScrapeError::Request(
+                TrackerResponseError::Status(404).to_string()
+            ))
+        );
+        assert_eq!(
+            parse_scrape(&response(200, b"d5:filesi0ee")),
+            Err(ScrapeError::InvalidResponse)
+        );
+    }
+
+    #[test]
+    fn scrape_query_has_every_hash() {
+        assert_eq!(
+            scrape_query(&[[b'a'; 20], [b'b'; 20]]).unwrap(),
+            format!("info_hash={}&info_hash={}", "a".repeat(20), "b".repeat(20))
+        );
+    }
+}
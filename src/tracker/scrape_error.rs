@@ -0,0 +1,39 @@
+use crate::tracker::udp_tracker_error::UdpTrackerError;
+use std::fmt;
+
+/// Represents the possible errors that can occur while scraping a
+/// tracker.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ScrapeError {
+    /// The announce URL has no scrape URL
+    Unsupported,
+    /// The request couldn't be sent or the response couldn't be read
+    Request(String),
+    /// The response from the tracker is invalid
+    InvalidResponse,
+    /// The tracker rejected the scrape, with the reason it gave
+    Failure(String),
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScrapeError::Unsupported => write!(f, "The tracker doesn't support scrapes"),
+            ScrapeError::Request(e) => write!(f, "{}", e),
+            ScrapeError::InvalidResponse => write!(f, "The scrape response is invalid"),
+            ScrapeError::Failure(reason) => {
+                write!(f, "The tracker returned a failure: {}", reason)
+            }
+        }
+    }
+}
+
+impl From<UdpTrackerError> for ScrapeError {
+    fn from(e: UdpTrackerError) -> Self {
+        match e {
+            UdpTrackerError::Tracker(reason) => ScrapeError::Failure(reason),
+            UdpTrackerError::InvalidResponse => ScrapeError::InvalidResponse,
+            e => ScrapeError::Request(e.to_string()),
+        }
+    }
+}
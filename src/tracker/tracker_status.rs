@@ -0,0 +1,34 @@
+use std::time::SystemTime;
+
+/// State of one of the trackers of a torrent, as seen by the last
+/// announce sent to it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TrackerStatus {
+    /// Announce URL of the tracker
+    pub url: String,
+    /// Tier of the announce list the tracker belongs to, starting at
+    /// zero
+    pub tier: usize,
+    /// Time of the last announce sent to the tracker
+    pub last_announce: Option<SystemTime>,
+    /// Error of the last announce, [`None`] if it succeeded
+    pub last_error: Option<String>,
+    /// Amount of peers returned by the last successful announce
+    pub peers: usize,
+    /// `tracker id` sent by the tracker, to send back in the next
+    /// announces
+    pub tracker_id: Option<Vec<u8>>,
+}
+
+impl TrackerStatus {
+    pub fn new(url: String, tier: usize) -> Self {
+        Self {
+            url,
+            tier,
+            last_announce: None,
+            last_error: None,
+            peers: 0,
+            tracker_id: None,
+        }
+    }
+}
@@ -0,0 +1,404 @@
+use crate::peer::peer_handler::Peer;
+use crate::proxy::connector;
+use crate::tracker::request::tracker_request::TrackerRequest;
+use crate::tracker::response::scrape_data::ScrapeData;
+use crate::tracker::response::tracker_response::{TrackerResponse, TrackerResponseMode};
+use crate::tracker::response::tracker_response_builder::TrackerResponseBuilder;
+use crate::tracker::udp_tracker_error::UdpTrackerError;
+
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Magic constant that identifies the connect packets.
+const PROTOCOL_ID: u64 = 0x41727101980;
+pub const ACTION_CONNECT: u32 = 0;
+pub const ACTION_ANNOUNCE: u32 = 1;
+pub const ACTION_SCRAPE: u32 = 2;
+pub const ACTION_ERROR: u32 = 3;
+
+/// A connection id can be used for a minute after it's received.
+const CONNECTION_ID_LIFETIME: Duration = Duration::from_secs(60);
+/// Wait for the first answer, doubled after every retransmission.
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Amount of times a packet is sent again if the tracker doesn't answer.
+const RETRIES: u32 = 2;
+/// Maximum amount of info hashes in a scrape packet.
+const MAX_SCRAPE_HASHES: usize = 74;
+/// Largest packet read from the tracker.
+const MAX_PACKET_LENGTH: usize = 64 * 1024;
+
+/// Connection ids of the trackers, shared by every announce so they
+/// aren't requested again while they are valid.
+static CONNECTION_IDS: Mutex<Vec<(SocketAddr, u64, Instant)>> = Mutex::new(Vec::new());
+
+/// # UdpTracker
+/// Client of the UDP tracker protocol (BEP 15). Every exchange starts
+/// with a connect that returns a connection id, which is cached, and
+/// packets that get no answer are sent again with an exponential
+/// timeout.
+#[derive(Debug)]
+pub struct UdpTracker {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl UdpTracker {
+    /// Resolves the address of the tracker and binds a socket of the
+    /// same family.
+    pub fn new(host: &str, port: u16) -> Result<Self, UdpTrackerError> {
+        if !connector::allows_udp() {
+            return Err(UdpTrackerError::ProxyOnly);
+        }
+        let addr = (host, port)
+            .to_socket_addrs()
+            .map_err(|_| UdpTrackerError::Host)?
+            .next()
+            .ok_or(UdpTrackerError::Host)?;
+        let local: SocketAddr = if addr.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local).map_err(|_| UdpTrackerError::Socket)?;
+        Ok(Self {
+            socket,
+            addr,
+            timeout: TIMEOUT,
+            retries: RETRIES,
+        })
+    }
+
+    /// Sets the wait for the first answer, which is doubled after
+    /// every retransmission, and the amount of retransmissions.
+    pub fn set_timeout(&mut self, timeout: Duration, retries: u32) {
+        self.timeout = timeout;
+        self.retries = retries;
+    }
+
+    /// Announces the torrent of the request. An error sent by the
+    /// tracker is returned as a [`TrackerResponseMode::Failure`].
+    pub fn announce(&self, request: &TrackerRequest) -> Result<TrackerResponse, UdpTrackerError> {
+        let connection_id = self.connection_id()?;
+        let transaction_id = rand::random();
+        let packet = request.generate_udp_announce(connection_id, transaction_id);
+        match self.exchange(&packet, ACTION_ANNOUNCE, transaction_id) {
+            Ok(response) => parse_announce(&response, self.addr.is_ipv6()),
+            Err(UdpTrackerError::Tracker(msg)) => {
+                Ok(TrackerResponse(TrackerResponseMode::Failure(msg)))
+            }
+            Err(e) => {
+                forget_connection_id(self.addr);
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the statistics of the torrents, in the same order as
+    /// the info hashes.
+    pub fn scrape(&self, info_hashes: &[[u8; 20]]) -> Result<Vec<ScrapeData>, UdpTrackerError> {
+        let mut stats = Vec::with_capacity(info_hashes.len());
+        for hashes in info_hashes.chunks(MAX_SCRAPE_HASHES) {
+            let connection_id = self.connection_id()?;
+            let transaction_id = rand::random();
+            let mut packet = header(connection_id, ACTION_SCRAPE, transaction_id);
+            hashes.iter().for_each(|h| packet.extend_from_slice(h));
+
+            let response = self
+                .exchange(&packet, ACTION_SCRAPE, transaction_id)
+                .inspect_err(|e| {
+                    if !matches!(e, UdpTrackerError::Tracker(_)) {
+                        forget_connection_id(self.addr);
+                    }
+                })?;
+            let chunk = response[8..]
+                .chunks_exact(12)
+                .map(|s| ScrapeData {
+                    complete: read_u32(s, 0).unwrap_or_default() as i64,
+                    downloaded: read_u32(s, 4).unwrap_or_default() as i64,
+                    incomplete: read_u32(s, 8).unwrap_or_default() as i64,
+                })
+                .collect::<Vec<_>>();
+            if chunk.len() != hashes.len() {
+                return Err(UdpTrackerError::InvalidResponse);
+            }
+            stats.extend(chunk);
+        }
+        Ok(stats)
+    }
+
+    /// Returns the cached connection id of the tracker, or connects
+    /// to get a new one.
+    fn connection_id(&self) -> Result<u64, UdpTrackerError> {
+        if let Some(id) = cached_connection_id(self.addr) {
+            return Ok(id);
+        }
+        let transaction_id = rand::random();
+        let packet = header(PROTOCOL_ID, ACTION_CONNECT, transaction_id);
+        let response = self.exchange(&packet, ACTION_CONNECT, transaction_id)?;
+        let id = response
+            .get(8..16)
+            .and_then(|id| id.try_into().ok())
+            .map(u64::from_be_bytes)
+            .ok_or(UdpTrackerError::InvalidResponse)?;
+        cache_connection_id(self.addr, id);
+        Ok(id)
+    }
+
+    /// Sends the packet until the tracker answers it, waiting
+    /// `timeout * 2^n` for the answer to the nth retransmission.
+    /// Packets from other addresses or for other transactions are
+    /// ignored.
+    fn exchange(
+        &self,
+        packet: &[u8],
+        action: u32,
+        transaction_id: u32,
+    ) -> Result<Vec<u8>, UdpTrackerError> {
+        let mut buf = vec![0; MAX_PACKET_LENGTH];
+        for attempt in 0..=self.retries {
+            self.socket
+                .send_to(packet, self.addr)
+                .map_err(|_| UdpTrackerError::Socket)?;
+            let deadline = Instant::now() + self.timeout * (1 << attempt.min(8));
+
+            while let Some(wait) = deadline
+                .checked_duration_since(Instant::now())
+                .filter(|w| !w.is_zero())
+            {
+                self.socket
+                    .set_read_timeout(Some(wait))
+                    .map_err(|_| UdpTrackerError::Socket)?;
+                let (n, from) = match self.socket.recv_from(&mut buf) {
+                    Ok(r) => r,
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                        break
+                    }
+                    Err(_) => return Err(UdpTrackerError::Socket),
+                };
+                if from != self.addr || n < 8 || read_u32(&buf, 4) != Some(transaction_id) {
+                    continue;
+                }
+                return match read_u32(&buf, 0) {
+                    Some(ACTION_ERROR) => Err(UdpTrackerError::Tracker(
+                        String::from_utf8_lossy(&buf[8..n]).into_owned(),
+                    )),
+                    Some(a) if a == action => Ok(buf[..n].to_vec()),
+                    _ => Err(UdpTrackerError::InvalidResponse),
+                };
+            }
+        }
+        Err(UdpTrackerError::Timeout)
+    }
+}
+
+/// Parses the answer to an announce. The peers are compact, 6 bytes
+/// each, or 18 bytes if the tracker was contacted over IPv6.
+fn parse_announce(response: &[u8], ipv6: bool) -> Result<TrackerResponse, UdpTrackerError> {
+    let field = |offset| read_u32(response, offset).ok_or(UdpTrackerError::InvalidResponse);
+    let interval = field(8)?;
+    let leechers = field(12)?;
+    let seeders = field(16)?;
+
+    let peers = Peer::new_compact_list(&response[20..], ipv6);
+
+    let mut tracker_response = TrackerResponseBuilder::new();
+    tracker_response
+        .interval(interval as i64)
+        .complete(seeders as i64)
+        .incomplete(leechers as i64)
+        .peers(peers);
+    Ok(tracker_response.build())
+}
+
+fn header(connection_id: u64, action: u32, transaction_id: u32) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(16);
+    packet.extend_from_slice(&connection_id.to_be_bytes());
+    packet.extend_from_slice(&action.to_be_bytes());
+    packet.extend_from_slice(&transaction_id.to_be_bytes());
+    packet
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    buf.get(offset..offset + 4)
+        .and_then(|b| b.try_into().ok())
+        .map(u32::from_be_bytes)
+}
+
+fn cached_connection_id(addr: SocketAddr) -> Option<u64> {
+    let mut ids = CONNECTION_IDS.lock().ok()?;
+    ids.retain(|(_, _, received)| received.elapsed() < CONNECTION_ID_LIFETIME);
+    ids.iter()
+        .find(|(a, _, _)| *a == addr)
+        .map(|(_, id, _)| *id)
+}
+
+fn cache_connection_id(addr: SocketAddr, id: u64) {
+    if let Ok(mut ids) = CONNECTION_IDS.lock() {
+        ids.retain(|(a, _, _)| *a != addr);
+        ids.push((addr, id, Instant::now()));
+    }
+}
+
+fn forget_connection_id(addr: SocketAddr) {
+    if let Ok(mut ids) = CONNECTION_IDS.lock() {
+        ids.retain(|(a, _, _)| *a != addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::peer_source::PeerSource;
+    use crate::tracker::response::tracker_response::ResponseData;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::thread::{self, JoinHandle};
+
+    /// Local stand-in of a tracker that reads `packets` packets and
+    /// sends back the answer built by `answer`, if any. Returns the
+    /// packets it received.
+    fn stand_in<F>(packets: usize, mut answer: F) -> (UdpTracker, JoinHandle<Vec<Vec<u8>>>)
+    where
+        F: FnMut(usize, &[u8]) -> Option<Vec<u8>> + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let mut received = Vec::new();
+            let mut buf = [0; 1024];
+            for i in 0..packets {
+                let (n, from) = socket.recv_from(&mut buf).unwrap();
+                if let Some(response) = answer(i, &buf[..n]) {
+                    socket.send_to(&response, from).unwrap();
+                }
+                received.push(buf[..n].to_vec());
+            }
+            received
+        });
+        let mut tracker = UdpTracker::new("127.0.0.1", port).unwrap();
+        tracker.set_timeout(Duration::from_millis(100), 2);
+        (tracker, handle)
+    }
+
+    fn connect_answer(packet: &[u8], connection_id: u64) -> Vec<u8> {
+        assert_eq!(packet[..12], header(PROTOCOL_ID, ACTION_CONNECT, 0)[..12]);
+        let mut response = header(0, ACTION_CONNECT, read_u32(packet, 12).unwrap())[8..].to_vec();
+        response.extend_from_slice(&connection_id.to_be_bytes());
+        response
+    }
+
+    #[test]
+    fn announce_and_reuse_the_connection_id() {
+        let (tracker, handle) = stand_in(3, |i, packet| match i {
+            0 => Some(connect_answer(packet, 0x1234)),
+            _ => {
+                let mut response = header(0, ACTION_ANNOUNCE, read_u32(packet, 12).unwrap());
+                response.drain(..8);
+                for field in [1800u32, 3, 5] {
+                    response.extend_from_slice(&field.to_be_bytes());
+                }
+                response.extend_from_slice(&[1, 2, 3, 4, 0x1a, 0xe1]);
+                Some(response)
+            }
+        });
+        let request = TrackerRequest::new([7; 20], "udp://127.0.0.1".into(), 6881);
+
+        let got = tracker.announce(&request).unwrap();
+        tracker.announce(&request).unwrap();
+
+        let want = TrackerResponse(TrackerResponseMode::Response(ResponseData {
+            interval: 1800,
+            complete: 5,
+            incomplete: 3,
+            peers: vec![Peer {
+                peer_id: None,
+                ip: Some(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))),
+                port: 6881,
+                source: PeerSource::Tracker,
+            }],
+            min_interval: None,
+            warning_message: None,
+            tracker_id: None,
+        }));
+        assert_eq!(got, want);
+
+        let received = handle.join().unwrap();
+        for announce in &received[1..] {
+            assert_eq!(announce.len(), 98);
+            assert_eq!(announce[..8], 0x1234u64.to_be_bytes());
+            assert_eq!(read_u32(announce, 8), Some(ACTION_ANNOUNCE));
+            assert_eq!(announce[16..36], [7; 20]);
+            // Started
+            assert_eq!(read_u32(announce, 80), Some(2));
+        }
+    }
+
+    #[test]
+    fn lost_packets_are_sent_again() {
+        let (tracker, handle) = stand_in(3, |i, packet| match i {
+            0 => None,
+            1 => Some(connect_answer(packet, 99)),
+            _ => {
+                let mut response = header(0, ACTION_SCRAPE, read_u32(packet, 12).unwrap());
+                response.drain(..8);
+                for field in [10u32, 20, 30, 1, 2, 3] {
+                    response.extend_from_slice(&field.to_be_bytes());
+                }
+                Some(response)
+            }
+        });
+
+        let got = tracker.scrape(&[[1; 20], [2; 20]]).unwrap();
+
+        assert_eq!(
+            got,
+            vec![
+                ScrapeData {
+                    complete: 10,
+                    downloaded: 20,
+                    incomplete: 30
+                },
+                ScrapeData {
+                    complete: 1,
+                    downloaded: 2,
+                    incomplete: 3
+                }
+            ]
+        );
+        let received = handle.join().unwrap();
+        assert_eq!(received[0], received[1]);
+        assert_eq!(received[2].len(), 16 + 40);
+    }
+
+    #[test]
+    fn tracker_error_is_a_failure() {
+        let (tracker, handle) = stand_in(2, |i, packet| match i {
+            0 => Some(connect_answer(packet, 1)),
+            _ => {
+                let mut response = header(0, ACTION_ERROR, read_u32(packet, 12).unwrap());
+                response.drain(..8);
+                response.extend_from_slice(b"banned");
+                Some(response)
+            }
+        });
+        let request = TrackerRequest::new([0; 20], "udp://127.0.0.1".into(), 6881);
+
+        assert_eq!(
+            tracker.announce(&request).unwrap(),
+            TrackerResponse(TrackerResponseMode::Failure("banned".into()))
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn no_answer_times_out() {
+        let (mut tracker, handle) = stand_in(2, |_, _| None);
+        tracker.set_timeout(Duration::from_millis(20), 1);
+
+        assert_eq!(tracker.scrape(&[[0; 20]]), Err(UdpTrackerError::Timeout));
+        handle.join().unwrap();
+    }
+}
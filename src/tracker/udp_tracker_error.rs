@@ -0,0 +1,37 @@
+use std::fmt;
+
+/// Represents the possible errors that can occur while talking to a
+/// tracker over UDP.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UdpTrackerError {
+    /// The address of the tracker couldn't be resolved
+    Host,
+    /// The socket couldn't be created or used
+    Socket,
+    /// The tracker didn't answer after every retransmission
+    Timeout,
+    /// The tracker sent a malformed packet
+    InvalidResponse,
+    /// The tracker answered with an error message
+    Tracker(String),
+    /// Only connections through the proxy are allowed, and it can't
+    /// carry UDP
+    ProxyOnly,
+}
+
+impl fmt::Display for UdpTrackerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UdpTrackerError::Host => write!(f, "Couldn't resolve the tracker address"),
+            UdpTrackerError::Socket => write!(f, "An error ocurred using the UDP socket"),
+            UdpTrackerError::Timeout => write!(f, "The tracker didn't answer in time"),
+            UdpTrackerError::InvalidResponse => {
+                write!(f, "The response from the tracker is invalid")
+            }
+            UdpTrackerError::Tracker(msg) => write!(f, "The tracker returned an error: {}", msg),
+            UdpTrackerError::ProxyOnly => {
+                write!(f, "UDP trackers can't be reached through the proxy")
+            }
+        }
+    }
+}
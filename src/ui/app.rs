@@ -2,7 +2,7 @@ use crate::client::client_handler::Client;
 use crate::config;
 use crate::log::logger::Logger;
 
-use super::render::{Card, Render};
+use super::render::{Card, Render, RequestMessage};
 use super::utils;
 use super::views::live_view::LiveView;
 use super::views::main_view::MainView;
@@ -13,7 +13,6 @@ use gtk::{CssProvider, Orientation};
 use std::cell::RefCell;
 use std::fs;
 use std::path::Path;
-use std::process::exit;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use std::thread::JoinHandle;
@@ -33,9 +32,7 @@ pub enum Panes {
 
 pub fn run_app() -> Option<(JoinHandle<()>, JoinHandle<()>)> {
     gtk::init().unwrap();
-    let threads = init_window();
-    gtk::main();
-    threads
+    init_window()
 }
 
 fn init_css(win: &ApplicationWindow) {
@@ -88,7 +85,14 @@ fn init_window() -> Option<(JoinHandle<()>, JoinHandle<()>)> {
             .default_height(70)
             .build();
 
-        win.connect_destroy(|_| exit(0));
+        // The client tells the trackers that it stopped before the
+        // app exits
+        let client_copy = client.clone();
+        win.connect_destroy(move |_| {
+            if let Ok(render) = client_copy.borrow().render.lock() {
+                let _ = render.send_ui(RequestMessage::Terminate);
+            }
+        });
 
         init_css(&win);
 
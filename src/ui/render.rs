@@ -62,6 +62,8 @@ pub enum RawData {
         number_of_pieces: u32,
         number_of_peers: u32,
         remaining_pieces: u32,
+        seeders: Option<u32>,
+        leechers: Option<u32>,
     },
     Torrent {
         name: String,
@@ -204,7 +206,13 @@ impl Render {
                             Err(_) => continue,
                         }
                     }
-                    RequestMessage::Terminate => stop_condition.1 = true,
+                    RequestMessage::Terminate => {
+                        stop_condition.1 = true;
+                        match tx_main_aux.send(RequestMessage::Terminate) {
+                            Ok(_) => (),
+                            Err(_) => continue,
+                        }
+                    }
                 }
             }
 
@@ -252,6 +260,8 @@ fn data_to_maininfo(data: MainViewRawData) -> Result<MainViewInfo, Box<dyn std::
             number_of_pieces: cantidad_de_piezas,
             number_of_peers: cantidad_de_peers,
             remaining_pieces: piezas_faltantes,
+            seeders,
+            leechers,
         } = r
         {
             title.push_str(&nombre);
@@ -263,6 +273,9 @@ fn data_to_maininfo(data: MainViewRawData) -> Result<MainViewInfo, Box<dyn std::
             write!(info, "Tamaño total: {} MB\n\n", tamaño_total / 1000000)?;
             write!(info, "Cantidad de Piezas: {}\n\n", cantidad_de_piezas)?;
             write!(info, "Cantidad de Peers: {}\n\n", cantidad_de_peers)?;
+            let unknown = |n: Option<u32>| n.map_or_else(|| "-".to_string(), |n| n.to_string());
+            write!(info, "Semillas: {}\n\n", unknown(seeders))?;
+            write!(info, "Descargadores: {}\n\n", unknown(leechers))?;
             if piezas_faltantes == 0 {
                 write!(info, "Estado: Almacenado\n\n")?;
             } else if piezas_faltantes == cantidad_de_piezas {
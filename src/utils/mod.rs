@@ -23,11 +23,9 @@ macro_rules! append {
 pub(crate) use append;
 use sha1::{Digest, Sha1};
 
-use crate::torrent::info::{Info, SingleFileData};
-
 pub fn from_u32_be(array: &mut &[u8]) -> Option<u32> {
-    let (int_bytes, rest) = array.split_at(std::mem::size_of::<u32>());
-    *array = rest;
+    let int_bytes = array.get(..std::mem::size_of::<u32>())?;
+    *array = &array[int_bytes.len()..];
     Some(u32::from_be_bytes(int_bytes.try_into().ok()?))
 }
 
@@ -39,12 +37,90 @@ pub fn hash_info(buf: &[u8]) -> [u8; 20] {
     hasher.finalize()[0..20].try_into().unwrap()
 }
 
-pub fn get_info_from_torrentfile(i: Info) -> SingleFileData {
-    let Info(mode) = i;
-    match mode {
-        crate::torrent::info::InfoMode::Empty => todo!(),
-        crate::torrent::info::InfoMode::SingleFile(it) => it,
+/// Encodes the bytes as a lowercase hexadecimal string.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes a hexadecimal string, in lowercase or uppercase. Returns
+/// [`None`] if the length is odd or a character is not a hex digit.
+pub fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 == 1 {
+        return None;
+    }
+    s.as_bytes()
+        .chunks(2)
+        .map(|c| {
+            let digits = std::str::from_utf8(c).ok()?;
+            u8::from_str_radix(digits, 16).ok()
+        })
+        .collect()
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes the bytes with the standard base64 alphabet, with padding.
+pub fn to_base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
     }
+    out
+}
+
+/// Decodes a string encoded with the standard base64 alphabet. The
+/// padding is optional. Returns [`None`] if the string is not valid
+/// base64.
+pub fn from_base64(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim_end_matches('=');
+    if s.len() % 4 == 1 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    for chunk in s.as_bytes().chunks(4) {
+        let mut n = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let v = BASE64_ALPHABET.iter().position(|a| a == c)? as u32;
+            n |= v << (18 - 6 * i);
+        }
+        out.extend_from_slice(&n.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(out)
+}
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Decodes a string encoded with the base32 alphabet of RFC 4648, in
+/// lowercase or uppercase. The padding is optional. Returns [`None`]
+/// if the string is not valid base32.
+pub fn from_base32(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim_end_matches('=');
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in s.bytes() {
+        let v = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | v;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(out)
 }
 
 pub fn round_float(n: f64, p: usize) -> String {
@@ -63,7 +139,44 @@ mod tests {
     #[test]
     fn empty() {
         let got: Vec<u32> = append!();
-        let want = vec![];
+        let want: Vec<u32> = vec![];
         assert_eq!(got, want);
     }
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes = [0x00, 0xab, 0x10, 0xff];
+
+        assert_eq!(super::to_hex(&bytes), "00ab10ff");
+        assert_eq!(super::from_hex("00AB10ff"), Some(bytes.to_vec()));
+        assert_eq!(super::from_hex("0ab"), None);
+        assert_eq!(super::from_hex("zz"), None);
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        for (bytes, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (b"\xff\xfe\x00", "//4A"),
+        ] {
+            assert_eq!(super::to_base64(bytes), encoded);
+            assert_eq!(super::from_base64(encoded), Some(bytes.to_vec()));
+        }
+        assert_eq!(super::from_base64("Z"), None);
+        assert_eq!(super::from_base64("Zm9*"), None);
+    }
+
+    #[test]
+    fn base32_decoding() {
+        assert_eq!(
+            super::from_base32("MZXW6YTBOI======"),
+            Some(b"foobar".to_vec())
+        );
+        assert_eq!(super::from_base32("mzxw6ytb"), Some(b"fooba".to_vec()));
+        assert_eq!(super::from_base32("MZ1"), None);
+    }
 }